@@ -0,0 +1,606 @@
+// Shell arithmetic.
+//
+// Expressions are parsed into a small tree by precedence climbing and then
+// evaluated on 64-bit integers that wrap around on overflow, as in bash.
+// Names stand for shell variables; a variable whose value is itself an
+// expression is evaluated in turn.  The evaluator works on a `Variables`
+// implementation so that it needs no access to the shell itself.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::arrays::split_subscript;
+use crate::parser;
+use crate::{Shell, ShellError};
+
+/// Maximum depth of variables referring to expressions referring to
+/// variables, which stops `a=a` from recursing forever.
+const MAX_DEPTH: usize = 1024;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArithError {
+    pub message: String,
+    /// The rest of the expression from where the error was found.
+    pub token: String,
+}
+
+impl fmt::Display for ArithError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (error token is \"{}\")", self.message, self.token)
+    }
+}
+
+/// Variable storage the evaluator reads and assigns.
+pub trait Variables {
+    fn value(&self, name: &str) -> Option<String>;
+    fn assign(&mut self, name: &str, value: i64);
+}
+
+/// Values of the variables an expression may read, looked up before it is
+/// evaluated, and the names it assigned.
+#[derive(Debug, Default)]
+struct Snapshot {
+    values: HashMap<String, Option<String>>,
+    assigned: Vec<String>,
+}
+
+impl Variables for Snapshot {
+    fn value(&self, name: &str) -> Option<String> {
+        self.values.get(name).cloned().flatten()
+    }
+
+    fn assign(&mut self, name: &str, value: i64) {
+        self.values.insert(name.to_string(), Some(value.to_string()));
+        if !self.assigned.iter().any(|assigned| assigned == name) {
+            self.assigned.push(name.to_string());
+        }
+    }
+}
+
+impl Shell {
+    /// Evaluates an already expanded arithmetic expression, assigning the
+    /// variables it changes.
+    pub(crate) async fn evaluate_arithmetic(&self, expression: &str) -> Result<i64, ShellError> {
+        let error = |e: ArithError| ShellError::Arithmetic(format!("{}: {}", expression.trim(), e));
+        let parsed = Expression::parse(expression).map_err(error)?;
+
+        // Read every variable the expression could reach, following values
+        // that are expressions themselves
+        let mut snapshot = Snapshot::default();
+        let mut pending = parsed.names();
+        while let Some(name) = pending.pop() {
+            if snapshot.values.contains_key(&name) {
+                continue;
+            }
+            let value = match split_subscript(&name) {
+                Some((array, subscript)) => self.element(array, subscript).await?,
+                None => self.parameter_value(&name).await,
+            };
+            if let Some(Ok(inner)) = value.as_deref().filter(|v| v.trim().parse::<i64>().is_err()).map(Expression::parse) {
+                pending.extend(inner.names());
+            }
+            snapshot.values.insert(name, value);
+        }
+
+        let result = parsed.evaluate(&mut snapshot);
+        // Assignments made before an error still take effect
+        for name in &snapshot.assigned {
+            let value = snapshot.value(name).unwrap_or_default();
+            if let Some((array, subscript)) = split_subscript(name) {
+                self.assign_element(array, subscript, value, false).await?;
+            } else {
+                self.assign_scalar(name, value, false).await?;
+            }
+        }
+        result.map_err(error)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BinaryOp {
+    Mul,
+    Div,
+    Rem,
+    Add,
+    Sub,
+    Shl,
+    Shr,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+    BitAnd,
+    BitXor,
+    BitOr,
+    And,
+    Or,
+    Pow,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum UnaryOp {
+    Neg,
+    Plus,
+    Not,
+    BitNot,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    Number(i64),
+    Variable(String),
+    Unary(UnaryOp, Box<Expr>),
+    /// The position of the right operand is kept for error messages.
+    Binary(BinaryOp, Box<Expr>, Box<Expr>, usize),
+    /// `name = value`, or `name op= value`
+    Assign(String, Option<BinaryOp>, Box<Expr>, usize),
+    /// `++name` and `--name`
+    PreIncrement(String, i64),
+    /// `name++` and `name--`
+    PostIncrement(String, i64),
+    Conditional(Box<Expr>, Box<Expr>, Box<Expr>),
+    Comma(Box<Expr>, Box<Expr>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(i64),
+    Name(String),
+    Op(&'static str),
+}
+
+/// Operators, longest first so that the tokenizer takes `<<=` before `<<`.
+const OPERATORS: &[&str] = &[
+    "<<=", ">>=", "**", "++", "--", "<<", ">>", "<=", ">=", "==", "!=", "&&", "||", "*=", "/=", "%=", "+=", "-=", "&=", "^=", "|=",
+    "*", "/", "%", "+", "-", "<", ">", "&", "^", "|", "!", "~", "?", ":", ",", "=", "(", ")",
+];
+
+/// A parsed arithmetic expression.
+#[derive(Debug, Clone)]
+pub struct Expression {
+    source: String,
+    expr: Option<Expr>,
+}
+
+impl Expression {
+    pub fn parse(source: &str) -> Result<Expression, ArithError> {
+        let tokens = tokenize(source)?;
+        let mut parser = Parser { source, tokens, pos: 0 };
+        let expr = if parser.tokens.is_empty() {
+            None
+        } else {
+            let expr = parser.parse_comma()?;
+            if parser.pos < parser.tokens.len() {
+                return Err(parser.error("syntax error in expression"));
+            }
+            Some(expr)
+        };
+        Ok(Expression { source: source.to_string(), expr })
+    }
+
+    /// Every variable the expression mentions.
+    pub fn names(&self) -> Vec<String> {
+        let mut names = Vec::new();
+        if let Some(expr) = &self.expr {
+            collect_names(expr, &mut names);
+        }
+        names
+    }
+
+    /// Evaluates the expression; an empty one is 0.
+    pub fn evaluate(&self, variables: &mut dyn Variables) -> Result<i64, ArithError> {
+        self.evaluate_at(variables, 0)
+    }
+
+    fn evaluate_at(&self, variables: &mut dyn Variables, depth: usize) -> Result<i64, ArithError> {
+        match &self.expr {
+            Some(expr) => Evaluator { source: &self.source, variables, depth }.eval(expr),
+            None => Ok(0),
+        }
+    }
+}
+
+fn collect_names(expr: &Expr, names: &mut Vec<String>) {
+    match expr {
+        Expr::Number(_) => {}
+        Expr::Variable(name) | Expr::PreIncrement(name, _) | Expr::PostIncrement(name, _) => names.push(name.clone()),
+        Expr::Unary(_, operand) => collect_names(operand, names),
+        Expr::Binary(_, left, right, _) | Expr::Comma(left, right) => {
+            collect_names(left, names);
+            collect_names(right, names);
+        }
+        Expr::Assign(name, _, value, _) => {
+            names.push(name.clone());
+            collect_names(value, names);
+        }
+        Expr::Conditional(condition, then, otherwise) => {
+            collect_names(condition, names);
+            collect_names(then, names);
+            collect_names(otherwise, names);
+        }
+    }
+}
+
+/// Splits `source` into tokens, each with its byte offset.
+fn tokenize(source: &str) -> Result<Vec<(Token, usize)>, ArithError> {
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < source.len() {
+        let rest = &source[i..];
+        let c = rest.chars().next().unwrap();
+        if c.is_whitespace() {
+            i += c.len_utf8();
+        } else if c.is_ascii_digit() {
+            let len = rest.find(|c: char| !(c.is_ascii_alphanumeric() || matches!(c, '#' | '@' | '_'))).unwrap_or(rest.len());
+            tokens.push((Token::Number(parse_number(&rest[..len])?), i));
+            i += len;
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let mut len = rest.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_')).unwrap_or(rest.len());
+            // An array element `name[subscript]` is a single name
+            if rest[len..].starts_with('[') {
+                match parser::subscript_end(&rest[len..]) {
+                    Some(close) => len += close + 1,
+                    None => {
+                        return Err(ArithError {
+                            message: "syntax error: `]' expected".to_string(),
+                            token: rest[len..].trim().to_string(),
+                        })
+                    }
+                }
+            }
+            tokens.push((Token::Name(rest[..len].to_string()), i));
+            i += len;
+        } else if let Some(op) = OPERATORS.iter().find(|op| rest.starts_with(**op)) {
+            tokens.push((Token::Op(op), i));
+            i += op.len();
+        } else {
+            return Err(ArithError {
+                message: "syntax error: invalid arithmetic operator".to_string(),
+                token: rest.trim().to_string(),
+            });
+        }
+    }
+    Ok(tokens)
+}
+
+/// Parses `0x1F`, `0755`, `base#digits` and decimal constants.
+fn parse_number(text: &str) -> Result<i64, ArithError> {
+    let invalid = |message: &str| ArithError { message: message.to_string(), token: text.to_string() };
+    let (base, digits) = if let Some((base, digits)) = text.split_once('#') {
+        match base.parse::<u32>() {
+            Ok(base @ 2..=64) => (base, digits),
+            _ => return Err(invalid("invalid arithmetic base")),
+        }
+    } else if let Some(digits) = text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        (16, digits)
+    } else if text.len() > 1 && text.starts_with('0') {
+        (8, &text[1..])
+    } else {
+        (10, text)
+    };
+    // A bare `0x` is 0
+    if digits.is_empty() && base != 16 {
+        return Err(invalid("invalid number"));
+    }
+    let mut value: i64 = 0;
+    for c in digits.chars() {
+        // Letters count from 10; up to base 36 their case does not matter,
+        // above it lower case comes first, then upper case, `@` and `_`
+        let digit = match c {
+            '0'..='9' => c as u32 - '0' as u32,
+            'a'..='z' => c as u32 - 'a' as u32 + 10,
+            'A'..='Z' if base <= 36 => c as u32 - 'A' as u32 + 10,
+            'A'..='Z' => c as u32 - 'A' as u32 + 36,
+            '@' => 62,
+            '_' => 63,
+            _ => return Err(invalid("invalid number")),
+        };
+        if digit >= base {
+            return Err(invalid("value too great for base"));
+        }
+        value = value.wrapping_mul(base as i64).wrapping_add(digit as i64);
+    }
+    Ok(value)
+}
+
+struct Parser<'a> {
+    source: &'a str,
+    tokens: Vec<(Token, usize)>,
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn peek_op(&self) -> Option<&'static str> {
+        match self.tokens.get(self.pos) {
+            Some((Token::Op(op), _)) => Some(op),
+            _ => None,
+        }
+    }
+
+    /// Byte offset of the next token, or the end of the source.
+    fn offset(&self) -> usize {
+        self.tokens.get(self.pos).map_or(self.source.len(), |(_, offset)| *offset)
+    }
+
+    /// An error at the next token; at the end of the expression, bash
+    /// reports the last token instead.
+    fn error(&self, message: &str) -> ArithError {
+        let offset = match self.tokens.get(self.pos).or(self.tokens.last()) {
+            Some((_, offset)) => *offset,
+            None => self.source.len(),
+        };
+        ArithError {
+            message: message.to_string(),
+            token: self.source[offset..].trim().to_string(),
+        }
+    }
+
+    fn expect(&mut self, op: &str) -> Result<(), ArithError> {
+        if self.peek_op() == Some(op) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(self.error(&format!("syntax error: `{}' expected", op)))
+        }
+    }
+
+    fn parse_comma(&mut self) -> Result<Expr, ArithError> {
+        let mut expr = self.parse_assignment()?;
+        while self.peek_op() == Some(",") {
+            self.pos += 1;
+            expr = Expr::Comma(Box::new(expr), Box::new(self.parse_assignment()?));
+        }
+        Ok(expr)
+    }
+
+    fn parse_assignment(&mut self) -> Result<Expr, ArithError> {
+        if let (Some((Token::Name(name), _)), Some((Token::Op(op), _))) = (self.tokens.get(self.pos), self.tokens.get(self.pos + 1)) {
+            let compound = match *op {
+                "=" => Some(None),
+                "*=" => Some(Some(BinaryOp::Mul)),
+                "/=" => Some(Some(BinaryOp::Div)),
+                "%=" => Some(Some(BinaryOp::Rem)),
+                "+=" => Some(Some(BinaryOp::Add)),
+                "-=" => Some(Some(BinaryOp::Sub)),
+                "<<=" => Some(Some(BinaryOp::Shl)),
+                ">>=" => Some(Some(BinaryOp::Shr)),
+                "&=" => Some(Some(BinaryOp::BitAnd)),
+                "^=" => Some(Some(BinaryOp::BitXor)),
+                "|=" => Some(Some(BinaryOp::BitOr)),
+                _ => None,
+            };
+            if let Some(op) = compound {
+                let name = name.clone();
+                self.pos += 2;
+                let offset = self.offset();
+                let value = self.parse_assignment()?;
+                return Ok(Expr::Assign(name, op, Box::new(value), offset));
+            }
+        }
+        self.parse_conditional()
+    }
+
+    fn parse_conditional(&mut self) -> Result<Expr, ArithError> {
+        let condition = self.parse_binary(0)?;
+        if self.peek_op() != Some("?") {
+            return Ok(condition);
+        }
+        self.pos += 1;
+        let then = self.parse_comma()?;
+        self.expect(":")?;
+        let otherwise = self.parse_conditional()?;
+        Ok(Expr::Conditional(Box::new(condition), Box::new(then), Box::new(otherwise)))
+    }
+
+    /// Binary operators from `||` up to `*`, by precedence climbing.
+    fn parse_binary(&mut self, min_level: usize) -> Result<Expr, ArithError> {
+        const LEVELS: &[&[(&str, BinaryOp)]] = &[
+            &[("||", BinaryOp::Or)],
+            &[("&&", BinaryOp::And)],
+            &[("|", BinaryOp::BitOr)],
+            &[("^", BinaryOp::BitXor)],
+            &[("&", BinaryOp::BitAnd)],
+            &[("==", BinaryOp::Eq), ("!=", BinaryOp::Ne)],
+            &[("<", BinaryOp::Lt), ("<=", BinaryOp::Le), (">", BinaryOp::Gt), (">=", BinaryOp::Ge)],
+            &[("<<", BinaryOp::Shl), (">>", BinaryOp::Shr)],
+            &[("+", BinaryOp::Add), ("-", BinaryOp::Sub)],
+            &[("*", BinaryOp::Mul), ("/", BinaryOp::Div), ("%", BinaryOp::Rem)],
+        ];
+        if min_level == LEVELS.len() {
+            return self.parse_power();
+        }
+        let mut left = self.parse_binary(min_level + 1)?;
+        while let Some(&(_, op)) = self.peek_op().and_then(|token| LEVELS[min_level].iter().find(|(op, _)| *op == token)) {
+            self.pos += 1;
+            let offset = self.offset();
+            let right = self.parse_binary(min_level + 1)?;
+            left = Expr::Binary(op, Box::new(left), Box::new(right), offset);
+        }
+        Ok(left)
+    }
+
+    /// `**`, which groups to the right.
+    fn parse_power(&mut self) -> Result<Expr, ArithError> {
+        let base = self.parse_unary()?;
+        if self.peek_op() != Some("**") {
+            return Ok(base);
+        }
+        self.pos += 1;
+        let offset = self.offset();
+        let exponent = self.parse_power()?;
+        Ok(Expr::Binary(BinaryOp::Pow, Box::new(base), Box::new(exponent), offset))
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr, ArithError> {
+        let op = match self.peek_op() {
+            Some("-") => UnaryOp::Neg,
+            Some("+") => UnaryOp::Plus,
+            Some("!") => UnaryOp::Not,
+            Some("~") => UnaryOp::BitNot,
+            Some(op @ ("++" | "--")) => {
+                if let Some((Token::Name(name), _)) = self.tokens.get(self.pos + 1) {
+                    let name = name.clone();
+                    self.pos += 2;
+                    return Ok(Expr::PreIncrement(name, if op == "++" { 1 } else { -1 }));
+                }
+                // Not followed by a name: two signs
+                self.tokens[self.pos].0 = Token::Op(&op[..1]);
+                self.tokens.insert(self.pos + 1, (Token::Op(&op[..1]), self.offset() + 1));
+                return self.parse_unary();
+            }
+            _ => return self.parse_postfix(),
+        };
+        self.pos += 1;
+        Ok(Expr::Unary(op, Box::new(self.parse_unary()?)))
+    }
+
+    fn parse_postfix(&mut self) -> Result<Expr, ArithError> {
+        let primary = self.parse_primary()?;
+        if let Expr::Variable(name) = &primary {
+            if let Some(op @ ("++" | "--")) = self.peek_op() {
+                self.pos += 1;
+                return Ok(Expr::PostIncrement(name.clone(), if op == "++" { 1 } else { -1 }));
+            }
+        }
+        Ok(primary)
+    }
+
+    fn parse_primary(&mut self) -> Result<Expr, ArithError> {
+        let Some((token, _)) = self.tokens.get(self.pos).cloned() else {
+            return Err(self.error("syntax error: operand expected"));
+        };
+        match token {
+            Token::Number(n) => {
+                self.pos += 1;
+                Ok(Expr::Number(n))
+            }
+            Token::Name(name) => {
+                self.pos += 1;
+                Ok(Expr::Variable(name))
+            }
+            Token::Op("(") => {
+                self.pos += 1;
+                let expr = self.parse_comma()?;
+                self.expect(")")?;
+                Ok(expr)
+            }
+            Token::Op(_) => Err(self.error("syntax error: operand expected")),
+        }
+    }
+}
+
+struct Evaluator<'a> {
+    source: &'a str,
+    variables: &'a mut dyn Variables,
+    depth: usize,
+}
+
+impl Evaluator<'_> {
+    fn error(&self, message: &str, offset: usize) -> ArithError {
+        ArithError {
+            message: message.to_string(),
+            token: self.source[offset..].trim().to_string(),
+        }
+    }
+
+    /// The value of a variable: 0 when unset or empty, otherwise its value
+    /// evaluated as an expression.
+    fn variable(&mut self, name: &str) -> Result<i64, ArithError> {
+        let value = self.variables.value(name).unwrap_or_default();
+        if value.trim().is_empty() {
+            return Ok(0);
+        }
+        if let Ok(n) = value.trim().parse::<i64>() {
+            return Ok(n);
+        }
+        if self.depth >= MAX_DEPTH {
+            return Err(ArithError {
+                message: "expression recursion level exceeded".to_string(),
+                token: value,
+            });
+        }
+        Expression::parse(&value)?.evaluate_at(self.variables, self.depth + 1)
+    }
+
+    fn eval(&mut self, expr: &Expr) -> Result<i64, ArithError> {
+        match expr {
+            Expr::Number(n) => Ok(*n),
+            Expr::Variable(name) => self.variable(name),
+            Expr::Unary(op, operand) => {
+                let value = self.eval(operand)?;
+                Ok(match op {
+                    UnaryOp::Neg => value.wrapping_neg(),
+                    UnaryOp::Plus => value,
+                    UnaryOp::Not => (value == 0) as i64,
+                    UnaryOp::BitNot => !value,
+                })
+            }
+            // `&&` and `||` only evaluate their right side when needed
+            Expr::Binary(BinaryOp::And, left, right, _) => Ok((self.eval(left)? != 0 && self.eval(right)? != 0) as i64),
+            Expr::Binary(BinaryOp::Or, left, right, _) => Ok((self.eval(left)? != 0 || self.eval(right)? != 0) as i64),
+            Expr::Binary(op, left, right, offset) => {
+                let left = self.eval(left)?;
+                let right = self.eval(right)?;
+                self.apply(*op, left, right, *offset)
+            }
+            Expr::Assign(name, op, value, offset) => {
+                let mut value = self.eval(value)?;
+                if let Some(op) = op {
+                    let current = self.variable(name)?;
+                    value = self.apply(*op, current, value, *offset)?;
+                }
+                self.variables.assign(name, value);
+                Ok(value)
+            }
+            Expr::PreIncrement(name, delta) => {
+                let value = self.variable(name)?.wrapping_add(*delta);
+                self.variables.assign(name, value);
+                Ok(value)
+            }
+            Expr::PostIncrement(name, delta) => {
+                let value = self.variable(name)?;
+                self.variables.assign(name, value.wrapping_add(*delta));
+                Ok(value)
+            }
+            Expr::Conditional(condition, then, otherwise) => {
+                if self.eval(condition)? != 0 {
+                    self.eval(then)
+                } else {
+                    self.eval(otherwise)
+                }
+            }
+            Expr::Comma(left, right) => {
+                self.eval(left)?;
+                self.eval(right)
+            }
+        }
+    }
+
+    fn apply(&self, op: BinaryOp, left: i64, right: i64, offset: usize) -> Result<i64, ArithError> {
+        Ok(match op {
+            BinaryOp::Mul => left.wrapping_mul(right),
+            BinaryOp::Div | BinaryOp::Rem if right == 0 => return Err(self.error("division by 0", offset)),
+            BinaryOp::Div => left.wrapping_div(right),
+            BinaryOp::Rem => left.wrapping_rem(right),
+            BinaryOp::Add => left.wrapping_add(right),
+            BinaryOp::Sub => left.wrapping_sub(right),
+            BinaryOp::Shl => left.wrapping_shl(right as u32),
+            BinaryOp::Shr => left.wrapping_shr(right as u32),
+            BinaryOp::Lt => (left < right) as i64,
+            BinaryOp::Le => (left <= right) as i64,
+            BinaryOp::Gt => (left > right) as i64,
+            BinaryOp::Ge => (left >= right) as i64,
+            BinaryOp::Eq => (left == right) as i64,
+            BinaryOp::Ne => (left != right) as i64,
+            BinaryOp::BitAnd => left & right,
+            BinaryOp::BitXor => left ^ right,
+            BinaryOp::BitOr => left | right,
+            BinaryOp::And => (left != 0 && right != 0) as i64,
+            BinaryOp::Or => (left != 0 || right != 0) as i64,
+            BinaryOp::Pow if right < 0 => return Err(self.error("exponent less than 0", offset)),
+            BinaryOp::Pow => left.wrapping_pow(right.min(u32::MAX as i64) as u32),
+        })
+    }
+}
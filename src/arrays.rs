@@ -0,0 +1,391 @@
+// Indexed and associative arrays.
+//
+// Arrays are kept in `Shell::arrays`, apart from scalar variables, and a
+// name is in at most one of the two maps.  Indexed arrays are sparse: only
+// the elements that were assigned exist.  The subscript of an indexed array
+// is an arithmetic expression, and a negative one counts back from the end;
+// that of an associative array is a string.  As in bash, `$arr` is element
+// 0 and assigning to `arr` without a subscript sets element 0.
+
+use std::collections::BTreeMap;
+
+use crate::ast::{Assignment, Word};
+use crate::parser;
+use crate::{Shell, ShellError};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Array {
+    Indexed(BTreeMap<usize, String>),
+    Associative(BTreeMap<String, String>),
+}
+
+/// A resolved subscript.
+#[derive(Debug, Clone, PartialEq)]
+enum Index {
+    Position(usize),
+    Key(String),
+}
+
+impl Array {
+    /// Element values, in index or key order.
+    pub fn values(&self) -> Vec<String> {
+        match self {
+            Array::Indexed(elements) => elements.values().cloned().collect(),
+            Array::Associative(elements) => elements.values().cloned().collect(),
+        }
+    }
+
+    /// Indices or keys of the elements that are set.
+    pub fn keys(&self) -> Vec<String> {
+        match self {
+            Array::Indexed(elements) => elements.keys().map(|index| index.to_string()).collect(),
+            Array::Associative(elements) => elements.keys().cloned().collect(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            Array::Indexed(elements) => elements.len(),
+            Array::Associative(elements) => elements.len(),
+        }
+    }
+
+    /// `${arr[@]:offset:length}`: for an indexed array the offset is an
+    /// index, and a negative one counts back from one past the last index.
+    pub fn slice(&self, offset: i64, length: Option<i64>) -> Result<Vec<String>, String> {
+        let (skip, available) = match self {
+            Array::Indexed(elements) => {
+                let end = elements.keys().next_back().map_or(0, |last| *last as i64 + 1);
+                let start = if offset < 0 { offset + end } else { offset };
+                if start < 0 {
+                    return Ok(Vec::new());
+                }
+                let skip = elements.keys().take_while(|&&index| (index as i64) < start).count();
+                (skip, elements.len() - skip)
+            }
+            Array::Associative(elements) => {
+                let len = elements.len() as i64;
+                let start = if offset < 0 { offset + len } else { offset };
+                if start < 0 {
+                    return Ok(Vec::new());
+                }
+                let skip = start.min(len) as usize;
+                (skip, elements.len() - skip)
+            }
+        };
+        let take = match length {
+            None => available,
+            Some(length) if length < 0 => return Err(format!("{}: substring expression < 0", length)),
+            Some(length) => (length as usize).min(available),
+        };
+        Ok(self.values().into_iter().skip(skip).take(take).collect())
+    }
+
+    fn get(&self, index: &Index) -> Option<&String> {
+        match (self, index) {
+            (Array::Indexed(elements), Index::Position(n)) => elements.get(n),
+            (Array::Associative(elements), Index::Key(key)) => elements.get(key),
+            _ => None,
+        }
+    }
+
+    fn set(&mut self, index: Index, value: String) {
+        match (self, index) {
+            (Array::Indexed(elements), Index::Position(n)) => {
+                elements.insert(n, value);
+            }
+            (Array::Associative(elements), Index::Key(key)) => {
+                elements.insert(key, value);
+            }
+            _ => {}
+        }
+    }
+
+    fn remove(&mut self, index: &Index) {
+        match (self, index) {
+            (Array::Indexed(elements), Index::Position(n)) => {
+                elements.remove(n);
+            }
+            (Array::Associative(elements), Index::Key(key)) => {
+                elements.remove(key);
+            }
+            _ => {}
+        }
+    }
+
+    /// Element 0, which stands for the whole array in scalar contexts.
+    fn first(&self) -> Option<&String> {
+        match self {
+            Array::Indexed(elements) => elements.get(&0),
+            Array::Associative(elements) => elements.get("0"),
+        }
+    }
+}
+
+/// Splits `name[subscript]` into its name and subscript.
+pub fn split_subscript(name: &str) -> Option<(&str, &str)> {
+    let open = name.find('[')?;
+    let subscript = name[open + 1..].strip_suffix(']')?;
+    parser::is_valid_name(&name[..open]).then_some((&name[..open], subscript))
+}
+
+/// Whether `subscript` is `@` or `*`, which stand for every element.
+pub fn is_whole_array(subscript: &str) -> Option<bool> {
+    match subscript {
+        "@" => Some(true),
+        "*" => Some(false),
+        _ => None,
+    }
+}
+
+/// Takes the array assignments out of the operands of `declare` or
+/// `local`, leaving their names in place.  The builtin declares the names,
+/// and the assignments are then made like any other.
+pub fn split_declaration(words: &[Word]) -> (Vec<Word>, Vec<Assignment>) {
+    let mut operands = Vec::with_capacity(words.len());
+    let mut assignments = Vec::new();
+    for word in words {
+        match parser::split_assignment(&word.raw) {
+            Some(assignment) if assignment.array.is_some() => {
+                operands.push(Word::new(assignment.name.clone()));
+                assignments.push(assignment);
+            }
+            _ => operands.push(word.clone()),
+        }
+    }
+    (operands, assignments)
+}
+
+fn bad_subscript(name: &str, subscript: &str) -> ShellError {
+    ShellError::Arithmetic(format!("{}[{}]: bad array subscript", name, subscript))
+}
+
+impl Shell {
+    /// Resolves an already expanded subscript of array `name`.
+    async fn index(&self, name: &str, subscript: &str) -> Result<Index, ShellError> {
+        let last = match self.arrays.read().await.get(name) {
+            Some(Array::Associative(_)) if subscript.is_empty() => return Err(bad_subscript(name, subscript)),
+            Some(Array::Associative(_)) => return Ok(Index::Key(subscript.to_string())),
+            Some(Array::Indexed(elements)) => elements.keys().next_back().map(|last| *last as i64),
+            None => None,
+        };
+        let n = Box::pin(self.evaluate_arithmetic(subscript)).await?;
+        let n = if n < 0 { n + last.map_or(0, |last| last + 1) } else { n };
+        if n < 0 {
+            return Err(bad_subscript(name, subscript));
+        }
+        Ok(Index::Position(n as usize))
+    }
+
+    /// The value of `name[subscript]`.  A scalar variable is an array with
+    /// only element 0.
+    pub(crate) async fn element(&self, name: &str, subscript: &str) -> Result<Option<String>, ShellError> {
+        let name = &self.resolve_nameref(name).await;
+        let index = self.index(name, subscript).await?;
+        if let Some(array) = self.arrays.read().await.get(name) {
+            return Ok(array.get(&index).cloned());
+        }
+        match index {
+            Index::Position(0) => Ok(self.parameter_value(name).await),
+            _ => Ok(None),
+        }
+    }
+
+    /// Every element of `name`: those of an array, the value of a scalar.
+    pub(crate) async fn elements(&self, name: &str) -> Vec<String> {
+        let name = &self.resolve_nameref(name).await;
+        if let Some(array) = self.arrays.read().await.get(name) {
+            return array.values();
+        }
+        self.parameter_value(name).await.into_iter().collect()
+    }
+
+    /// The indices or keys of `name`, for `${!arr[@]}`.
+    pub(crate) async fn element_keys(&self, name: &str) -> Vec<String> {
+        let name = &self.resolve_nameref(name).await;
+        if let Some(array) = self.arrays.read().await.get(name) {
+            return array.keys();
+        }
+        match self.parameter_value(name).await {
+            Some(_) => vec!["0".to_string()],
+            None => Vec::new(),
+        }
+    }
+
+    /// `${arr[@]:offset:length}` on a scalar or array.
+    pub(crate) async fn slice_elements(&self, name: &str, offset: i64, length: Option<i64>) -> Result<Vec<String>, ShellError> {
+        let name = &self.resolve_nameref(name).await;
+        let array = match self.arrays.read().await.get(name) {
+            Some(array) => array.clone(),
+            None => Array::Indexed(self.parameter_value(name).await.map(|value| (0, value)).into_iter().collect()),
+        };
+        array.slice(offset, length).map_err(ShellError::Expansion)
+    }
+
+    /// Value of an array as a scalar: its element 0.
+    pub(crate) async fn array_scalar(&self, name: &str) -> Option<String> {
+        self.arrays.read().await.get(name).and_then(|array| array.first().cloned())
+    }
+
+    /// Stores a scalar value in `name`, which sets element 0 of an array.
+    /// Attributes are up to the caller; see `assign_scalar`.
+    pub(crate) async fn set_scalar(&self, name: &str, value: String) {
+        let mut arrays = self.arrays.write().await;
+        match arrays.get_mut(name) {
+            Some(Array::Indexed(elements)) => {
+                elements.insert(0, value);
+            }
+            Some(Array::Associative(elements)) => {
+                elements.insert("0".to_string(), value);
+            }
+            None => {
+                self.variables.write().await.insert(name.to_string(), value);
+            }
+        }
+    }
+
+    /// Assigns `name[subscript]=value`, or appends to the element.  A scalar
+    /// variable becomes an indexed array keeping its value as element 0.
+    pub(crate) async fn assign_element(&self, name: &str, subscript: &str, value: String, append: bool) -> Result<(), ShellError> {
+        let name = &self.resolve_nameref(name).await;
+        self.check_writable(name).await?;
+        let index = self.index(name, subscript).await?;
+        let old = self.arrays.read().await.get(name).and_then(|array| array.get(&index).cloned());
+        let value = self.converted_value(name, old, value, append).await?;
+        let mut arrays = self.arrays.write().await;
+        let array = match arrays.get_mut(name) {
+            Some(array) => array,
+            None => {
+                let scalar = self.variables.write().await.remove(name);
+                let elements = scalar.map(|value| (0, value)).into_iter().collect();
+                arrays.entry(name.to_string()).or_insert(Array::Indexed(elements))
+            }
+        };
+        array.set(index, value);
+        drop(arrays);
+        self.export_if_allexport(name).await;
+        Ok(())
+    }
+
+    /// `unset 'name[subscript]'`.
+    pub(crate) async fn unset_element(&self, name: &str, subscript: &str) -> Result<(), ShellError> {
+        let name = &self.resolve_nameref(name).await;
+        if is_whole_array(subscript).is_some() {
+            self.arrays.write().await.remove(name);
+            return Ok(());
+        }
+        let index = self.index(name, subscript).await?;
+        if let Some(array) = self.arrays.write().await.get_mut(name) {
+            array.remove(&index);
+        } else if index == Index::Position(0) {
+            self.variables.write().await.remove(name);
+        }
+        Ok(())
+    }
+
+    /// Makes `name` an empty array of the given kind unless it already is
+    /// one, for `declare -a` and `declare -A`.
+    pub(crate) async fn declare_array(&self, name: &str, associative: bool) -> Result<(), String> {
+        let mut arrays = self.arrays.write().await;
+        match arrays.get(name) {
+            Some(Array::Indexed(_)) if associative => Err(format!("{}: cannot convert indexed to associative array", name)),
+            Some(Array::Associative(_)) if !associative => Err(format!("{}: cannot convert associative to indexed array", name)),
+            Some(_) => Ok(()),
+            None => {
+                let scalar = self.variables.write().await.remove(name);
+                let array = if associative {
+                    Array::Associative(scalar.map(|value| ("0".to_string(), value)).into_iter().collect())
+                } else {
+                    Array::Indexed(scalar.map(|value| (0, value)).into_iter().collect())
+                };
+                arrays.insert(name.to_string(), array);
+                Ok(())
+            }
+        }
+    }
+
+    /// `name=(...)` and `name+=(...)`.  Elements written `[subscript]=value`
+    /// go to the given subscript; the others follow the previous element of
+    /// an indexed array, or pair up as key and value in an associative one.
+    pub(crate) async fn assign_array(&mut self, name: &str, words: &[Word], append: bool) -> Result<(), ShellError> {
+        let name = &self.resolve_nameref(name).await;
+        self.check_writable(name).await?;
+        let mut elements = Vec::new();
+        for word in words {
+            match split_element(&word.raw) {
+                Some((subscript, value)) => {
+                    let subscript = self.expand_fragment(subscript, false).await?;
+                    let value = self.expand_word(&Word::new(value)).await?;
+                    elements.push((Some(subscript), self.converted_value(name, None, value, false).await?));
+                }
+                None => {
+                    for value in self.expand_words(std::slice::from_ref(word)).await? {
+                        elements.push((None, self.converted_value(name, None, value, false).await?));
+                    }
+                }
+            }
+        }
+
+        let associative = matches!(self.arrays.read().await.get(name), Some(Array::Associative(_)));
+        let mut array = match self.arrays.write().await.remove(name) {
+            Some(array) if append => array,
+            _ if associative => Array::Associative(BTreeMap::new()),
+            _ => {
+                let scalar = self.variables.write().await.remove(name).filter(|_| append);
+                Array::Indexed(scalar.map(|value| (0, value)).into_iter().collect())
+            }
+        };
+        let mut next = match &array {
+            Array::Indexed(elements) => elements.keys().next_back().map_or(0, |last| last + 1),
+            Array::Associative(_) => 0,
+        };
+        let mut pending_key = None;
+        let mut result = Ok(());
+        for (subscript, value) in elements {
+            match (&mut array, subscript) {
+                (Array::Indexed(elements), Some(subscript)) => {
+                    let last = elements.keys().next_back().map(|last| *last as i64);
+                    match Box::pin(self.evaluate_arithmetic(&subscript)).await {
+                        Ok(n) => {
+                            let n = if n < 0 { n + last.map_or(0, |last| last + 1) } else { n };
+                            if n < 0 {
+                                result = Err(bad_subscript(name, &subscript));
+                                continue;
+                            }
+                            elements.insert(n as usize, value);
+                            next = n as usize + 1;
+                        }
+                        Err(e) => result = Err(e),
+                    }
+                }
+                (Array::Indexed(elements), None) => {
+                    elements.insert(next, value);
+                    next += 1;
+                }
+                (Array::Associative(elements), Some(key)) => {
+                    elements.insert(key, value);
+                }
+                (Array::Associative(elements), None) => match pending_key.take() {
+                    Some(key) => {
+                        elements.insert(key, value);
+                    }
+                    None => pending_key = Some(value),
+                },
+            }
+        }
+        // A key without a value gets an empty one
+        if let (Array::Associative(elements), Some(key)) = (&mut array, pending_key) {
+            elements.insert(key, String::new());
+        }
+        self.arrays.write().await.insert(name.to_string(), array);
+        self.export_if_allexport(name).await;
+        result
+    }
+}
+
+/// Splits an element `[subscript]=value` of a compound assignment.
+fn split_element(raw: &str) -> Option<(&str, &str)> {
+    let rest = raw.strip_prefix('[')?;
+    let close = rest.find("]=")?;
+    Some((&rest[..close], &rest[close + 2..]))
+}
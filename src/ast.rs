@@ -0,0 +1,205 @@
+// Abstract syntax tree produced by the parser and walked by the executor.
+
+use std::sync::Arc;
+
+/// A word as written in the source, quotes and expansions included.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Word {
+    pub raw: String,
+}
+
+impl Word {
+    pub fn new(raw: impl Into<String>) -> Self {
+        Word { raw: raw.into() }
+    }
+}
+
+impl std::fmt::Display for Word {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.raw)
+    }
+}
+
+/// A sequence of and-or lists separated by `;`, `&` or newlines.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct List {
+    pub items: Vec<ListItem>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ListItem {
+    pub and_or: AndOr,
+    pub background: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Connector {
+    And,
+    Or,
+}
+
+/// Pipelines joined by `&&` and `||`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AndOr {
+    pub first: Pipeline,
+    pub rest: Vec<(Connector, Pipeline)>,
+    /// Source text of the whole list, used for job listings.
+    pub text: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pipeline {
+    pub negated: bool,
+    pub commands: Vec<Command>,
+    /// Source text, used to describe the pipeline as a job.
+    pub text: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Simple(SimpleCommand),
+    Compound(CompoundCommand, Vec<Redirect>),
+    FunctionDef(Arc<FunctionDef>),
+}
+
+/// `name() compound-command` or `function name compound-command`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FunctionDef {
+    pub name: String,
+    /// A compound command, with any redirections that apply on every call.
+    pub body: Command,
+    /// Source text of the body, shown by `declare -f` and `type`.
+    pub body_text: String,
+    /// Line of the script the body's line numbers count from, known once
+    /// the definition runs.
+    pub line_offset: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Assignment {
+    pub name: String,
+    /// `name[subscript]=value` assigns one element of an array.
+    pub subscript: Option<String>,
+    pub append: bool,
+    pub value: Word,
+    /// The elements of `name=(...)`, whose value is then empty.
+    pub array: Option<Vec<Word>>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SimpleCommand {
+    pub assignments: Vec<Assignment>,
+    pub words: Vec<Word>,
+    pub redirects: Vec<Redirect>,
+    pub line: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CompoundCommand {
+    /// `{ list; }`
+    BraceGroup(List),
+    /// `( list )`, with its source text for the job table
+    Subshell {
+        body: List,
+        text: String,
+    },
+    If {
+        branches: Vec<(List, List)>,
+        else_branch: Option<List>,
+    },
+    For {
+        var: String,
+        words: Option<Vec<Word>>,
+        body: List,
+    },
+    While {
+        condition: List,
+        body: List,
+    },
+    Until {
+        condition: List,
+        body: List,
+    },
+    Case {
+        word: Word,
+        items: Vec<CaseItem>,
+    },
+    Conditional(Vec<Word>),
+    /// `(( expression ))`, holding the expression
+    Arithmetic(Word),
+}
+
+/// One `pattern | pattern) list ;;` arm of a `case` command.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CaseItem {
+    pub patterns: Vec<Word>,
+    pub body: List,
+    pub terminator: CaseTerminator,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CaseTerminator {
+    /// `;;`: stop after this arm
+    Break,
+    /// `;&`: run the next arm's body without testing its patterns
+    FallThrough,
+    /// `;;&`: go on testing the following patterns
+    Continue,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RedirectKind {
+    /// `<`
+    Input,
+    /// `>`
+    Output,
+    /// `>>`
+    Append,
+    /// `>|`
+    Clobber,
+    /// `<>`
+    ReadWrite,
+    /// `<&`
+    DupInput,
+    /// `>&`
+    DupOutput,
+    /// `&>`
+    OutputAll,
+    /// `&>>`
+    AppendAll,
+    /// `<<` and `<<-`; the body is in `Redirect::here_doc`
+    HereDoc,
+    /// `<<<`
+    HereString,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct HereDoc {
+    /// Body text with `<<-` tab stripping already applied.
+    pub body: String,
+    /// False when the delimiter was quoted and the body is taken literally.
+    pub expand: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Redirect {
+    pub fd: Option<i32>,
+    pub kind: RedirectKind,
+    /// The file, descriptor or string operand; the delimiter for here-documents.
+    pub target: Word,
+    pub here_doc: Option<HereDoc>,
+}
+
+impl Redirect {
+    /// The descriptor the redirection applies to when none is written explicitly.
+    pub fn target_fd(&self) -> i32 {
+        self.fd.unwrap_or(match self.kind {
+            RedirectKind::Input
+            | RedirectKind::ReadWrite
+            | RedirectKind::DupInput
+            | RedirectKind::HereDoc
+            | RedirectKind::HereString => 0,
+            _ => 1,
+        })
+    }
+}
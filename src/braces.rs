@@ -0,0 +1,170 @@
+// Brace expansion.
+//
+// Runs on the raw text of a word before any other expansion, as in bash:
+// `a{b,c}d` becomes the words `abd` and `acd`, and `{1..3}` counts.  Quoted
+// braces and the insides of quotes, `${...}` and substitutions are skipped,
+// and a brace pair without a top-level comma or a valid sequence expression
+// is left as it is.
+
+use crate::lexer;
+
+/// Expands every brace expression in `word`, returning the words in order.
+pub fn expand(word: &str) -> Vec<String> {
+    let Some((open, close, alternatives)) = find_brace(word) else {
+        return vec![word.to_string()];
+    };
+    // Nothing before `open` can expand any more, so only the alternative and
+    // what follows it are expanded again
+    let (preamble, postscript) = (&word[..open], &word[close + 1..]);
+    let mut words = Vec::new();
+    for alternative in alternatives {
+        for rest in expand(&format!("{}{}", alternative, postscript)) {
+            words.push(format!("{}{}", preamble, rest));
+        }
+    }
+    words
+}
+
+/// Finds the first brace expression that expands.  Returns the positions of
+/// its braces and what it expands to.
+fn find_brace(word: &str) -> Option<(usize, usize, Vec<String>)> {
+    let mut i = 0;
+    while i < word.len() {
+        if word.as_bytes()[i] == b'{' {
+            if let Some((close, alternatives)) = parse_brace(word, i) {
+                return Some((i, close, alternatives));
+            }
+            i += 1;
+        } else {
+            i = skip(word, i);
+        }
+    }
+    None
+}
+
+/// Steps over the character at `i`, or over the whole quoted string or
+/// expansion that starts there.
+fn skip(word: &str, i: usize) -> usize {
+    let end = match word.as_bytes()[i] {
+        b'\\' => Ok(i + 1 + word[i + 1..].chars().next().map_or(0, char::len_utf8)),
+        b'\'' => lexer::scan_single_quote(word, i),
+        b'"' => lexer::scan_double_quote(word, i),
+        b'`' => lexer::scan_backquote(word, i),
+        b'$' => lexer::scan_dollar(word, i),
+        _ => Ok(i + word[i..].chars().next().map_or(1, char::len_utf8)),
+    };
+    end.unwrap_or(word.len()).min(word.len())
+}
+
+/// Parses the brace expression opening at `open`: a comma-separated list
+/// or a sequence.  Returns the position of the closing brace and the
+/// alternatives, or `None` when the braces are to be taken literally.
+fn parse_brace(word: &str, open: usize) -> Option<(usize, Vec<String>)> {
+    let mut depth = 0;
+    let mut commas = Vec::new();
+    let mut i = open;
+    while i < word.len() {
+        match word.as_bytes()[i] {
+            b'{' => depth += 1,
+            b'}' => {
+                depth -= 1;
+                if depth == 0 {
+                    break;
+                }
+            }
+            b',' if depth == 1 => commas.push(i),
+            _ => {
+                i = skip(word, i);
+                continue;
+            }
+        }
+        i += 1;
+    }
+    if i >= word.len() {
+        return None;
+    }
+    let close = i;
+    if commas.is_empty() {
+        return sequence(&word[open + 1..close]).map(|words| (close, words));
+    }
+    let mut alternatives = Vec::new();
+    let mut start = open + 1;
+    for comma in commas.into_iter().chain([close]) {
+        alternatives.push(word[start..comma].to_string());
+        start = comma + 1;
+    }
+    Some((close, alternatives))
+}
+
+/// Expands `x..y` or `x..y..step`, where `x` and `y` are both integers or
+/// both letters.  Integers written with leading zeros are padded to the
+/// same width.
+fn sequence(text: &str) -> Option<Vec<String>> {
+    let parts: Vec<&str> = text.split("..").collect();
+    let step = match parts.len() {
+        2 => 1,
+        3 => parts[2].parse::<i64>().ok()?.unsigned_abs().max(1),
+        _ => return None,
+    };
+
+    if let (Some(start), Some(end)) = (parse_integer(parts[0]), parse_integer(parts[1])) {
+        let width = if is_padded(parts[0]) || is_padded(parts[1]) {
+            parts[0].len().max(parts[1].len())
+        } else {
+            0
+        };
+        return Some(count(start, end, step).map(|n| format!("{:0width$}", n, width = width)).collect());
+    }
+
+    let (start, end) = (letter(parts[0])?, letter(parts[1])?);
+    Some(
+        count(start as i64, end as i64, step)
+            .map(|n| {
+                let c = char::from(n as u8);
+                // Characters between `Z` and `a` must not be taken for quotes
+                // or patterns later on
+                if c.is_ascii_alphanumeric() {
+                    c.to_string()
+                } else {
+                    format!("\\{}", c)
+                }
+            })
+            .collect(),
+    )
+}
+
+fn parse_integer(s: &str) -> Option<i64> {
+    let digits = s.strip_prefix(['-', '+']).unwrap_or(s);
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    s.parse().ok()
+}
+
+fn is_padded(s: &str) -> bool {
+    let digits = s.strip_prefix(['-', '+']).unwrap_or(s);
+    digits.len() > 1 && digits.starts_with('0')
+}
+
+fn letter(s: &str) -> Option<u8> {
+    match s.as_bytes() {
+        [c] if c.is_ascii_alphabetic() => Some(*c),
+        _ => None,
+    }
+}
+
+/// `start`, `start ± step`, ... up to `end`, counting down when `end` is
+/// the smaller.
+fn count(start: i64, end: i64, step: u64) -> impl Iterator<Item = i64> {
+    let step = step as i64;
+    let mut next = Some(start);
+    std::iter::from_fn(move || {
+        let current = next?;
+        next = if start <= end {
+            current.checked_add(step).filter(|&n| n <= end)
+        } else {
+            current.checked_sub(step).filter(|&n| n >= end)
+        };
+        Some(current)
+    })
+}
@@ -0,0 +1,63 @@
+// Variables whose value the shell works out each time they are read.
+//
+// `RANDOM` and `SECONDS` can also be assigned: a number assigned to
+// `RANDOM` seeds the generator, one assigned to `SECONDS` is where the count
+// restarts from.
+
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::Shell;
+
+#[derive(Debug)]
+pub struct DynamicVariables {
+    random: StdRng,
+    /// When `SECONDS` was last assigned, and the value it was given.
+    seconds: (Instant, i64),
+}
+
+impl Default for DynamicVariables {
+    fn default() -> Self {
+        DynamicVariables {
+            random: StdRng::from_entropy(),
+            seconds: (Instant::now(), 0),
+        }
+    }
+}
+
+impl Shell {
+    /// The value of a dynamic variable; `None` when `name` is not one.
+    pub(crate) async fn dynamic_variable(&self, name: &str) -> Option<String> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        match name {
+            "RANDOM" => Some(self.dynamic.write().await.random.gen_range(0..32768).to_string()),
+            "SECONDS" => {
+                let (since, start) = self.dynamic.read().await.seconds;
+                Some((start + since.elapsed().as_secs() as i64).to_string())
+            }
+            "EPOCHSECONDS" => Some(now.as_secs().to_string()),
+            "EPOCHREALTIME" => Some(format!("{}.{:06}", now.as_secs(), now.subsec_micros())),
+            "LINENO" => Some(self.line_number().await.to_string()),
+            "BASHPID" => Some(nix::unistd::getpid().to_string()),
+            _ => None,
+        }
+    }
+
+    /// Gives an assignment to `RANDOM` or `SECONDS` its special meaning.
+    pub(crate) async fn assign_dynamic(&self, name: &str, value: &str) {
+        let number = value.trim().parse::<i64>().unwrap_or(0);
+        match name {
+            "RANDOM" => self.dynamic.write().await.random = StdRng::seed_from_u64(number as u64),
+            "SECONDS" => self.dynamic.write().await.seconds = (Instant::now(), number),
+            _ => {}
+        }
+    }
+
+    /// `LINENO`: the line of the running command in its script.
+    pub(crate) async fn line_number(&self) -> usize {
+        let (offset, line) = *self.line.read().await;
+        offset + line
+    }
+}
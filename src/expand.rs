@@ -0,0 +1,1159 @@
+// Word expansion.
+//
+// A raw word is expanded in a single left-to-right pass that performs tilde,
+// parameter, command and arithmetic expansion together with quote removal.
+// Every byte of the result remembers where it came from so that field
+// splitting only breaks up the results of unquoted expansions.
+
+use crate::arrays;
+use crate::ast::Word;
+use crate::braces;
+use crate::lexer;
+use crate::parser;
+use crate::pathname;
+use crate::pattern::Pattern;
+use crate::{Shell, ShellError};
+
+/// Where a byte of an expanded word came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Origin {
+    /// Unquoted text written in the word itself.
+    Literal,
+    /// Quoted or escaped text, including expansions inside double quotes.
+    Quoted,
+    /// The result of an unquoted expansion; subject to field splitting.
+    Expanded,
+    /// The space between two positional parameters of `"$@"`, or of an
+    /// unquoted `$@` or `$*`, which always ends a field.
+    Separator,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Field {
+    pub text: String,
+    pub origins: Vec<Origin>,
+    /// Byte offsets at which quotes appeared, so `""` still yields a field.
+    pub quotes: Vec<usize>,
+}
+
+impl Field {
+    fn push_str(&mut self, s: &str, origin: Origin) {
+        self.text.push_str(s);
+        self.origins.extend(std::iter::repeat_n(origin, s.len()));
+    }
+
+    fn push(&mut self, c: char, origin: Origin) {
+        let mut buf = [0u8; 4];
+        self.push_str(c.encode_utf8(&mut buf), origin);
+    }
+
+    fn mark_quoted(&mut self) {
+        self.quotes.push(self.text.len());
+    }
+
+    fn is_quoted(&self) -> bool {
+        !self.quotes.is_empty()
+    }
+}
+
+/// Decodes the body of a `$'...'` string.
+pub fn decode_ansi_c(s: &str) -> String {
+    let mut result = String::with_capacity(s.len());
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+        let Some(e) = chars.next() else {
+            result.push('\\');
+            break;
+        };
+        match e {
+            'a' => result.push('\x07'),
+            'b' => result.push('\x08'),
+            'e' | 'E' => result.push('\x1b'),
+            'f' => result.push('\x0c'),
+            'n' => result.push('\n'),
+            'r' => result.push('\r'),
+            't' => result.push('\t'),
+            'v' => result.push('\x0b'),
+            '\\' | '\'' | '"' | '?' => result.push(e),
+            '0'..='7' => {
+                let mut value = e.to_digit(8).unwrap();
+                for _ in 0..2 {
+                    match chars.peek().and_then(|c| c.to_digit(8)) {
+                        Some(d) => {
+                            value = value * 8 + d;
+                            chars.next();
+                        }
+                        None => break,
+                    }
+                }
+                result.push(char::from_u32(value & 0xff).unwrap_or('?'));
+            }
+            'x' | 'u' | 'U' => {
+                let max = match e {
+                    'x' => 2,
+                    'u' => 4,
+                    _ => 8,
+                };
+                let mut value = 0u32;
+                let mut digits = 0;
+                while digits < max {
+                    match chars.peek().and_then(|c| c.to_digit(16)) {
+                        Some(d) => {
+                            value = value * 16 + d;
+                            chars.next();
+                            digits += 1;
+                        }
+                        None => break,
+                    }
+                }
+                if digits == 0 {
+                    result.push('\\');
+                    result.push(e);
+                } else {
+                    result.push(char::from_u32(value).unwrap_or('\u{fffd}'));
+                }
+            }
+            'c' => {
+                if let Some(ctl) = chars.next() {
+                    result.push(char::from_u32((ctl.to_ascii_uppercase() as u32) ^ 0x40).unwrap_or('?'));
+                }
+            }
+            other => {
+                result.push('\\');
+                result.push(other);
+            }
+        }
+    }
+    result
+}
+
+/// Removes the backslashes that protect `$`, `` ` `` and `\` inside backquotes.
+fn unescape_backquote(s: &str) -> String {
+    let mut result = String::with_capacity(s.len());
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '\\' && matches!(chars.peek(), Some('$') | Some('`') | Some('\\')) {
+            result.push(chars.next().unwrap());
+        } else {
+            result.push(c);
+        }
+    }
+    result
+}
+
+/// Removes the backslashes that protect `}` in a word read inside double
+/// quotes, leaving the other escapes for the expansion to handle.
+fn unescape_brace(s: &str) -> String {
+    let mut result = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        match (c, chars.clone().next()) {
+            ('\\', Some('}')) => result.push(chars.next().unwrap()),
+            ('\\', Some(next)) => {
+                result.push(c);
+                result.push(next);
+                chars.next();
+            }
+            _ => result.push(c),
+        }
+    }
+    result
+}
+
+/// Looks up the home directory of `user` in the password database.
+fn home_of(user: &str) -> Option<String> {
+    let name = std::ffi::CString::new(user).ok()?;
+    unsafe {
+        let entry = libc::getpwnam(name.as_ptr());
+        if entry.is_null() {
+            return None;
+        }
+        Some(std::ffi::CStr::from_ptr((*entry).pw_dir).to_string_lossy().into_owned())
+    }
+}
+
+/// The text of a field as a pattern, with quoted special characters escaped.
+fn pattern_text(field: &Field) -> String {
+    let mut pattern = String::new();
+    for (i, c) in field.text.char_indices() {
+        if field.origins[i] == Origin::Quoted && matches!(c, '*' | '?' | '[' | ']' | '\\' | '(' | ')' | '|' | '!' | '@' | '+') {
+            pattern.push('\\');
+        }
+        pattern.push(c);
+    }
+    pattern
+}
+
+/// Length of the parameter name at the start of the inside of `${...}`:
+/// a variable name, a positional parameter or a special parameter.
+fn parameter_name_len(expr: &str) -> usize {
+    if expr.starts_with(|c: char| c.is_ascii_digit()) {
+        expr.find(|c: char| !c.is_ascii_digit()).unwrap_or(expr.len())
+    } else if expr.starts_with(['#', '@', '*', '?', '$', '!', '-']) {
+        1
+    } else {
+        let len = expr.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_')).unwrap_or(expr.len());
+        // An array subscript is part of the name
+        match expr[len..].starts_with('[').then(|| parser::subscript_end(&expr[len..])).flatten() {
+            Some(close) if len > 0 => len + close + 1,
+            _ => len,
+        }
+    }
+}
+
+/// Splits `${name[@]...}` or `${name[*]...}` into the array name, whether
+/// it was `@`, and the operation that follows.
+fn whole_array(expr: &str) -> Option<(&str, bool, &str)> {
+    let len = parameter_name_len(expr);
+    let (name, subscript) = arrays::split_subscript(&expr[..len])?;
+    Some((name, arrays::is_whole_array(subscript)?, &expr[len..]))
+}
+
+/// Splits `${@:offset:length}` into its offset and length.
+fn positional_slice(expr: &str) -> Option<(&str, Option<&str>)> {
+    let rest = expr.strip_prefix(['@', '*'])?.strip_prefix(':')?;
+    if rest.starts_with(['-', '=', '?', '+']) {
+        return None;
+    }
+    Some(split_unquoted(rest, ':'))
+}
+
+/// Splits `s` at the first `separator` that is not quoted, escaped or part
+/// of an expansion.
+fn split_unquoted(s: &str, separator: char) -> (&str, Option<&str>) {
+    let mut i = 0;
+    while i < s.len() {
+        let c = s[i..].chars().next().unwrap();
+        if c == separator {
+            return (&s[..i], Some(&s[i + 1..]));
+        }
+        let end = match c {
+            '\\' => Ok(i + 1 + s[i + 1..].chars().next().map_or(0, char::len_utf8)),
+            '\'' => lexer::scan_single_quote(s, i),
+            '"' => lexer::scan_double_quote(s, i),
+            '`' => lexer::scan_backquote(s, i),
+            '$' => lexer::scan_dollar(s, i),
+            _ => Ok(i + c.len_utf8()),
+        };
+        i = end.unwrap_or(s.len());
+    }
+    (s, None)
+}
+
+/// Byte offsets of the character boundaries of `s`, its end included.
+fn boundaries(s: &str) -> Vec<usize> {
+    s.char_indices().map(|(i, _)| i).chain([s.len()]).collect()
+}
+
+/// `${VAR#pattern}` and `${VAR##pattern}`.
+fn remove_prefix(value: &str, pattern: &Pattern, longest: bool) -> String {
+    let mut ends = boundaries(value);
+    if longest {
+        ends.reverse();
+    }
+    match ends.into_iter().find(|&end| pattern.matches(&value[..end])) {
+        Some(end) => value[end..].to_string(),
+        None => value.to_string(),
+    }
+}
+
+/// `${VAR%pattern}` and `${VAR%%pattern}`.
+fn remove_suffix(value: &str, pattern: &Pattern, longest: bool) -> String {
+    let mut starts = boundaries(value);
+    if !longest {
+        starts.reverse();
+    }
+    match starts.into_iter().find(|&start| pattern.matches(&value[start..])) {
+        Some(start) => value[..start].to_string(),
+        None => value.to_string(),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Replace {
+    /// `${VAR/pattern/string}`
+    First,
+    /// `${VAR//pattern/string}`
+    All,
+    /// `${VAR/#pattern/string}`
+    Prefix,
+    /// `${VAR/%pattern/string}`
+    Suffix,
+}
+
+/// Replaces the longest matches of `pattern` in `value`.
+fn replace(value: &str, pattern: &Pattern, replacement: &str, mode: Replace) -> String {
+    let bounds = boundaries(value);
+    match mode {
+        Replace::Prefix => match bounds.iter().rev().find(|&&end| pattern.matches(&value[..end])) {
+            Some(&end) => format!("{}{}", replacement, &value[end..]),
+            None => value.to_string(),
+        },
+        Replace::Suffix => match bounds.iter().find(|&&start| pattern.matches(&value[start..])) {
+            Some(&start) => format!("{}{}", &value[..start], replacement),
+            None => value.to_string(),
+        },
+        Replace::First | Replace::All => {
+            let mut result = String::new();
+            let mut n = 0;
+            while n + 1 < bounds.len() {
+                let start = bounds[n];
+                let end = bounds[n + 1..].iter().rev().position(|&end| pattern.matches(&value[start..end]));
+                match end {
+                    Some(from_back) => {
+                        let end = bounds.len() - 1 - from_back;
+                        result.push_str(replacement);
+                        if mode == Replace::First {
+                            result.push_str(&value[bounds[end]..]);
+                            return result;
+                        }
+                        n = end;
+                    }
+                    None => {
+                        result.push_str(&value[start..bounds[n + 1]]);
+                        n += 1;
+                    }
+                }
+            }
+            result
+        }
+    }
+}
+
+/// `${VAR^pattern}` and friends: changes the case of the first character,
+/// or of every character, that matches `pattern`.
+fn change_case(value: &str, pattern: &Pattern, upper: bool, all: bool) -> String {
+    let mut result = String::with_capacity(value.len());
+    for (n, c) in value.chars().enumerate() {
+        if (all || n == 0) && pattern.matches(c.encode_utf8(&mut [0; 4])) {
+            if upper {
+                result.extend(c.to_uppercase());
+            } else {
+                result.extend(c.to_lowercase());
+            }
+        } else {
+            result.push(c);
+        }
+    }
+    result
+}
+
+/// Quotes `value` so that the shell reads it back as the same word:
+/// single quotes, or `$'...'` when it has control characters.
+pub fn quote(value: &str) -> String {
+    if !value.chars().any(char::is_control) {
+        return format!("'{}'", value.replace('\'', "'\\''"));
+    }
+    let mut quoted = String::from("$'");
+    for c in value.chars() {
+        match c {
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            '\r' => quoted.push_str("\\r"),
+            '\x1b' => quoted.push_str("\\E"),
+            '\x07' => quoted.push_str("\\a"),
+            '\x08' => quoted.push_str("\\b"),
+            '\x0b' => quoted.push_str("\\v"),
+            '\x0c' => quoted.push_str("\\f"),
+            '\\' | '\'' => {
+                quoted.push('\\');
+                quoted.push(c);
+            }
+            c if c.is_control() => quoted.push_str(&format!("\\{:03o}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('\'');
+    quoted
+}
+
+/// Splits fields on IFS characters that came from unquoted expansions.
+pub fn split_fields(fields: Vec<Field>, ifs: &str) -> Vec<Field> {
+    let mut result = Vec::new();
+    for field in fields {
+        let splits = field.origins.contains(&Origin::Separator) || !ifs.is_empty() && field.origins.contains(&Origin::Expanded);
+        if !splits {
+            if !field.text.is_empty() || field.is_quoted() {
+                result.push(field);
+            }
+            continue;
+        }
+
+        let mut current = Field::default();
+        // Whether `current` is a field even if it stays empty.
+        let mut started = false;
+        // Whether the last delimiter was IFS whitespace that already ended a field.
+        let mut split_by_space = false;
+        for (i, c) in field.text.char_indices() {
+            if field.quotes.contains(&i) {
+                current.mark_quoted();
+                started = true;
+            }
+            if field.origins[i] == Origin::Separator {
+                // Each parameter is split on its own; the quote mark of an
+                // empty one in "$@" has already started its field
+                if started {
+                    result.push(std::mem::take(&mut current));
+                }
+                started = false;
+                split_by_space = false;
+                continue;
+            }
+            if field.origins[i] == Origin::Expanded && ifs.contains(c) {
+                if matches!(c, ' ' | '\t' | '\n') {
+                    if started {
+                        result.push(std::mem::take(&mut current));
+                        started = false;
+                        split_by_space = true;
+                    }
+                } else {
+                    if started || !split_by_space {
+                        result.push(std::mem::take(&mut current));
+                    }
+                    started = false;
+                    split_by_space = false;
+                }
+                continue;
+            }
+            current.push(c, field.origins[i]);
+            started = true;
+            split_by_space = false;
+        }
+        if field.quotes.contains(&field.text.len()) {
+            current.mark_quoted();
+            started = true;
+        }
+        if started {
+            result.push(current);
+        }
+    }
+    result
+}
+
+impl Shell {
+    async fn ifs(&self) -> String {
+        let variables = self.variables.read().await;
+        variables.get("IFS").cloned().unwrap_or_else(|| " \t\n".to_string())
+    }
+
+    /// Expands command words into an argument vector.
+    pub(crate) async fn expand_words(&mut self, words: &[Word]) -> Result<Vec<String>, ShellError> {
+        let ifs = self.ifs().await;
+        let mut argv = Vec::new();
+        for word in words {
+            for braced in braces::expand(&word.raw) {
+                let fields = self.expand_word_fields(&braced).await?;
+                for field in split_fields(fields, &ifs) {
+                    self.expand_pathname(field, &mut argv).await?;
+                }
+            }
+        }
+        Ok(argv)
+    }
+
+    /// Expands a single word without field splitting or pathname expansion,
+    /// as for assignments and redirection targets.
+    pub(crate) async fn expand_word(&mut self, word: &Word) -> Result<String, ShellError> {
+        let fields = self.expand_word_fields(&word.raw).await?;
+        Ok(fields.into_iter().map(|f| f.text).collect::<Vec<_>>().join(" "))
+    }
+
+    /// Expands the value of an assignment.  Like a single word, except that
+    /// a `~` after each unquoted `:` is expanded as well as a leading one.
+    pub(crate) async fn expand_assignment_value(&mut self, word: &Word) -> Result<String, ShellError> {
+        let mut field = Field::default();
+        let mut rest = word.raw.as_str();
+        loop {
+            let (part, next) = split_unquoted(rest, ':');
+            let start = self.expand_tilde(part, &mut field).await;
+            self.expand_text(&part[start..], false, &mut field).await?;
+            match next {
+                Some(next) => {
+                    field.push(':', Origin::Literal);
+                    rest = next;
+                }
+                None => return Ok(field.text),
+            }
+        }
+    }
+
+    /// Expands the operands of `declare`, `export`, `local` or `readonly`.
+    /// The value of a `name=value` operand is expanded as an assignment,
+    /// without field splitting; the other operands are ordinary words.
+    pub(crate) async fn expand_declaration(&mut self, words: &[Word]) -> Result<Vec<String>, ShellError> {
+        let mut argv = Vec::with_capacity(words.len());
+        for word in words {
+            match parser::split_assignment(&word.raw) {
+                Some(assignment) if assignment.array.is_none() => {
+                    let prefix = &word.raw[..word.raw.len() - assignment.value.raw.len()];
+                    let value = self.expand_assignment_value(&assignment.value).await?;
+                    argv.push(format!("{}{}", prefix, value));
+                }
+                _ => argv.extend(self.expand_words(std::slice::from_ref(word)).await?),
+            }
+        }
+        Ok(argv)
+    }
+
+    /// Runs all expansions on `raw` and removes quotes, keeping origin information.
+    pub(crate) async fn expand_word_fields(&mut self, raw: &str) -> Result<Vec<Field>, ShellError> {
+        let mut field = Field::default();
+        let rest = self.expand_tilde(raw, &mut field).await;
+        self.expand_text(&raw[rest..], false, &mut field).await?;
+        Ok(vec![field])
+    }
+
+    /// Expands a word used as a pattern.  Characters that were quoted come
+    /// out escaped with a backslash so that they only match themselves.
+    pub(crate) async fn expand_pattern(&mut self, word: &Word) -> Result<String, ShellError> {
+        let fields = self.expand_word_fields(&word.raw).await?;
+        Ok(fields.iter().map(pattern_text).collect())
+    }
+
+    /// Adds the file names a field matches to `argv`, or the field itself
+    /// when it is not a glob or, unless `nullglob` or `failglob` say
+    /// otherwise, matches nothing.  `set -f` turns this off.
+    async fn expand_pathname(&self, field: Field, argv: &mut Vec<String>) -> Result<(), ShellError> {
+        let options = self.shopt.read().await.clone();
+        let pattern = pattern_text(&field);
+        if self.options.read().await.noglob || !pathname::is_glob(&pattern, &options) {
+            argv.push(field.text);
+            return Ok(());
+        }
+        let cwd = self.current_dir.read().await.clone();
+        let paths = pathname::expand(&pattern, &cwd, &options);
+        if !paths.is_empty() {
+            argv.extend(paths);
+        } else if options.failglob {
+            return Err(ShellError::NoMatch(field.text));
+        } else if !options.nullglob {
+            argv.push(field.text);
+        }
+        Ok(())
+    }
+
+    /// Expands and concatenates `raw` into a plain string.
+    pub(crate) async fn expand_fragment(&mut self, raw: &str, in_dquote: bool) -> Result<String, ShellError> {
+        let mut field = Field::default();
+        Box::pin(self.expand_text(raw, in_dquote, &mut field)).await?;
+        Ok(field.text)
+    }
+
+    /// Expands the body of a here-document whose delimiter was not quoted.
+    /// Quotes are ordinary characters here and `\` only escapes `$`, `` ` ``,
+    /// `\` and newline.
+    pub(crate) async fn expand_here_document(&mut self, body: &str) -> Result<String, ShellError> {
+        let mut field = Field::default();
+        let mut i = 0;
+        while i < body.len() {
+            let c = body[i..].chars().next().unwrap();
+            match c {
+                '\\' => match body[i + 1..].chars().next() {
+                    Some('\n') => i += 2,
+                    Some(next @ ('$' | '`' | '\\')) => {
+                        field.push(next, Origin::Quoted);
+                        i += 2;
+                    }
+                    _ => {
+                        field.push('\\', Origin::Quoted);
+                        i += 1;
+                    }
+                },
+                '$' => i = Box::pin(self.expand_dollar(body, i, true, &mut field)).await?,
+                '`' => {
+                    let end = lexer::scan_backquote(body, i)?;
+                    Box::pin(self.expand_text(&body[i..end], true, &mut field)).await?;
+                    i = end;
+                }
+                _ => {
+                    field.push(c, Origin::Quoted);
+                    i += c.len_utf8();
+                }
+            }
+        }
+        Ok(field.text)
+    }
+
+    /// Expands a leading `~`, `~user`, `~+` or `~-` and returns how much of `raw` it consumed.
+    async fn expand_tilde(&self, raw: &str, field: &mut Field) -> usize {
+        if !raw.starts_with('~') {
+            return 0;
+        }
+        let end = raw.find(['/', ':']).unwrap_or(raw.len());
+        let user = &raw[1..end];
+        if user.contains(['\'', '"', '\\', '$', '`']) {
+            return 0;
+        }
+        let home = match user {
+            "" => self.variables.read().await.get("HOME").cloned(),
+            "+" => Some(self.current_dir.read().await.display().to_string()),
+            "-" => self.variables.read().await.get("OLDPWD").cloned(),
+            _ => home_of(user),
+        };
+        match home {
+            Some(home) => {
+                field.push_str(&home, Origin::Quoted);
+                end
+            }
+            None => 0,
+        }
+    }
+
+    async fn expand_text(&mut self, raw: &str, in_dquote: bool, out: &mut Field) -> Result<(), ShellError> {
+        let literal = if in_dquote { Origin::Quoted } else { Origin::Literal };
+        let mut i = 0;
+        while i < raw.len() {
+            let c = raw[i..].chars().next().unwrap();
+            match c {
+                '\\' => match raw[i + 1..].chars().next() {
+                    None => {
+                        out.push('\\', literal);
+                        i += 1;
+                    }
+                    Some('\n') => i += 2,
+                    Some(next) if !in_dquote || matches!(next, '$' | '`' | '"' | '\\') => {
+                        out.push(next, Origin::Quoted);
+                        i += 1 + next.len_utf8();
+                    }
+                    Some(_) => {
+                        out.push('\\', Origin::Quoted);
+                        i += 1;
+                    }
+                },
+                '<' | '>' if !in_dquote && raw[i + 1..].starts_with('(') => {
+                    let end = lexer::scan_command_substitution(raw, i + 1)?;
+                    let path = self.process_substitution(&raw[i + 2..end - 1], c == '>').await?;
+                    out.push_str(&path, Origin::Quoted);
+                    i = end;
+                }
+                '\'' if !in_dquote => {
+                    let end = lexer::scan_single_quote(raw, i)?;
+                    out.mark_quoted();
+                    out.push_str(&raw[i + 1..end - 1], Origin::Quoted);
+                    i = end;
+                }
+                '"' if !in_dquote => {
+                    let end = lexer::scan_double_quote(raw, i)?;
+                    out.mark_quoted();
+                    Box::pin(self.expand_text(&raw[i + 1..end - 1], true, out)).await?;
+                    i = end;
+                }
+                '`' => {
+                    let end = lexer::scan_backquote(raw, i)?;
+                    let command = unescape_backquote(&raw[i + 1..end - 1]);
+                    let output = self.command_substitution(&command).await?;
+                    out.push_str(&output, if in_dquote { Origin::Quoted } else { Origin::Expanded });
+                    i = end;
+                }
+                '$' => i = Box::pin(self.expand_dollar(raw, i, in_dquote, out)).await?,
+                _ => {
+                    out.push(c, literal);
+                    i += c.len_utf8();
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Expands the `$` construct at `i` and returns the index just past it.
+    async fn expand_dollar(&mut self, raw: &str, i: usize, in_dquote: bool, out: &mut Field) -> Result<usize, ShellError> {
+        let origin = if in_dquote { Origin::Quoted } else { Origin::Expanded };
+        let rest = &raw[i..];
+        if rest.starts_with("$((") {
+            let end = lexer::scan_arithmetic(raw, i + 1)?;
+            let expression = self.expand_fragment(&raw[i + 3..end - 2], true).await?;
+            let value = self.evaluate_arithmetic(&expression).await?;
+            out.push_str(&value.to_string(), origin);
+            Ok(end)
+        } else if rest.starts_with("$(") {
+            let end = lexer::scan_command_substitution(raw, i + 1)?;
+            let output = self.command_substitution(&raw[i + 2..end - 1]).await?;
+            out.push_str(&output, origin);
+            Ok(end)
+        } else if rest.starts_with("${") {
+            let end = lexer::scan_parameter(raw, i + 1)?;
+            let expr = &raw[i + 2..end - 1];
+            if let "@" | "*" = expr {
+                let params = self.positional.read().await.clone();
+                self.expand_positional(params, expr == "@", in_dquote, out).await;
+                return Ok(end);
+            }
+            if let Some((name, at, operation)) = whole_array(expr) {
+                Box::pin(self.expand_array(expr, name, at, operation, in_dquote, out)).await?;
+                return Ok(end);
+            }
+            if let Some(range) = positional_slice(expr) {
+                let params = self.slice_positional(range).await?;
+                self.expand_positional(params, expr.starts_with('@'), in_dquote, out).await;
+                return Ok(end);
+            }
+            Box::pin(self.expand_parameter(expr, in_dquote, out)).await?;
+            Ok(end)
+        } else if rest.starts_with("$'") && !in_dquote {
+            let end = lexer::scan_ansi_c_quote(raw, i)?;
+            out.mark_quoted();
+            out.push_str(&decode_ansi_c(&raw[i + 2..end - 1]), Origin::Quoted);
+            Ok(end)
+        } else if rest.starts_with("$\"") && !in_dquote {
+            let end = lexer::scan_double_quote(raw, i + 1)?;
+            out.mark_quoted();
+            Box::pin(self.expand_text(&raw[i + 2..end - 1], true, out)).await?;
+            Ok(end)
+        } else if let Some(c @ ('@' | '*')) = rest[1..].chars().next() {
+            let params = self.positional.read().await.clone();
+            self.expand_positional(params, c == '@', in_dquote, out).await;
+            Ok(i + 2)
+        } else if let Some(c @ ('#' | '?' | '$' | '!' | '-' | '0'..='9')) = rest[1..].chars().next() {
+            let value = self.special_parameter(&c.to_string()).await;
+            let value = self.check_bound(&c.to_string(), value).await?;
+            out.push_str(&value, origin);
+            Ok(i + 2)
+        } else {
+            let name_len = rest[1..]
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len() - 1);
+            if name_len == 0 {
+                out.push('$', if in_dquote { Origin::Quoted } else { Origin::Literal });
+                return Ok(i + 1);
+            }
+            let name = &rest[1..1 + name_len];
+            let value = self.parameter_value(name).await;
+            let value = self.check_bound(name, value).await?;
+            out.push_str(&value, origin);
+            Ok(i + 1 + name_len)
+        }
+    }
+
+    /// Expands `$@` or `$*`, or a slice of them, given as `params`.  Inside
+    /// double quotes `"$@"` keeps every parameter a separate field and `"$*"`
+    /// joins them with the first character of IFS.
+    async fn expand_positional(&mut self, params: Vec<String>, at: bool, in_dquote: bool, out: &mut Field) {
+        if !in_dquote {
+            for (n, param) in params.iter().enumerate() {
+                if n > 0 {
+                    out.push(' ', Origin::Separator);
+                }
+                out.push_str(param, Origin::Expanded);
+            }
+        } else if at {
+            if params.is_empty() && out.quotes.last() == Some(&out.text.len()) {
+                // "$@" without parameters leaves no empty field behind
+                out.quotes.pop();
+            }
+            for (n, param) in params.iter().enumerate() {
+                if n > 0 {
+                    // Every parameter of "$@" is a field, even an empty one
+                    out.push(' ', Origin::Separator);
+                    out.mark_quoted();
+                }
+                out.push_str(param, Origin::Quoted);
+            }
+        } else {
+            let separator = self.ifs().await.chars().next().map(String::from).unwrap_or_default();
+            out.push_str(&params.join(&separator), Origin::Quoted);
+        }
+    }
+
+    /// Value of a positional or special parameter; `None` when `name` is
+    /// not one or is an unset positional parameter.
+    async fn special_parameter(&self, name: &str) -> Option<String> {
+        match name {
+            "0" => Some(self.arg0.clone()),
+            "#" => Some(self.positional.read().await.len().to_string()),
+            "?" => Some(self.exit_code.read().await.to_string()),
+            "$" => Some(self.shell_pid.to_string()),
+            "!" => self.last_background.read().await.map(|pid| pid.to_string()),
+            "-" => {
+                let flags = self.options.read().await.flags();
+                Some(if self.interactive { format!("i{}", flags) } else { flags })
+            }
+            "@" | "*" => Some(self.positional.read().await.join(" ")),
+            _ if !name.is_empty() && name.bytes().all(|b| b.is_ascii_digit()) => {
+                let index = name.parse::<usize>().ok()?.checked_sub(1)?;
+                self.positional.read().await.get(index).cloned()
+            }
+            _ => None,
+        }
+    }
+
+    /// Looks up a variable, following namerefs, or a positional or special
+    /// parameter.
+    pub(crate) async fn parameter_value(&self, name: &str) -> Option<String> {
+        if let Some(value) = self.special_parameter(name).await {
+            return Some(value);
+        }
+        let name = &self.resolve_nameref(name).await;
+        if let Some(value) = self.dynamic_variable(name).await {
+            return Some(value);
+        }
+        if let Some(value) = self.variables.read().await.get(name) {
+            return Some(value.clone());
+        }
+        self.array_scalar(name).await
+    }
+
+    /// The value of a parameter about to be expanded; under `set -u` an
+    /// unset one is an error.
+    async fn check_bound(&self, name: &str, value: Option<String>) -> Result<String, ShellError> {
+        match value {
+            Some(value) => Ok(value),
+            None if self.options.read().await.nounset => Err(ShellError::Expansion(format!("{}: unbound variable", name))),
+            None => Ok(String::new()),
+        }
+    }
+
+    /// Looks up a parameter that may be an array element `name[subscript]`.
+    async fn lookup(&mut self, name: &str) -> Result<Option<String>, ShellError> {
+        match arrays::split_subscript(name) {
+            Some((name, subscript)) => {
+                let subscript = self.expand_fragment(subscript, false).await?;
+                self.element(name, &subscript).await
+            }
+            None => Ok(self.parameter_value(name).await),
+        }
+    }
+
+    /// Expands the inside of `${...}` into `out`.
+    async fn expand_parameter(&mut self, expr: &str, in_dquote: bool, out: &mut Field) -> Result<(), ShellError> {
+        let origin = if in_dquote { Origin::Quoted } else { Origin::Expanded };
+        let bad_substitution = || ShellError::SyntaxError(format!("${{{}}}: bad substitution", expr));
+
+        // ${!prefix*} and ${!prefix@} - names of variables starting with prefix
+        if let Some(prefix) = expr.strip_prefix('!').and_then(|e| e.strip_suffix(['*', '@'])) {
+            if parser::is_valid_name(prefix) {
+                let mut names: Vec<String> = self.variables.read().await.keys().filter(|name| name.starts_with(prefix)).cloned().collect();
+                names.extend(self.arrays.read().await.keys().filter(|name| name.starts_with(prefix)).cloned());
+                names.sort();
+                out.push_str(&names.join(" "), origin);
+                return Ok(());
+            }
+        }
+        if let Some(name) = expr.strip_prefix('#').filter(|name| !name.is_empty()) {
+            // ${#VAR} - length, ${#@} - number of parameters
+            if name == "@" || name == "*" {
+                out.push_str(&self.positional.read().await.len().to_string(), origin);
+                return Ok(());
+            }
+            if parameter_name_len(name) != name.len() {
+                return Err(bad_substitution());
+            }
+            // ${#arr[@]} - number of elements
+            if let Some((name, _, "")) = whole_array(name) {
+                out.push_str(&self.elements(name).await.len().to_string(), origin);
+                return Ok(());
+            }
+            let value = self.lookup(name).await?;
+            let length = self.check_bound(name, value).await?.chars().count();
+            out.push_str(&length.to_string(), origin);
+            return Ok(());
+        }
+
+        // ${!arr[@]} - indices or keys of an array
+        if let Some((name, at, "")) = expr.strip_prefix('!').and_then(whole_array) {
+            let keys = self.element_keys(name).await;
+            self.expand_positional(keys, at, in_dquote, out).await;
+            return Ok(());
+        }
+
+        // ${!name...} looks up the parameter named by the value of `name`
+        let (indirect, expr_rest) = match expr.strip_prefix('!') {
+            Some(rest) if !rest.is_empty() => (true, rest),
+            _ => (false, expr),
+        };
+        let name_len = parameter_name_len(expr_rest);
+        if name_len == 0 {
+            return Err(bad_substitution());
+        }
+        let (name, operation) = expr_rest.split_at(name_len);
+        let mut name = name.to_string();
+        let mut value = self.lookup(&name).await?;
+        // ${!ref} of a nameref is the name it refers to
+        if let Some(target) = self.nameref_target(&name).await.filter(|_| indirect) {
+            value = Some(target);
+        } else if indirect {
+            let target = value.unwrap_or_default();
+            if target.is_empty() || parameter_name_len(&target) != target.len() {
+                return Err(ShellError::Expansion(format!("{}: invalid indirect expansion", name)));
+            }
+            value = self.lookup(&target).await?;
+            name = target;
+        }
+
+        if operation.is_empty() {
+            out.push_str(&self.check_bound(&name, value).await?, origin);
+            return Ok(());
+        }
+
+        // ${VAR-word} and friends test whether VAR is set; with a colon,
+        // whether it is set and not empty
+        let colon = operation.starts_with(':');
+        if let Some(op @ ('-' | '=' | '?' | '+')) = operation[colon as usize..].chars().next() {
+            let word = &operation[colon as usize + 1..];
+            let present = if colon { value.as_deref().is_some_and(|v| !v.is_empty()) } else { value.is_some() };
+            match op {
+                '-' if present => out.push_str(&value.unwrap_or_default(), origin),
+                '-' => self.expand_operand(word, in_dquote, out).await?,
+                '=' if present => out.push_str(&value.unwrap_or_default(), origin),
+                '=' => {
+                    let mut field = Field::default();
+                    self.expand_operand_text(word, in_dquote, &mut field).await?;
+                    let text = field.text;
+                    if let Some((array, subscript)) = arrays::split_subscript(&name) {
+                        let subscript = self.expand_fragment(subscript, false).await?;
+                        self.assign_element(array, &subscript, text.clone(), false).await?;
+                    } else if parser::is_valid_name(&name) {
+                        self.assign_scalar(&name, text.clone(), false).await?;
+                    } else {
+                        return Err(ShellError::Expansion(format!("${}: cannot assign in this way", name)));
+                    }
+                    out.push_str(&text, origin);
+                }
+                '?' if present => out.push_str(&value.unwrap_or_default(), origin),
+                '?' => {
+                    let mut message = Field::default();
+                    self.expand_operand_text(word, in_dquote, &mut message).await?;
+                    let message = message.text;
+                    let message = if message.is_empty() {
+                        if colon { "parameter null or not set" } else { "parameter not set" }.to_string()
+                    } else {
+                        message
+                    };
+                    return Err(ShellError::Expansion(format!("{}: {}", name, message)));
+                }
+                _ if present => self.expand_operand(word, in_dquote, out).await?,
+                _ => {}
+            }
+            return Ok(());
+        }
+
+        let value = self.check_bound(&name, value).await?;
+        let result = self.transform(&name, value, operation, expr, in_dquote).await?;
+        out.push_str(&result, origin);
+        Ok(())
+    }
+
+    /// Applies the pattern, substring and case operations of `${...}` to
+    /// `value`, the value of `name`.
+    async fn transform(&mut self, name: &str, value: String, operation: &str, expr: &str, in_dquote: bool) -> Result<String, ShellError> {
+        let bad_substitution = || ShellError::SyntaxError(format!("${{{}}}: bad substitution", expr));
+        let extglob = self.shopt.read().await.extglob;
+        let result = if let Some(rest) = operation.strip_prefix('#') {
+            // ${VAR#pattern} and ${VAR##pattern}
+            let (longest, word) = match rest.strip_prefix('#') {
+                Some(word) => (true, word),
+                None => (false, rest),
+            };
+            let pattern = Pattern::parse(&self.expand_pattern_text(word).await?, extglob);
+            remove_prefix(&value, &pattern, longest)
+        } else if let Some(rest) = operation.strip_prefix('%') {
+            // ${VAR%pattern} and ${VAR%%pattern}
+            let (longest, word) = match rest.strip_prefix('%') {
+                Some(word) => (true, word),
+                None => (false, rest),
+            };
+            let pattern = Pattern::parse(&self.expand_pattern_text(word).await?, extglob);
+            remove_suffix(&value, &pattern, longest)
+        } else if let Some(rest) = operation.strip_prefix('/') {
+            // ${VAR/pattern/string}, anchored with # or %, all matches with /
+            let (mode, rest) = match rest.chars().next() {
+                Some('/') => (Replace::All, &rest[1..]),
+                Some('#') => (Replace::Prefix, &rest[1..]),
+                Some('%') => (Replace::Suffix, &rest[1..]),
+                _ => (Replace::First, rest),
+            };
+            let (word, replacement) = split_unquoted(rest, '/');
+            let pattern_text = self.expand_pattern_text(word).await?;
+            let replacement = match replacement {
+                Some(text) if in_dquote => self.expand_fragment(&unescape_brace(text), true).await?,
+                text => self.expand_fragment(text.unwrap_or(""), in_dquote).await?,
+            };
+            if pattern_text.is_empty() && matches!(mode, Replace::All | Replace::First) {
+                value
+            } else {
+                replace(&value, &Pattern::parse(&pattern_text, extglob), &replacement, mode)
+            }
+        } else if let Some(rest) = operation.strip_prefix(':') {
+            // ${VAR:offset} and ${VAR:offset:length}; an empty offset is 0,
+            // but there must be something after the colon
+            if rest.is_empty() {
+                return Err(bad_substitution());
+            }
+            let (offset, length) = split_unquoted(rest, ':');
+            let chars: Vec<char> = value.chars().collect();
+            let (start, end) = self.substring_range(offset, length, chars.len()).await?;
+            chars[start..end].iter().collect()
+        } else if let Some(rest) = operation.strip_prefix(['^', ',']) {
+            // ${VAR^pattern}, ${VAR^^pattern}, ${VAR,pattern} and ${VAR,,pattern}
+            let upper = operation.starts_with('^');
+            let (all, word) = match rest.strip_prefix(if upper { '^' } else { ',' }) {
+                Some(word) => (true, word),
+                None => (false, rest),
+            };
+            let pattern = if word.is_empty() { "?".to_string() } else { self.expand_pattern_text(word).await? };
+            change_case(&value, &Pattern::parse(&pattern, extglob), upper, all)
+        } else if let Some(transform) = operation.strip_prefix('@') {
+            // ${VAR@Q} and the other transformations
+            match transform {
+                "Q" => quote(&value),
+                "E" => decode_ansi_c(&value),
+                "U" => value.to_uppercase(),
+                "L" => value.to_lowercase(),
+                "u" => change_case(&value, &Pattern::parse("?", false), true, false),
+                "A" => format!("{}={}", name, quote(&value)),
+                _ => return Err(bad_substitution()),
+            }
+        } else {
+            return Err(bad_substitution());
+        };
+        Ok(result)
+    }
+
+    /// Expands `${arr[@]...}` and `${arr[*]...}`: the elements are fields
+    /// like the positional parameters of `$@` and `$*`, and an operation
+    /// applies to each of them.
+    async fn expand_array(&mut self, expr: &str, name: &str, at: bool, operation: &str, in_dquote: bool, out: &mut Field) -> Result<(), ShellError> {
+        let values = self.elements(name).await;
+        let colon = operation.starts_with(':');
+        match operation[colon as usize..].chars().next() {
+            None => self.expand_positional(values, at, in_dquote, out).await,
+            Some(op @ ('-' | '=' | '?' | '+')) => {
+                let word = &operation[colon as usize + 1..];
+                let present = if colon { values.iter().any(|v| !v.is_empty()) } else { !values.is_empty() };
+                match op {
+                    '-' | '=' | '?' if present => self.expand_positional(values, at, in_dquote, out).await,
+                    '-' => self.expand_operand(word, in_dquote, out).await?,
+                    '=' => return Err(ShellError::Expansion(format!("{}: cannot assign list element", &expr[..expr.len() - operation.len()]))),
+                    '?' => {
+                        let mut message = Field::default();
+                        self.expand_operand_text(word, in_dquote, &mut message).await?;
+                        let message = message.text;
+                        let message = if message.is_empty() { "parameter null or not set".to_string() } else { message };
+                        return Err(ShellError::Expansion(format!("{}: {}", name, message)));
+                    }
+                    _ if present => self.expand_operand(word, in_dquote, out).await?,
+                    _ => {}
+                }
+            }
+            Some(_) if colon => {
+                // ${arr[@]:offset:length}
+                let (offset, length) = split_unquoted(&operation[1..], ':');
+                let offset = self.expand_fragment(offset, true).await?;
+                let offset = self.evaluate_arithmetic(&offset).await?;
+                let length = match length {
+                    Some(length) => {
+                        let length = self.expand_fragment(length, true).await?;
+                        Some(self.evaluate_arithmetic(&length).await?)
+                    }
+                    None => None,
+                };
+                let values = self.slice_elements(name, offset, length).await?;
+                self.expand_positional(values, at, in_dquote, out).await;
+            }
+            Some(_) => {
+                let mut results = Vec::with_capacity(values.len());
+                for value in values {
+                    results.push(self.transform(name, value, operation, expr, in_dquote).await?);
+                }
+                self.expand_positional(results, at, in_dquote, out).await;
+            }
+        }
+        Ok(())
+    }
+
+    /// Expands the word of `${VAR-word}` and the like into `out`.  Its
+    /// unquoted parts count as expanded, so they are split like the value of
+    /// the parameter would be.
+    async fn expand_operand(&mut self, raw: &str, in_dquote: bool, out: &mut Field) -> Result<(), ShellError> {
+        let mut field = Field::default();
+        self.expand_operand_text(raw, in_dquote, &mut field).await?;
+        let offset = out.text.len();
+        out.quotes.extend(field.quotes.iter().map(|quote| quote + offset));
+        out.text.push_str(&field.text);
+        out.origins.extend(field.origins.iter().map(|&origin| if origin == Origin::Literal { Origin::Expanded } else { origin }));
+        Ok(())
+    }
+
+    /// Expands the word of `${VAR-word}` and the like.  Unquoted, it starts
+    /// with tilde expansion.  Inside double quotes a nested `"..."` opens a
+    /// quoted part of its own and its quotes are removed.
+    async fn expand_operand_text(&mut self, raw: &str, in_dquote: bool, field: &mut Field) -> Result<(), ShellError> {
+        if !in_dquote {
+            let start = self.expand_tilde(raw, field).await;
+            return Box::pin(self.expand_text(&raw[start..], false, field)).await;
+        }
+        let mut start = 0;
+        let mut i = 0;
+        while i < raw.len() {
+            let c = raw[i..].chars().next().unwrap();
+            i = match c {
+                '\\' => i + 1 + raw[i + 1..].chars().next().map_or(0, char::len_utf8),
+                '`' => lexer::scan_backquote(raw, i)?,
+                '$' => lexer::scan_dollar(raw, i)?,
+                '"' => {
+                    let end = lexer::scan_double_quote(raw, i)?;
+                    Box::pin(self.expand_text(&raw[start..i], true, field)).await?;
+                    field.mark_quoted();
+                    Box::pin(self.expand_text(&raw[i + 1..end - 1], true, field)).await?;
+                    start = end;
+                    end
+                }
+                _ => i + c.len_utf8(),
+            };
+        }
+        Box::pin(self.expand_text(&raw[start..], true, field)).await
+    }
+
+    /// Expands the pattern operand of `${VAR#pattern}` and the like.  It is
+    /// a pattern even inside double quotes, unless quoted within the braces.
+    async fn expand_pattern_text(&mut self, raw: &str) -> Result<String, ShellError> {
+        let mut field = Field::default();
+        Box::pin(self.expand_text(raw, false, &mut field)).await?;
+        Ok(pattern_text(&field))
+    }
+
+    /// Evaluates the `offset[:length]` of a substring expansion on a value
+    /// of `len` items into the range it selects.
+    async fn substring_range(&mut self, offset: &str, length: Option<&str>, len: usize) -> Result<(usize, usize), ShellError> {
+        let len = len as i64;
+        let offset = self.expand_fragment(offset, true).await?;
+        let mut start = self.evaluate_arithmetic(&offset).await?;
+        // A negative offset counts from the end
+        if start < 0 {
+            start += len;
+            if start < 0 {
+                return Ok((0, 0));
+            }
+        }
+        let start = start.min(len);
+        let end = match length {
+            None => len,
+            Some(length) => {
+                let length = self.expand_fragment(length, true).await?;
+                let length = self.evaluate_arithmetic(&length).await?;
+                if length < 0 {
+                    // A negative length counts back from the end
+                    if len + length < start {
+                        return Err(ShellError::Expansion(format!("{}: substring expression < 0", length)));
+                    }
+                    len + length
+                } else {
+                    start.saturating_add(length).min(len)
+                }
+            }
+        };
+        Ok((start as usize, end as usize))
+    }
+
+    /// `${@:offset:length}`: the selected parameters, where `$0` is the
+    /// one at offset 0.
+    async fn slice_positional(&mut self, (offset, length): (&str, Option<&str>)) -> Result<Vec<String>, ShellError> {
+        let mut params = vec![self.arg0.clone()];
+        params.extend(self.positional.read().await.iter().cloned());
+        let (start, end) = self.substring_range(offset, length, params.len()).await?;
+        Ok(params[start..end].to_vec())
+    }
+}
@@ -0,0 +1,183 @@
+// Shell functions.
+//
+// A function is kept as its parsed definition and runs in the calling shell.
+// Each call pushes a frame holding the caller's positional parameters and
+// the previous values and attributes of the variables the function makes
+// `local`; popping the frame puts them back, which gives bash's dynamic
+// scoping: a function sees the locals of its callers, and they reappear
+// once it returns.
+
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::sync::Arc;
+
+use crate::arrays::Array;
+use crate::ast::FunctionDef;
+use crate::streams::Streams;
+use crate::traps::Condition;
+use crate::variables::{Attributes, SavedVariable};
+use crate::{split_declare_flags, Shell, ShellError};
+
+#[derive(Debug)]
+pub struct Frame {
+    pub name: String,
+    /// Positional parameters of the caller.
+    positional: Vec<String>,
+    /// Loops the caller was in; `break` cannot reach them from the function.
+    loop_depth: usize,
+    /// Variables made local in this call as they were outside it.
+    saved: Vec<SavedVariable>,
+}
+
+impl Shell {
+    pub(crate) async fn find_function(&self, name: &str) -> Option<Arc<FunctionDef>> {
+        self.functions.read().await.get(name).cloned()
+    }
+
+    pub(crate) async fn define_function(&self, function: &Arc<FunctionDef>) {
+        // `LINENO` in the body counts from the script the definition is in
+        let line_offset = self.line.read().await.0;
+        let function = if function.line_offset == line_offset {
+            function.clone()
+        } else {
+            Arc::new(FunctionDef { line_offset, ..FunctionDef::clone(function) })
+        };
+        self.functions.write().await.insert(function.name.clone(), function);
+    }
+
+    /// Runs `function` with `argv[1..]` as its positional parameters.
+    pub(crate) async fn call_function(&mut self, function: &FunctionDef, argv: &[String]) -> Result<i32, ShellError> {
+        // FUNCNEST caps the depth of recursion when set
+        let limit = self.variables.read().await.get("FUNCNEST").and_then(|n| n.parse::<usize>().ok());
+        if let Some(limit) = limit.filter(|&limit| limit > 0) {
+            if self.frames.read().await.len() >= limit {
+                eprintln!("{}: maximum function nesting level exceeded ({})", function.name, limit);
+                return Ok(1);
+            }
+        }
+        let positional = std::mem::replace(&mut *self.positional.write().await, argv[1..].to_vec());
+        let loop_depth = std::mem::take(&mut *self.loop_depth.write().await);
+        self.frames.write().await.push(Frame {
+            name: function.name.clone(),
+            positional,
+            loop_depth,
+            saved: Vec::new(),
+        });
+        self.push_funcname(&function.name).await;
+        let hidden = self.hide_function_traps().await;
+        let line = std::mem::replace(&mut *self.line.write().await, (function.line_offset, 0));
+
+        let result = Box::pin(self.execute_single_command(&function.body)).await;
+        *self.line.write().await = line;
+        // Only a RETURN trap the function set itself fires here
+        let result = self.run_trap(Condition::Return).await.and(result);
+        self.restore_function_traps(hidden).await;
+
+        let frame = self.frames.write().await.pop().expect("function frame");
+        self.restore_variables(frame.saved).await;
+        *self.positional.write().await = frame.positional;
+        *self.loop_depth.write().await = frame.loop_depth;
+        self.pop_funcname().await;
+
+        match result {
+            Err(ShellError::Return(code)) => Ok(code),
+            result => result,
+        }
+    }
+
+    /// Puts the function being called in front of `FUNCNAME`, which lists
+    /// every active call innermost first, so `$FUNCNAME` names the running
+    /// function.  The other names move along rather than being rebuilt from
+    /// the frames, as recursion can run thousands of calls deep.
+    async fn push_funcname(&self, name: &str) {
+        let mut arrays = self.arrays.write().await;
+        let names = match arrays.remove("FUNCNAME") {
+            Some(Array::Indexed(names)) => names,
+            _ => BTreeMap::new(),
+        };
+        let names = std::iter::once((0, name.to_string())).chain(names.into_iter().map(|(i, name)| (i + 1, name))).collect();
+        arrays.insert("FUNCNAME".to_string(), Array::Indexed(names));
+    }
+
+    /// Takes the returning function off the front of `FUNCNAME`.
+    async fn pop_funcname(&self) {
+        let mut arrays = self.arrays.write().await;
+        if let Some(Array::Indexed(names)) = arrays.remove("FUNCNAME") {
+            let names: BTreeMap<usize, String> = names.into_iter().filter(|&(i, _)| i > 0).map(|(i, name)| (i - 1, name)).collect();
+            if !names.is_empty() {
+                arrays.insert("FUNCNAME".to_string(), Array::Indexed(names));
+            }
+        }
+    }
+
+    pub(crate) async fn in_function(&self) -> bool {
+        !self.frames.read().await.is_empty()
+    }
+
+    /// Makes `name` local to the running function.  The new local starts
+    /// out unset, keeping only the export attribute of the variable it hides.
+    pub(crate) async fn make_local(&self, name: &str) {
+        if self.frames.read().await.last().is_none_or(|frame| frame.saved.iter().any(|saved| saved.name() == name)) {
+            return;
+        }
+        let saved = self.save_variable(name).await;
+        let exported = self.attributes(name).await.exported;
+        self.remove_variable(name).await;
+        if exported {
+            self.attributes.write().await.insert(name.to_string(), Attributes { exported, ..Attributes::default() });
+        }
+        if let Some(frame) = self.frames.write().await.last_mut() {
+            frame.saved.push(saved);
+        }
+    }
+
+    pub(crate) async fn builtin_local(&mut self, args: &[String], io: &mut Streams) -> Result<i32, ShellError> {
+        if !self.in_function().await {
+            writeln!(io.stderr, "local: can only be used in a function")?;
+            return Ok(1);
+        }
+        let (on, off, operands) = split_declare_flags(args);
+        self.declare_variables("local", (&on, &off), &operands, true, io).await
+    }
+
+    pub(crate) async fn builtin_return(&self, args: &[String], io: &mut Streams) -> Result<i32, ShellError> {
+        if !self.in_function().await && *self.sourcing.read().await == 0 {
+            writeln!(io.stderr, "return: can only `return' from a function or sourced script")?;
+            return Ok(1);
+        }
+        let code = match args.first() {
+            None => *self.exit_code.read().await,
+            Some(arg) => match arg.parse::<i32>() {
+                Ok(code) => code & 0xff,
+                Err(_) => {
+                    writeln!(io.stderr, "return: {}: numeric argument required", arg)?;
+                    2
+                }
+            },
+        };
+        Err(ShellError::Return(code))
+    }
+
+    /// Prints functions as `declare -f` does; all of them, sorted by name,
+    /// when `names` is empty.  Returns false if one of `names` is not a
+    /// function.
+    pub(crate) async fn print_functions(&self, names: &[String], names_only: bool, io: &mut Streams) -> Result<bool, ShellError> {
+        let functions = self.functions.read().await;
+        let mut selected: Vec<&Arc<FunctionDef>> = if names.is_empty() {
+            functions.values().collect()
+        } else {
+            names.iter().filter_map(|name| functions.get(name)).collect()
+        };
+        if names.is_empty() {
+            selected.sort_by(|a, b| a.name.cmp(&b.name));
+        }
+        for function in &selected {
+            if names_only {
+                writeln!(io.stdout, "declare -f {}", function.name)?;
+            } else {
+                writeln!(io.stdout, "{} () \n{}", function.name, function.body_text)?;
+            }
+        }
+        Ok(selected.len() == names.len() || names.is_empty())
+    }
+}
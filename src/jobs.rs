@@ -0,0 +1,913 @@
+// Job control.
+//
+// Every pipeline the shell starts is a job.  With the `monitor` option on
+// (the default for an interactive shell on a terminal) each job runs in its
+// own process group and a foreground job owns the terminal while it runs, so
+// Ctrl-Z stops just that job.  Stopped and background jobs live in the job
+// table, where `jobs`, `fg`, `bg`, `wait`, `kill` and `disown` find them.
+// Children are collected as SIGCHLD reports them; finished jobs are announced
+// before the next prompt and their statuses kept for a later `wait`.
+
+use std::io::Write;
+use std::os::unix::io::{BorrowedFd, RawFd};
+use std::os::unix::process::CommandExt;
+
+use nix::errno::Errno;
+use nix::sys::signal::{self, killpg, SigHandler, Signal};
+use nix::sys::termios::{tcgetattr, tcsetattr, SetArg, Termios};
+use nix::sys::wait::{waitpid, WaitPidFlag, WaitStatus};
+use nix::unistd::{getpgrp, setpgid, tcgetpgrp, tcsetpgrp, Pid};
+use futures::StreamExt;
+use signal_hook::consts::SIGCHLD;
+use signal_hook_tokio::Signals;
+
+use crate::signals;
+use crate::streams::Streams;
+use crate::{Shell, ShellError};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProcessState {
+    Running,
+    Stopped,
+    Exited(i32),
+    Signaled(Signal),
+}
+
+impl ProcessState {
+    fn from_wait_status(status: WaitStatus) -> Option<ProcessState> {
+        match status {
+            WaitStatus::Exited(_, code) => Some(ProcessState::Exited(code)),
+            WaitStatus::Signaled(_, signal, _) => Some(ProcessState::Signaled(signal)),
+            WaitStatus::Stopped(..) => Some(ProcessState::Stopped),
+            WaitStatus::Continued(_) => Some(ProcessState::Running),
+            _ => None,
+        }
+    }
+
+    fn is_finished(self) -> bool {
+        matches!(self, ProcessState::Exited(_) | ProcessState::Signaled(_))
+    }
+
+    /// The process's exit status as `$?` reports it.
+    fn status(self) -> i32 {
+        match self {
+            ProcessState::Exited(code) => code,
+            ProcessState::Signaled(signal) => 128 + signal as i32,
+            ProcessState::Stopped => 128 + Signal::SIGTSTP as i32,
+            ProcessState::Running => 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Process {
+    pub pid: Pid,
+    pub state: ProcessState,
+}
+
+#[derive(Debug, Clone)]
+pub struct Job {
+    /// Job number; 0 until the job enters the job table.
+    pub id: u32,
+    pub command: String,
+    /// Process group of the job, or `None` when it shares the shell's group.
+    pub pgid: Option<Pid>,
+    pub processes: Vec<Process>,
+    /// Stopped or finished since the user was last told about it.
+    pub changed: bool,
+}
+
+impl Job {
+    pub fn new(command: &str) -> Self {
+        Job {
+            id: 0,
+            command: command.to_string(),
+            pgid: None,
+            processes: Vec::new(),
+            changed: false,
+        }
+    }
+
+    pub fn push(&mut self, pid: Pid) {
+        self.processes.push(Process { pid, state: ProcessState::Running });
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.processes.iter().all(|p| p.state.is_finished())
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        !self.is_done() && self.processes.iter().any(|p| p.state == ProcessState::Stopped)
+    }
+
+    /// The job's exit status as `$?` reports it: that of its last process.
+    pub fn status(&self) -> i32 {
+        self.processes.last().map_or(0, |p| p.state.status())
+    }
+
+    /// The status of each process, in pipeline order.
+    pub fn statuses(&self) -> Vec<i32> {
+        self.processes.iter().map(|p| p.state.status()).collect()
+    }
+
+    /// State column of the `jobs` listing.
+    pub fn state_text(&self) -> String {
+        if self.is_stopped() {
+            return "Stopped".to_string();
+        }
+        if !self.is_done() {
+            return "Running".to_string();
+        }
+        match self.processes.last().map(|p| p.state) {
+            Some(ProcessState::Exited(0)) | None => "Done".to_string(),
+            Some(ProcessState::Exited(code)) => format!("Exit {}", code),
+            Some(ProcessState::Signaled(signal)) => signal_description(signal).to_string(),
+            Some(_) => unreachable!("finished job with a live process"),
+        }
+    }
+
+    /// Sends `sig` to every process of the job.
+    pub fn signal(&self, sig: Signal) -> nix::Result<()> {
+        match self.pgid {
+            Some(pgid) => killpg(pgid, sig),
+            None => {
+                for process in self.processes.iter().filter(|p| !p.state.is_finished()) {
+                    signal::kill(process.pid, sig)?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Marks the job's live processes as running again and sends them SIGCONT.
+    pub fn resume(&mut self) -> nix::Result<()> {
+        for process in self.processes.iter_mut().filter(|p| !p.state.is_finished()) {
+            process.state = ProcessState::Running;
+        }
+        self.signal(Signal::SIGCONT)
+    }
+
+    fn leader(&self) -> Option<Pid> {
+        self.processes.first().map(|p| p.pid)
+    }
+}
+
+/// How bash names the signal that killed a job.
+fn signal_description(signal: Signal) -> &'static str {
+    match signal {
+        Signal::SIGHUP => "Hangup",
+        Signal::SIGINT => "Interrupt",
+        Signal::SIGQUIT => "Quit",
+        Signal::SIGILL => "Illegal instruction",
+        Signal::SIGABRT => "Aborted",
+        Signal::SIGKILL => "Killed",
+        Signal::SIGSEGV => "Segmentation fault",
+        Signal::SIGPIPE => "Broken pipe",
+        Signal::SIGALRM => "Alarm clock",
+        Signal::SIGTERM => "Terminated",
+        other => other.as_str(),
+    }
+}
+
+/// Parses a signal given as a number, `TERM` or `SIGTERM`.
+pub fn parse_signal(name: &str) -> Option<Signal> {
+    if let Ok(number) = name.parse::<i32>() {
+        return Signal::try_from(number).ok();
+    }
+    let name = name.to_ascii_uppercase();
+    let name = if name.starts_with("SIG") { name } else { format!("SIG{}", name) };
+    name.parse().ok()
+}
+
+/// How many reported jobs keep their status around for `wait`.
+const FINISHED_LIMIT: usize = 256;
+
+/// Jobs that have been stopped or sent to the background.
+#[derive(Debug, Default)]
+pub struct JobTable {
+    jobs: Vec<Job>,
+    /// Job ids from least to most recently stopped or backgrounded; the
+    /// last one is the current job `%+`, the one before it `%-`.
+    recent: Vec<u32>,
+    /// Finished jobs that have left the table, oldest first.
+    finished: Vec<Job>,
+    /// Processes of disowned jobs, still to be collected when they exit.
+    orphans: Vec<Pid>,
+}
+
+impl JobTable {
+    pub fn is_empty(&self) -> bool {
+        self.jobs.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.jobs.len()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Job> {
+        self.jobs.iter()
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut Job> {
+        self.jobs.iter_mut()
+    }
+
+    /// Adds `job`, numbering it if it has no number yet, and makes it the
+    /// current job.
+    pub fn insert(&mut self, mut job: Job) -> u32 {
+        if job.id == 0 {
+            job.id = self.jobs.iter().map(|j| j.id).max().unwrap_or(0) + 1;
+        }
+        let id = job.id;
+        let index = self.jobs.partition_point(|j| j.id < id);
+        self.jobs.insert(index, job);
+        self.make_current(id);
+        id
+    }
+
+    pub fn get(&self, id: u32) -> Option<&Job> {
+        self.jobs.iter().find(|j| j.id == id)
+    }
+
+    pub fn get_mut(&mut self, id: u32) -> Option<&mut Job> {
+        self.jobs.iter_mut().find(|j| j.id == id)
+    }
+
+    pub fn remove(&mut self, id: u32) -> Option<Job> {
+        self.recent.retain(|&r| r != id);
+        let index = self.jobs.iter().position(|j| j.id == id)?;
+        Some(self.jobs.remove(index))
+    }
+
+    /// Moves a finished job out of the table, keeping its status for `wait`.
+    pub fn retire(&mut self, id: u32) {
+        if let Some(job) = self.remove(id) {
+            self.finished.retain(|j| j.id != id);
+            if self.finished.len() == FINISHED_LIMIT {
+                self.finished.remove(0);
+            }
+            self.finished.push(job);
+        }
+    }
+
+    /// Takes a retired job by `%N` or `%string` spec.
+    pub fn take_finished(&mut self, spec: &str) -> Option<Job> {
+        let body = spec.strip_prefix('%')?;
+        let index = self.finished.iter().rposition(|j| match body.parse::<u32>() {
+            Ok(id) => j.id == id,
+            Err(_) => j.command.starts_with(body),
+        })?;
+        Some(self.finished.remove(index))
+    }
+
+    /// Takes a retired job by the pid of one of its processes.
+    pub fn take_finished_pid(&mut self, pid: Pid) -> Option<Job> {
+        let index = self.finished.iter().rposition(|j| j.processes.iter().any(|p| p.pid == pid))?;
+        Some(self.finished.remove(index))
+    }
+
+    /// Forgets a job; its processes are still collected when they exit.
+    pub fn disown(&mut self, id: u32) {
+        if let Some(job) = self.remove(id) {
+            self.orphans.extend(job.processes.iter().filter(|p| !p.state.is_finished()).map(|p| p.pid));
+        }
+    }
+
+    /// Collects status changes of every job's processes without blocking.
+    pub fn reap(&mut self) {
+        let flags = WaitPidFlag::WNOHANG | WaitPidFlag::WUNTRACED | WaitPidFlag::WCONTINUED;
+        for job in self.jobs.iter_mut() {
+            let mut changed = false;
+            for process in job.processes.iter_mut().filter(|p| !p.state.is_finished()) {
+                if let Some(state) = waitpid(process.pid, Some(flags)).ok().and_then(ProcessState::from_wait_status) {
+                    changed |= state != process.state;
+                    process.state = state;
+                }
+            }
+            if changed && (job.is_done() || job.is_stopped()) {
+                job.changed = true;
+            }
+        }
+        self.orphans
+            .retain(|&pid| matches!(waitpid(pid, Some(WaitPidFlag::WNOHANG)), Ok(WaitStatus::StillAlive)));
+    }
+
+    /// A job's line in the `jobs` listing: `[1]+  Running  sleep 10 &`.
+    pub fn describe(&self, id: u32) -> String {
+        match self.get(id) {
+            Some(job) => format!("[{}]{}  {:<24}{}", id, self.marker(id), job.state_text(), job.command),
+            None => String::new(),
+        }
+    }
+
+    pub fn make_current(&mut self, id: u32) {
+        self.recent.retain(|&r| r != id);
+        self.recent.push(id);
+    }
+
+    pub fn current(&self) -> Option<u32> {
+        self.recent.last().copied()
+    }
+
+    pub fn previous(&self) -> Option<u32> {
+        self.recent.iter().rev().nth(1).copied()
+    }
+
+    /// `+` for the current job, `-` for the previous one.
+    pub fn marker(&self, id: u32) -> char {
+        if self.current() == Some(id) {
+            '+'
+        } else if self.previous() == Some(id) {
+            '-'
+        } else {
+            ' '
+        }
+    }
+
+    pub fn find_pid(&self, pid: Pid) -> Option<u32> {
+        self.jobs
+            .iter()
+            .find(|j| j.processes.iter().any(|p| p.pid == pid))
+            .map(|j| j.id)
+    }
+
+    /// Resolves a job spec: `%N`, `%+`, `%%`, `%-`, `%string` (command
+    /// starts with string) or `%?string` (command contains string).
+    pub fn resolve(&self, spec: &str) -> Result<u32, String> {
+        let Some(body) = spec.strip_prefix('%') else {
+            return Err(format!("{}: no such job", spec));
+        };
+        let found = match body {
+            "" | "+" | "%" => self.current(),
+            "-" => self.previous(),
+            _ if body.bytes().all(|b| b.is_ascii_digit()) => {
+                body.parse().ok().filter(|&id| self.get(id).is_some())
+            }
+            _ => {
+                let matches: Vec<u32> = match body.strip_prefix('?') {
+                    Some(text) => self.jobs.iter().filter(|j| j.command.contains(text)).map(|j| j.id).collect(),
+                    None => self.jobs.iter().filter(|j| j.command.starts_with(body)).map(|j| j.id).collect(),
+                };
+                if matches.len() > 1 {
+                    return Err(format!("{}: ambiguous job spec", spec));
+                }
+                matches.first().copied()
+            }
+        };
+        found.ok_or_else(|| format!("{}: no such job", spec))
+    }
+}
+
+/// The controlling terminal of an interactive shell doing job control.
+#[derive(Debug, Clone)]
+pub struct Terminal {
+    /// Private copy of the terminal descriptor, unaffected by redirections.
+    pub fd: RawFd,
+    pub shell_pgid: Pid,
+    /// Terminal modes to put back when a job returns control to the shell.
+    modes: Option<Termios>,
+}
+
+impl Terminal {
+    /// Puts the shell in its own process group in the foreground of the
+    /// terminal on stdin.  Returns `None` when stdin is not a terminal.
+    pub fn acquire() -> Option<Terminal> {
+        if !nix::unistd::isatty(0).unwrap_or(false) {
+            return None;
+        }
+        let fd = nix::fcntl::fcntl(0, nix::fcntl::FcntlArg::F_DUPFD_CLOEXEC(10)).ok()?;
+
+        // Started in the background: wait until we are brought to the foreground.
+        loop {
+            let pgrp = getpgrp();
+            match tcgetpgrp(fd) {
+                Ok(foreground) if foreground == pgrp => break,
+                Ok(_) => {
+                    let _ = killpg(pgrp, Signal::SIGTTIN);
+                }
+                Err(_) => return None,
+            }
+        }
+
+        for sig in [Signal::SIGTSTP, Signal::SIGTTIN, Signal::SIGTTOU] {
+            unsafe {
+                let _ = signal::signal(sig, SigHandler::SigIgn);
+            }
+        }
+        // Fails harmlessly if the shell already leads its group or session.
+        let _ = setpgid(Pid::from_raw(0), Pid::from_raw(0));
+        let shell_pgid = getpgrp();
+        let _ = tcsetpgrp(fd, shell_pgid);
+        let modes = tcgetattr(unsafe { BorrowedFd::borrow_raw(fd) }).ok();
+        Some(Terminal { fd, shell_pgid, modes })
+    }
+
+    pub fn give_to(&self, pgid: Pid) {
+        let _ = tcsetpgrp(self.fd, pgid);
+    }
+
+    /// Takes the terminal back from a job and restores the shell's modes.
+    pub fn reclaim(&self) {
+        let _ = tcsetpgrp(self.fd, self.shell_pgid);
+        if let Some(modes) = &self.modes {
+            let _ = tcsetattr(unsafe { BorrowedFd::borrow_raw(self.fd) }, SetArg::TCSADRAIN, modes);
+        }
+    }
+}
+
+/// Process group arrangements for the processes of a job being started.
+#[derive(Debug, Clone, Copy)]
+pub struct JobSetup {
+    /// Whether the job gets its own process group.
+    pub monitor: bool,
+    pub foreground: bool,
+    /// Group of the job, known once its first process has started.
+    pub pgid: Option<Pid>,
+    pub terminal: Option<RawFd>,
+}
+
+impl JobSetup {
+    /// Arranges for a spawned program to join the job.
+    pub fn configure(&self, command: &mut std::process::Command) {
+        let setup = *self;
+        unsafe {
+            command.pre_exec(move || {
+                setup.enter_child();
+                Ok(())
+            });
+        }
+    }
+
+    /// Runs first thing in a new child process: joins the job's process
+    /// group, takes the terminal if the job is in the foreground and puts
+    /// back the signal dispositions the shell changed for itself, keeping
+    /// those ignored with `trap ''`.
+    pub fn enter_child(&self) {
+        if self.monitor {
+            let _ = setpgid(Pid::from_raw(0), self.pgid.unwrap_or(Pid::from_raw(0)));
+            if let (true, Some(fd)) = (self.foreground, self.terminal) {
+                let _ = tcsetpgrp(fd, getpgrp());
+            }
+        }
+        for sig in [
+            Signal::SIGINT,
+            Signal::SIGQUIT,
+            Signal::SIGTSTP,
+            Signal::SIGTTIN,
+            Signal::SIGTTOU,
+            Signal::SIGPIPE,
+        ] {
+            if signals::is_ignored(sig) {
+                continue;
+            }
+            unsafe {
+                let _ = signal::signal(sig, SigHandler::SigDfl);
+            }
+        }
+    }
+
+    /// Records a started process in the parent.  The first process leads
+    /// the group; setting it from both sides avoids racing the child.
+    pub fn started(&mut self, pid: Pid) {
+        if self.monitor {
+            let pgid = *self.pgid.get_or_insert(pid);
+            let _ = setpgid(pid, pgid);
+        }
+    }
+}
+
+impl Shell {
+    /// Starts the task that collects children as SIGCHLD reports them, so
+    /// background jobs do not linger as zombies.
+    pub(crate) fn start_reaper(&self) -> std::io::Result<()> {
+        let mut signals = Signals::new([SIGCHLD])?;
+        let jobs = self.jobs.clone();
+        tokio::spawn(async move {
+            while signals.next().await.is_some() {
+                jobs.write().await.reap();
+            }
+        });
+        Ok(())
+    }
+
+    /// Tells the user, bash style, about jobs that stopped or finished
+    /// since the last prompt, and retires the finished ones.
+    pub(crate) async fn notify_jobs(&self) {
+        if !self.options.read().await.monitor {
+            return;
+        }
+        let mut jobs = self.jobs.write().await;
+        jobs.reap();
+        let changed: Vec<u32> = jobs.iter().filter(|j| j.changed).map(|j| j.id).collect();
+        for id in changed {
+            eprintln!("{}", jobs.describe(id));
+            let job = jobs.get_mut(id).unwrap();
+            job.changed = false;
+            if job.is_done() {
+                jobs.retire(id);
+            }
+        }
+    }
+
+    /// Process group arrangements for a new job.
+    pub(crate) async fn job_setup(&self, foreground: bool) -> JobSetup {
+        let terminal = self.terminal.as_ref().map(|t| t.fd);
+        JobSetup {
+            monitor: self.options.read().await.monitor && terminal.is_some(),
+            foreground,
+            pgid: None,
+            terminal,
+        }
+    }
+
+    /// Enters a started job in the job table as a background job.
+    pub(crate) async fn add_background_job(&mut self, mut job: Job, setup: &JobSetup) -> u32 {
+        job.pgid = if setup.monitor { setup.pgid } else { None };
+        let leader = job.leader();
+        *self.last_background.write().await = job.processes.last().map(|process| process.pid);
+        let id = self.jobs.write().await.insert(job);
+        if setup.monitor {
+            if let Some(pid) = leader {
+                println!("[{}] {}", id, pid);
+            }
+        }
+        id
+    }
+
+    /// Waits for a foreground job to finish or stop, giving it the terminal
+    /// meanwhile.  A stopped job goes (back) into the job table.
+    pub(crate) async fn wait_for_foreground(&mut self, job: Job, setup: &JobSetup) -> Result<i32, ShellError> {
+        let statuses = self.wait_for_foreground_statuses(job, setup).await?;
+        Ok(statuses.last().copied().unwrap_or(0))
+    }
+
+    /// `wait_for_foreground` returning the status of every process of the
+    /// job, for `PIPESTATUS`.
+    pub(crate) async fn wait_for_foreground_statuses(&mut self, mut job: Job, setup: &JobSetup) -> Result<Vec<i32>, ShellError> {
+        job.pgid = if setup.monitor { setup.pgid } else { None };
+        let terminal = self.terminal.clone().filter(|_| setup.monitor);
+        if let (Some(terminal), Some(pgid)) = (&terminal, job.pgid) {
+            terminal.give_to(pgid);
+        }
+        wait_until_stopped_or_done(&mut job);
+        if let Some(terminal) = &terminal {
+            terminal.reclaim();
+        }
+
+        let statuses = job.statuses();
+        // A job killed by Ctrl-C interrupts whatever the shell was running,
+        // unless SIGINT is trapped: the trap runs instead
+        let interrupted = job.processes.last().is_some_and(|p| p.state == ProcessState::Signaled(Signal::SIGINT));
+        if job.is_done() && interrupted && !self.is_trapped(Signal::SIGINT).await {
+            return Err(ShellError::Interrupted);
+        }
+        if job.is_stopped() {
+            let mut jobs = self.jobs.write().await;
+            let id = jobs.insert(job);
+            eprintln!("\n{}", jobs.describe(id));
+        }
+        Ok(statuses)
+    }
+
+    /// Polls every job for status changes without blocking.
+    pub(crate) async fn update_jobs(&self) {
+        self.jobs.write().await.reap();
+    }
+
+    /// Resolves job arguments, defaulting to the current job.
+    async fn resolve_jobs(&self, name: &str, args: &[String], io: &mut Streams) -> Result<Option<Vec<u32>>, ShellError> {
+        let jobs = self.jobs.read().await;
+        if args.is_empty() {
+            return match jobs.current() {
+                Some(id) => Ok(Some(vec![id])),
+                None => {
+                    writeln!(io.stderr, "{}: current: no such job", name)?;
+                    Ok(None)
+                }
+            };
+        }
+        let mut ids = Vec::new();
+        for spec in args {
+            match jobs.resolve(spec) {
+                Ok(id) => ids.push(id),
+                Err(e) => {
+                    writeln!(io.stderr, "{}: {}", name, e)?;
+                    return Ok(None);
+                }
+            }
+        }
+        Ok(Some(ids))
+    }
+
+    pub(crate) async fn builtin_jobs(&self, args: &[String], io: &mut Streams) -> Result<i32, ShellError> {
+        let mut long = false;
+        let mut pids_only = false;
+        let mut specs = Vec::new();
+        for arg in args {
+            match arg.as_str() {
+                "-l" => long = true,
+                "-p" => pids_only = true,
+                _ if arg.starts_with('-') && arg.len() > 1 => {
+                    writeln!(io.stderr, "jobs: {}: invalid option", arg)?;
+                    return Ok(2);
+                }
+                _ => specs.push(arg.clone()),
+            }
+        }
+
+        self.update_jobs().await;
+        let ids = if specs.is_empty() {
+            self.jobs.read().await.iter().map(|j| j.id).collect()
+        } else {
+            match self.resolve_jobs("jobs", &specs, io).await? {
+                Some(ids) => ids,
+                None => return Ok(1),
+            }
+        };
+
+        let mut jobs = self.jobs.write().await;
+        for id in ids {
+            let job = jobs.get(id).unwrap();
+            if pids_only {
+                writeln!(io.stdout, "{}", job.processes[0].pid)?;
+            } else if long {
+                writeln!(io.stdout, "[{}]{} {} {:<24}{}", id, jobs.marker(id), job.processes[0].pid, job.state_text(), job.command)?;
+            } else {
+                writeln!(io.stdout, "{}", jobs.describe(id))?;
+            }
+            let job = jobs.get_mut(id).unwrap();
+            job.changed = false;
+            // A finished job is reported once and then retired
+            if job.is_done() {
+                jobs.retire(id);
+            }
+        }
+        Ok(0)
+    }
+
+    pub(crate) async fn builtin_fg(&mut self, args: &[String], io: &mut Streams) -> Result<i32, ShellError> {
+        self.update_jobs().await;
+        let Some(ids) = self.resolve_jobs("fg", args, io).await? else {
+            return Ok(1);
+        };
+        let Some(mut job) = self.jobs.write().await.remove(ids[0]) else {
+            return Ok(1);
+        };
+        writeln!(io.stdout, "{}", job.command)?;
+        if job.is_done() {
+            return Ok(job.status());
+        }
+
+        let mut setup = self.job_setup(true).await;
+        setup.pgid = job.pgid;
+        if let (Some(terminal), Some(pgid)) = (&self.terminal, job.pgid) {
+            terminal.give_to(pgid);
+        }
+        if let Err(errno) = job.resume() {
+            writeln!(io.stderr, "fg: {}: {}", job.command, errno.desc())?;
+        }
+        self.wait_for_foreground(job, &setup).await
+    }
+
+    pub(crate) async fn builtin_bg(&mut self, args: &[String], io: &mut Streams) -> Result<i32, ShellError> {
+        self.update_jobs().await;
+        let Some(ids) = self.resolve_jobs("bg", args, io).await? else {
+            return Ok(1);
+        };
+        let mut jobs = self.jobs.write().await;
+        let mut exit_code = 0;
+        for id in ids {
+            let job = jobs.get_mut(id).unwrap();
+            if job.is_done() {
+                writeln!(io.stderr, "bg: job has terminated")?;
+                exit_code = 1;
+                continue;
+            }
+            if !job.is_stopped() {
+                writeln!(io.stderr, "bg: job {} already in background", id)?;
+                continue;
+            }
+            if let Err(errno) = job.resume() {
+                writeln!(io.stderr, "bg: {}: {}", job.command, errno.desc())?;
+                exit_code = 1;
+                continue;
+            }
+            let command = job.command.clone();
+            jobs.make_current(id);
+            writeln!(io.stdout, "[{}]{} {} &", id, jobs.marker(id), command)?;
+        }
+        Ok(exit_code)
+    }
+
+    pub(crate) async fn builtin_wait(&mut self, args: &[String], io: &mut Streams) -> Result<i32, ShellError> {
+        if args.is_empty() {
+            let ids: Vec<u32> = self.jobs.read().await.iter().map(|j| j.id).collect();
+            for id in ids {
+                self.wait_for_job(id).await;
+            }
+            return Ok(0);
+        }
+
+        let mut exit_code = 0;
+        for arg in args {
+            let found = {
+                let mut jobs = self.jobs.write().await;
+                if arg.starts_with('%') {
+                    match jobs.resolve(arg) {
+                        Ok(id) => Ok(Ok(id)),
+                        Err(e) => jobs.take_finished(arg).map(Err).ok_or(e),
+                    }
+                } else {
+                    match arg.parse::<i32>().map(Pid::from_raw) {
+                        Ok(pid) => match jobs.find_pid(pid) {
+                            Some(id) => Ok(Ok(id)),
+                            None => jobs
+                                .take_finished_pid(pid)
+                                .map(Err)
+                                .ok_or_else(|| format!("pid {} is not a child of this shell", pid)),
+                        },
+                        Err(_) => Err(format!("`{}': not a pid or valid job spec", arg)),
+                    }
+                }
+            };
+            exit_code = match found {
+                Ok(Ok(id)) => self.wait_for_job(id).await,
+                // Finished and already reported
+                Ok(Err(job)) => job.status(),
+                Err(e) => {
+                    writeln!(io.stderr, "wait: {}", e)?;
+                    127
+                }
+            };
+        }
+        Ok(exit_code)
+    }
+
+    /// Blocks until job `id` finishes, removes it and returns its status.
+    async fn wait_for_job(&mut self, id: u32) -> i32 {
+        let Some(mut job) = self.jobs.write().await.remove(id) else {
+            return 127;
+        };
+        wait_until_done(&mut job);
+        job.status()
+    }
+
+    pub(crate) async fn builtin_kill(&mut self, args: &[String], io: &mut Streams) -> Result<i32, ShellError> {
+        let mut sig = Signal::SIGTERM;
+        let mut rest = args;
+        match args.first().map(|a| a.as_str()) {
+            None => {
+                writeln!(io.stderr, "kill: usage: kill [-s sigspec | -n signum | -sigspec] pid | jobspec ... or kill -l [sigspec]")?;
+                return Ok(2);
+            }
+            Some("-l") | Some("-L") => {
+                if let Some(arg) = args.get(1) {
+                    // `kill -l 130` names the signal behind an exit status
+                    let number = arg.parse::<i32>().map(|n| if n > 128 { n - 128 } else { n });
+                    match number.ok().and_then(|n| Signal::try_from(n).ok()).or_else(|| parse_signal(arg)) {
+                        Some(found) if arg.parse::<i32>().is_ok() => writeln!(io.stdout, "{}", &found.as_str()[3..])?,
+                        Some(found) => writeln!(io.stdout, "{}", found as i32)?,
+                        None => {
+                            writeln!(io.stderr, "kill: {}: invalid signal specification", arg)?;
+                            return Ok(1);
+                        }
+                    }
+                    return Ok(0);
+                }
+                for (i, found) in Signal::iterator().enumerate() {
+                    let separator = if i % 5 == 4 { "\n" } else { "\t" };
+                    write!(io.stdout, "{:2}) {:<8}{}", found as i32, found.as_str(), separator)?;
+                }
+                writeln!(io.stdout)?;
+                return Ok(0);
+            }
+            Some("-s") | Some("-n") => {
+                let Some(found) = args.get(1).and_then(|name| parse_signal(name)) else {
+                    writeln!(io.stderr, "kill: {}: invalid signal specification", args.get(1).map_or("", |a| a.as_str()))?;
+                    return Ok(1);
+                };
+                sig = found;
+                rest = &args[2..];
+            }
+            Some(arg) if arg.starts_with('-') && arg.len() > 1 => {
+                let Some(found) = parse_signal(&arg[1..]) else {
+                    writeln!(io.stderr, "kill: {}: invalid signal specification", &arg[1..])?;
+                    return Ok(1);
+                };
+                sig = found;
+                rest = &args[1..];
+            }
+            Some(_) => {}
+        }
+
+        let mut exit_code = 0;
+        for target in rest {
+            let result = if target.starts_with('%') {
+                let mut jobs = self.jobs.write().await;
+                match jobs.resolve(target) {
+                    Ok(id) => {
+                        let job = jobs.get_mut(id).unwrap();
+                        let result = job.signal(sig);
+                        // A stopped job has to run to act on the signal
+                        if job.is_stopped() && matches!(sig, Signal::SIGTERM | Signal::SIGHUP) {
+                            let _ = job.resume();
+                        }
+                        result.map_err(|errno| format!("{}: {}", target, errno.desc()))
+                    }
+                    Err(e) => Err(e),
+                }
+            } else {
+                match target.parse::<i32>() {
+                    Ok(pid) => signal::kill(Pid::from_raw(pid), sig).map_err(|errno| format!("({}) - {}", pid, errno.desc())),
+                    Err(_) => Err(format!("{}: arguments must be process or job IDs", target)),
+                }
+            };
+            if let Err(e) = result {
+                writeln!(io.stderr, "kill: {}", e)?;
+                exit_code = 1;
+            }
+        }
+        Ok(exit_code)
+    }
+
+    pub(crate) async fn builtin_disown(&mut self, args: &[String], io: &mut Streams) -> Result<i32, ShellError> {
+        let mut all = false;
+        let mut running_only = false;
+        let mut specs = Vec::new();
+        for arg in args {
+            match arg.as_str() {
+                "-a" => all = true,
+                "-r" => running_only = true,
+                _ if arg.starts_with('-') && arg.len() > 1 => {
+                    writeln!(io.stderr, "disown: {}: invalid option", arg)?;
+                    return Ok(2);
+                }
+                _ => specs.push(arg.clone()),
+            }
+        }
+
+        self.update_jobs().await;
+        let ids = if all || running_only && specs.is_empty() {
+            self.jobs.read().await.iter().map(|j| j.id).collect()
+        } else {
+            match self.resolve_jobs("disown", &specs, io).await? {
+                Some(ids) => ids,
+                None => return Ok(1),
+            }
+        };
+        let mut jobs = self.jobs.write().await;
+        for id in ids {
+            let running = jobs.get(id).is_some_and(|j| !j.is_stopped() && !j.is_done());
+            if !running_only || running {
+                jobs.disown(id);
+            }
+        }
+        Ok(0)
+    }
+}
+
+/// Blocks until every process of `job` has finished.
+pub(crate) fn wait_until_done(job: &mut Job) {
+    for process in job.processes.iter_mut() {
+        while !process.state.is_finished() {
+            match waitpid(process.pid, None) {
+                Ok(status) => {
+                    if let Some(state) = ProcessState::from_wait_status(status) {
+                        process.state = state;
+                    }
+                }
+                Err(Errno::EINTR) => continue,
+                Err(_) => process.state = ProcessState::Exited(127),
+            }
+        }
+    }
+}
+
+/// Blocks until every process of `job` has finished or one of them stops.
+fn wait_until_stopped_or_done(job: &mut Job) {
+    for i in 0..job.processes.len() {
+        while job.processes[i].state == ProcessState::Running {
+            match waitpid(job.processes[i].pid, Some(WaitPidFlag::WUNTRACED)) {
+                Ok(status) => {
+                    if let Some(state) = ProcessState::from_wait_status(status) {
+                        job.processes[i].state = state;
+                    }
+                }
+                Err(Errno::EINTR) => continue,
+                Err(_) => job.processes[i].state = ProcessState::Exited(127),
+            }
+        }
+        if job.processes[i].state == ProcessState::Stopped {
+            // The rest of the group was stopped by the same signal; collect
+            // the reports that have already arrived.
+            for process in job.processes.iter_mut().filter(|p| p.state == ProcessState::Running) {
+                let flags = WaitPidFlag::WUNTRACED | WaitPidFlag::WNOHANG;
+                if let Some(state) = waitpid(process.pid, Some(flags)).ok().and_then(ProcessState::from_wait_status) {
+                    process.state = state;
+                }
+            }
+            return;
+        }
+    }
+}
+
@@ -0,0 +1,624 @@
+// Tokenizer for NexusShell command lines.
+//
+// Operators, blanks and comments are recognised with nom combinators; words
+// are scanned by hand because their extent depends on nested quoting and
+// `$(...)`, `${...}` and backquote constructs.  Words keep their raw source
+// text (quotes included) so that expansion can later tell quoted characters
+// from unquoted ones.
+
+use nom::branch::alt;
+use nom::bytes::complete::{tag, take_till, take_while};
+use nom::character::complete::{char, digit1, one_of};
+use nom::combinator::{map_res, peek, value};
+use nom::sequence::{preceded, terminated};
+use nom::IResult;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operator {
+    AndIf,
+    OrIf,
+    Semi,
+    DSemi,
+    SemiAnd,
+    DSemiAnd,
+    Amp,
+    Pipe,
+    PipeAmp,
+    LParen,
+    RParen,
+    Less,
+    Great,
+    DLess,
+    DLessDash,
+    TLess,
+    DGreat,
+    LessAnd,
+    GreatAnd,
+    LessGreat,
+    Clobber,
+    AndGreat,
+    AndDGreat,
+}
+
+impl Operator {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Operator::AndIf => "&&",
+            Operator::OrIf => "||",
+            Operator::Semi => ";",
+            Operator::DSemi => ";;",
+            Operator::SemiAnd => ";&",
+            Operator::DSemiAnd => ";;&",
+            Operator::Amp => "&",
+            Operator::Pipe => "|",
+            Operator::PipeAmp => "|&",
+            Operator::LParen => "(",
+            Operator::RParen => ")",
+            Operator::Less => "<",
+            Operator::Great => ">",
+            Operator::DLess => "<<",
+            Operator::DLessDash => "<<-",
+            Operator::TLess => "<<<",
+            Operator::DGreat => ">>",
+            Operator::LessAnd => "<&",
+            Operator::GreatAnd => ">&",
+            Operator::LessGreat => "<>",
+            Operator::Clobber => ">|",
+            Operator::AndGreat => "&>",
+            Operator::AndDGreat => "&>>",
+        }
+    }
+
+    pub fn is_redirection(&self) -> bool {
+        matches!(
+            self,
+            Operator::Less
+                | Operator::Great
+                | Operator::DLess
+                | Operator::DLessDash
+                | Operator::TLess
+                | Operator::DGreat
+                | Operator::LessAnd
+                | Operator::GreatAnd
+                | Operator::LessGreat
+                | Operator::Clobber
+                | Operator::AndGreat
+                | Operator::AndDGreat
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TokenKind {
+    Word(String),
+    IoNumber(i32),
+    Op(Operator),
+    Newline,
+}
+
+#[derive(Debug, Clone)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    /// Body of the here-document this word is the delimiter of.
+    pub here_doc: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LexError {
+    /// The input ended inside a construct that needs more text.
+    Incomplete(String),
+}
+
+impl std::fmt::Display for LexError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LexError::Incomplete(msg) => write!(f, "unexpected end of file while looking for {}", msg),
+        }
+    }
+}
+
+fn operator(input: &str) -> IResult<&str, Operator> {
+    alt((
+        alt((
+            value(Operator::DSemiAnd, tag(";;&")),
+            value(Operator::DSemi, tag(";;")),
+            value(Operator::SemiAnd, tag(";&")),
+            value(Operator::Semi, tag(";")),
+            value(Operator::AndIf, tag("&&")),
+            value(Operator::AndDGreat, tag("&>>")),
+            value(Operator::AndGreat, tag("&>")),
+            value(Operator::Amp, tag("&")),
+            value(Operator::OrIf, tag("||")),
+            value(Operator::PipeAmp, tag("|&")),
+            value(Operator::Pipe, tag("|")),
+        )),
+        alt((
+            value(Operator::TLess, tag("<<<")),
+            value(Operator::DLessDash, tag("<<-")),
+            value(Operator::DLess, tag("<<")),
+            value(Operator::LessAnd, tag("<&")),
+            value(Operator::LessGreat, tag("<>")),
+            value(Operator::Less, tag("<")),
+            value(Operator::DGreat, tag(">>")),
+            value(Operator::GreatAnd, tag(">&")),
+            value(Operator::Clobber, tag(">|")),
+            value(Operator::Great, tag(">")),
+            value(Operator::LParen, tag("(")),
+            value(Operator::RParen, tag(")")),
+        )),
+    ))(input)
+}
+
+/// A file descriptor number directly followed by a redirection operator, as in `2>`.
+fn io_number(input: &str) -> IResult<&str, i32> {
+    terminated(map_res(digit1, str::parse::<i32>), peek(one_of("<>")))(input)
+}
+
+fn blanks(input: &str) -> IResult<&str, &str> {
+    take_while(|c| c == ' ' || c == '\t')(input)
+}
+
+fn comment(input: &str) -> IResult<&str, &str> {
+    preceded(char('#'), take_till(|c| c == '\n'))(input)
+}
+
+pub fn is_metachar(b: u8) -> bool {
+    matches!(b, b' ' | b'\t' | b'\n' | b';' | b'&' | b'|' | b'<' | b'>' | b'(' | b')')
+}
+
+/// Returns the index just past the closing quote of a single-quoted string starting at `i`.
+pub fn scan_single_quote(s: &str, i: usize) -> Result<usize, LexError> {
+    match s[i + 1..].find('\'') {
+        Some(off) => Ok(i + 1 + off + 1),
+        None => Err(LexError::Incomplete("matching `''".to_string())),
+    }
+}
+
+/// Returns the index just past the closing quote of a `$'...'` string starting at `i`.
+pub fn scan_ansi_c_quote(s: &str, i: usize) -> Result<usize, LexError> {
+    let bytes = s.as_bytes();
+    let mut j = i + 2;
+    while j < bytes.len() {
+        match bytes[j] {
+            b'\\' => j += 2,
+            b'\'' => return Ok(j + 1),
+            _ => j += 1,
+        }
+    }
+    Err(LexError::Incomplete("matching `''".to_string()))
+}
+
+/// Returns the index just past the closing quote of a double-quoted string starting at `i`.
+pub fn scan_double_quote(s: &str, i: usize) -> Result<usize, LexError> {
+    let bytes = s.as_bytes();
+    let mut j = i + 1;
+    while j < bytes.len() {
+        match bytes[j] {
+            b'\\' => j += 2,
+            b'"' => return Ok(j + 1),
+            // `$"` has no special meaning inside double quotes
+            b'$' if bytes.get(j + 1) != Some(&b'"') => j = scan_dollar(s, j)?,
+            b'`' => j = scan_backquote(s, j)?,
+            _ => j += 1,
+        }
+    }
+    Err(LexError::Incomplete("matching `\"'".to_string()))
+}
+
+/// Returns the index just past the closing backquote of a command substitution starting at `i`.
+pub fn scan_backquote(s: &str, i: usize) -> Result<usize, LexError> {
+    let bytes = s.as_bytes();
+    let mut j = i + 1;
+    while j < bytes.len() {
+        match bytes[j] {
+            b'\\' => j += 2,
+            b'`' => return Ok(j + 1),
+            _ => j += 1,
+        }
+    }
+    Err(LexError::Incomplete("matching ``'".to_string()))
+}
+
+/// Scans a `$` construct starting at `i` and returns the index just past it.
+/// A `$` that does not introduce an expansion covers just itself.
+pub fn scan_dollar(s: &str, i: usize) -> Result<usize, LexError> {
+    let rest = &s[i..];
+    if rest.starts_with("$$") {
+        Ok(i + 2)
+    } else if rest.starts_with("$((") {
+        scan_arithmetic(s, i + 1)
+    } else if rest.starts_with("$(") {
+        scan_command_substitution(s, i + 1)
+    } else if rest.starts_with("${") {
+        scan_parameter(s, i + 1)
+    } else if rest.starts_with("$'") {
+        scan_ansi_c_quote(s, i)
+    } else if rest.starts_with("$\"") {
+        scan_double_quote(s, i + 1)
+    } else {
+        Ok(i + 1)
+    }
+}
+
+/// Scans `${...}` where `i` points at the opening brace.
+pub fn scan_parameter(s: &str, i: usize) -> Result<usize, LexError> {
+    let bytes = s.as_bytes();
+    let mut j = i + 1;
+    while j < bytes.len() {
+        match bytes[j] {
+            b'\\' => j += 2,
+            b'}' => return Ok(j + 1),
+            b'\'' => j = scan_single_quote(s, j)?,
+            b'"' => j = scan_double_quote(s, j)?,
+            b'`' => j = scan_backquote(s, j)?,
+            b'$' => j = scan_dollar(s, j)?,
+            _ => j += 1,
+        }
+    }
+    Err(LexError::Incomplete("matching `}'".to_string()))
+}
+
+/// Scans `((...))` where `i` points at the first opening parenthesis.
+pub fn scan_arithmetic(s: &str, i: usize) -> Result<usize, LexError> {
+    let bytes = s.as_bytes();
+    let mut depth = 0usize;
+    let mut j = i;
+    while j < bytes.len() {
+        match bytes[j] {
+            b'(' => depth += 1,
+            b')' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Ok(j + 1);
+                }
+            }
+            b'\\' => j += 1,
+            b'\'' => {
+                j = scan_single_quote(s, j)?;
+                continue;
+            }
+            b'"' => {
+                j = scan_double_quote(s, j)?;
+                continue;
+            }
+            b'`' => {
+                j = scan_backquote(s, j)?;
+                continue;
+            }
+            b'$' if bytes.get(j + 1).is_some_and(|b| matches!(b, b'(' | b'{')) => {
+                j = scan_dollar(s, j)?;
+                continue;
+            }
+            _ => {}
+        }
+        j += 1;
+    }
+    Err(LexError::Incomplete("matching `))'".to_string()))
+}
+
+/// The end of an `(( expression ))` command starting at `i`, which is
+/// lexed as a single word.  Parentheses that do not close with `))`, as in
+/// `((a); b)`, are left to be read as operators.
+fn arithmetic_command(s: &str, i: usize) -> Result<Option<usize>, LexError> {
+    if !s[i..].starts_with("((") {
+        return Ok(None);
+    }
+    let end = scan_arithmetic(s, i)?;
+    Ok(s[..end].ends_with("))").then_some(end))
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum CaseState {
+    AwaitIn,
+    Pattern,
+    Body,
+}
+
+/// Scans `(...)` where `i` points at the opening parenthesis of a command
+/// substitution.  The contents are tokenized so that quotes, comments and
+/// `case` patterns do not confuse the search for the closing parenthesis.
+pub fn scan_command_substitution(s: &str, i: usize) -> Result<usize, LexError> {
+    let mut lexer = Lexer::at(s, i + 1);
+    let mut depth = 0usize;
+    let mut cases: Vec<CaseState> = Vec::new();
+    let mut prev: Option<TokenKind> = None;
+    loop {
+        let token = match lexer.next_token()? {
+            Some(token) => token,
+            None => return Err(LexError::Incomplete("matching `)'".to_string())),
+        };
+        let command_start = matches!(prev, None | Some(TokenKind::Newline) | Some(TokenKind::Op(_)));
+        match &token.kind {
+            TokenKind::Word(w) => match (w.as_str(), cases.last().copied()) {
+                ("case", _) if command_start => cases.push(CaseState::AwaitIn),
+                ("in", Some(CaseState::AwaitIn)) => {
+                    *cases.last_mut().unwrap() = CaseState::Pattern;
+                }
+                ("esac", Some(CaseState::Pattern)) => {
+                    cases.pop();
+                }
+                ("esac", Some(CaseState::Body)) if command_start => {
+                    cases.pop();
+                }
+                _ => {}
+            },
+            TokenKind::Op(Operator::LParen) if cases.last() != Some(&CaseState::Pattern) => depth += 1,
+            TokenKind::Op(Operator::RParen) => {
+                if cases.last() == Some(&CaseState::Pattern) {
+                    *cases.last_mut().unwrap() = CaseState::Body;
+                } else if depth == 0 {
+                    return Ok(token.end);
+                } else {
+                    depth -= 1;
+                }
+            }
+            TokenKind::Op(Operator::DSemi | Operator::SemiAnd | Operator::DSemiAnd)
+                if cases.last() == Some(&CaseState::Body) =>
+            {
+                *cases.last_mut().unwrap() = CaseState::Pattern;
+            }
+            _ => {}
+        }
+        prev = Some(token.kind);
+    }
+}
+
+/// Scans a word starting at `i` and returns the index just past it.
+pub fn scan_word(s: &str, i: usize) -> Result<usize, LexError> {
+    let bytes = s.as_bytes();
+    let mut j = i;
+    while j < bytes.len() {
+        let b = bytes[j];
+        if matches!(b, b'<' | b'>') && bytes.get(j + 1) == Some(&b'(') {
+            j = scan_command_substitution(s, j + 1)?;
+            continue;
+        }
+        // The element list of an array assignment `name=(...)`
+        if b == b'(' && is_array_assignment_prefix(&s[i..j]) {
+            j = scan_command_substitution(s, j)?;
+            continue;
+        }
+        if is_metachar(b) {
+            break;
+        }
+        j = match b {
+            b'\\' => {
+                if j + 1 >= bytes.len() {
+                    return Err(LexError::Incomplete("escaped character".to_string()));
+                }
+                // Step over the whole escaped character, which may be multi-byte.
+                j + 1 + s[j + 1..].chars().next().map_or(1, char::len_utf8)
+            }
+            b'\'' => scan_single_quote(s, j)?,
+            b'"' => scan_double_quote(s, j)?,
+            b'`' => scan_backquote(s, j)?,
+            b'$' => scan_dollar(s, j)?,
+            b'?' | b'*' | b'+' | b'@' | b'!' if bytes.get(j + 1) == Some(&b'(') => scan_extglob(s, j + 1)?,
+            _ => j + 1,
+        };
+    }
+    Ok(j.min(bytes.len()))
+}
+
+/// Whether `word` is `name=` or `name+=`, which an array's elements follow.
+fn is_array_assignment_prefix(word: &str) -> bool {
+    let Some(name) = word.strip_suffix('=') else {
+        return false;
+    };
+    let name = name.strip_suffix('+').unwrap_or(name);
+    name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Scans the group of an extglob pattern such as `@(a|b)`, where `i` points
+/// at the opening parenthesis, so that it stays part of the word.
+pub fn scan_extglob(s: &str, i: usize) -> Result<usize, LexError> {
+    let bytes = s.as_bytes();
+    let mut depth = 0usize;
+    let mut j = i;
+    while j < bytes.len() {
+        match bytes[j] {
+            b'(' => {
+                depth += 1;
+                j += 1;
+            }
+            b')' => {
+                depth -= 1;
+                j += 1;
+                if depth == 0 {
+                    return Ok(j);
+                }
+            }
+            b'\\' => j += 2,
+            b'\'' => j = scan_single_quote(s, j)?,
+            b'"' => j = scan_double_quote(s, j)?,
+            b'`' => j = scan_backquote(s, j)?,
+            b'$' => j = scan_dollar(s, j)?,
+            _ => j += 1,
+        }
+    }
+    Err(LexError::Incomplete("matching `)'".to_string()))
+}
+
+/// Removes quoting from a here-document delimiter.  Returns the delimiter
+/// and whether any part of it was quoted, which disables expansion of the body.
+pub fn unquote_delimiter(raw: &str) -> (String, bool) {
+    let mut delimiter = String::with_capacity(raw.len());
+    let mut quoted = false;
+    let mut chars = raw.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' | '"' => quoted = true,
+            '\\' => {
+                quoted = true;
+                if let Some(next) = chars.next() {
+                    delimiter.push(next);
+                }
+            }
+            _ => delimiter.push(c),
+        }
+    }
+    (delimiter, quoted)
+}
+
+/// A here-document whose body starts after the next newline.
+struct PendingHereDoc {
+    delimiter: String,
+    strip_tabs: bool,
+}
+
+pub struct Lexer<'a> {
+    src: &'a str,
+    pos: usize,
+    line: usize,
+    /// Set after `<<` (false) or `<<-` (true) until the delimiter is read.
+    heredoc_op: Option<bool>,
+    pending: Vec<PendingHereDoc>,
+    /// Bodies read since the last call to `take_here_documents`.
+    bodies: Vec<String>,
+}
+
+impl<'a> Lexer<'a> {
+    pub fn new(src: &'a str) -> Self {
+        Lexer::at(src, 0)
+    }
+
+    fn at(src: &'a str, pos: usize) -> Self {
+        let line = 1 + src[..pos].matches('\n').count();
+        Lexer { src, pos, line, heredoc_op: None, pending: Vec::new(), bodies: Vec::new() }
+    }
+
+    /// Returns the here-document bodies read so far, in source order.
+    pub fn take_here_documents(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.bodies)
+    }
+
+    /// Reads the bodies of all pending here-documents, starting at the
+    /// beginning of the line after their operators.
+    fn read_here_documents(&mut self) -> Result<(), LexError> {
+        for heredoc in std::mem::take(&mut self.pending) {
+            let mut body = String::new();
+            loop {
+                let rest = &self.src[self.pos..];
+                if rest.is_empty() {
+                    return Err(LexError::Incomplete(format!("here-document delimiter `{}'", heredoc.delimiter)));
+                }
+                let (line, len) = match rest.find('\n') {
+                    Some(end) => (&rest[..end], end + 1),
+                    None => (rest, rest.len()),
+                };
+                self.pos += len;
+                self.line += 1;
+                let line = if heredoc.strip_tabs { line.trim_start_matches('\t') } else { line };
+                if line == heredoc.delimiter {
+                    break;
+                }
+                body.push_str(line);
+                body.push('\n');
+            }
+            self.bodies.push(body);
+        }
+        Ok(())
+    }
+
+    /// Skips blanks, line continuations and comments.
+    fn skip_separators(&mut self) {
+        loop {
+            let rest = &self.src[self.pos..];
+            let (after, _) = blanks(rest).unwrap_or((rest, ""));
+            self.pos += rest.len() - after.len();
+            let rest = &self.src[self.pos..];
+            if rest.starts_with("\\\n") {
+                self.pos += 2;
+                self.line += 1;
+                continue;
+            }
+            if let Ok((after, _)) = comment(rest) {
+                self.pos += rest.len() - after.len();
+            }
+            break;
+        }
+    }
+
+    pub fn next_token(&mut self) -> Result<Option<Token>, LexError> {
+        self.skip_separators();
+        let start = self.pos;
+        let line = self.line;
+        let rest = &self.src[start..];
+        if rest.is_empty() {
+            if let Some(heredoc) = self.pending.first() {
+                return Err(LexError::Incomplete(format!("here-document delimiter `{}'", heredoc.delimiter)));
+            }
+            return Ok(None);
+        }
+
+        let kind = if rest.starts_with('\n') {
+            self.pos += 1;
+            self.line += 1;
+            self.read_here_documents()?;
+            TokenKind::Newline
+        } else if let Some(end) = arithmetic_command(self.src, start)? {
+            self.line += self.src[start..end].matches('\n').count();
+            self.pos = end;
+            TokenKind::Word(self.src[start..end].to_string())
+        } else if rest.starts_with("<(") || rest.starts_with(">(") {
+            // Process substitution, read as (the start of) a word
+            let end = scan_word(self.src, start)?;
+            self.line += self.src[start..end].matches('\n').count();
+            self.pos = end;
+            TokenKind::Word(self.src[start..end].to_string())
+        } else if let Ok((after, fd)) = io_number(rest) {
+            self.pos += rest.len() - after.len();
+            TokenKind::IoNumber(fd)
+        } else if let Ok((after, op)) = operator(rest) {
+            self.pos += rest.len() - after.len();
+            TokenKind::Op(op)
+        } else {
+            let end = scan_word(self.src, start)?;
+            self.line += self.src[start..end].matches('\n').count();
+            self.pos = end;
+            TokenKind::Word(self.src[start..end].to_string())
+        };
+
+        match &kind {
+            TokenKind::Op(Operator::DLess) => self.heredoc_op = Some(false),
+            TokenKind::Op(Operator::DLessDash) => self.heredoc_op = Some(true),
+            TokenKind::Word(word) => {
+                if let Some(strip_tabs) = self.heredoc_op.take() {
+                    let (delimiter, _) = unquote_delimiter(word);
+                    self.pending.push(PendingHereDoc { delimiter, strip_tabs });
+                }
+            }
+            _ => self.heredoc_op = None,
+        }
+
+        // Token::end stops before any here-document bodies read after a newline.
+        let end = if kind == TokenKind::Newline { start + 1 } else { self.pos };
+        Ok(Some(Token { kind, start, end, line, here_doc: None }))
+    }
+}
+
+pub fn tokenize(input: &str) -> Result<Vec<Token>, LexError> {
+    let mut lexer = Lexer::new(input);
+    let mut tokens: Vec<Token> = Vec::new();
+    // Delimiter tokens still waiting for their bodies
+    let mut delimiters = Vec::new();
+    while let Some(token) = lexer.next_token()? {
+        let after_heredoc_op = matches!(
+            tokens.last().map(|t| &t.kind),
+            Some(TokenKind::Op(Operator::DLess | Operator::DLessDash))
+        );
+        if after_heredoc_op && matches!(token.kind, TokenKind::Word(_)) {
+            delimiters.push(tokens.len());
+        }
+        if token.kind == TokenKind::Newline {
+            for (index, body) in delimiters.drain(..).zip(lexer.take_here_documents()) {
+                tokens[index].here_doc = Some(body);
+            }
+        }
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
@@ -1,1760 +1,2473 @@
-use std::collections::HashMap;
-use std::env;
-use std::io;
-use std::path::{Path, PathBuf};
-use std::process::Command;
-use std::sync::Arc;
-use std::time::Instant;
-use tokio::sync::{RwLock, Mutex};
-use uuid;
-use rustyline::DefaultEditor;
-use rustyline::completion::{Completer as RustylineCompleter, FilenameCompleter, Pair};
-use rustyline::hint::{HistoryHinter, Hinter};
-use rustyline::highlight::Highlighter;
-use rustyline::validate::{Validator, ValidationResult, ValidationContext};
-use rustyline::Helper;
-use rustyline::config::Configurer;
-use regex::Regex;
-use whoami;
-
-// ANSI color codes for beautiful output
-const RESET: &str = "\x1b[0m";
-const BOLD: &str = "\x1b[1m";
-const DIM: &str = "\x1b[2m";
-const RED: &str = "\x1b[31m";
-const GREEN: &str = "\x1b[32m";
-const YELLOW: &str = "\x1b[33m";
-const BLUE: &str = "\x1b[34m";
-const MAGENTA: &str = "\x1b[35m";
-const CYAN: &str = "\x1b[36m";
-const WHITE: &str = "\x1b[37m";
-const BRIGHT_GREEN: &str = "\x1b[92m";
-const BRIGHT_BLUE: &str = "\x1b[94m";
-const BRIGHT_CYAN: &str = "\x1b[96m";
-const BRIGHT_YELLOW: &str = "\x1b[93m";
-const BRIGHT_MAGENTA: &str = "\x1b[95m";
-const BRIGHT_WHITE: &str = "\x1b[97m";
-
-// Shell parser structure (using regex-based parsing for now)
-
-#[derive(Debug, Clone)]
-pub struct Job {
-    pub id: u32,
-    pub command: String,
-    pub status: String,
-    pub pid: Option<u32>,
-}
-
-// Custom completion helper for NexusShell
-struct NexusHelper {
-    completer: FilenameCompleter,
-    hinter: HistoryHinter,
-}
-
-impl Default for NexusHelper {
-    fn default() -> Self {
-        NexusHelper {
-            completer: FilenameCompleter::new(),
-            hinter: HistoryHinter::new(),
-        }
-    }
-}
-
-impl Helper for NexusHelper {}
-
-impl RustylineCompleter for NexusHelper {
-    type Candidate = Pair;
-
-    fn complete(
-        &self,
-        line: &str,
-        pos: usize,
-        ctx: &rustyline::Context<'_>,
-    ) -> rustyline::Result<(usize, Vec<Pair>)> {
-        let mut candidates = Vec::new();
-        
-        // Built-in commands completion
-        let builtins = [
-            "cd", "pwd", "echo", "printf", "export", "env", "set", "unset", 
-            "declare", "local", "read", "test", "alias", "history", "jobs", 
-            "which", "type", "source", "help", "exit", "ls", "pushd", "popd", 
-            "dirs", "exec", "eval", "function", "return", "if", "then", "else", 
-            "elif", "fi", "for", "do", "done", "while", "until", "case", "esac", "stats"
-        ];
-        
-        let words: Vec<&str> = line.split_whitespace().collect();
-        let current_word = if line.ends_with(' ') { "" } else { words.last().map_or("", |v| *v) };
-        
-        // Complete built-in commands if it's the first word
-        if words.len() <= 1 && !line.ends_with(' ') {
-            for builtin in &builtins {
-                if builtin.starts_with(current_word) {
-                    candidates.push(Pair {
-                        display: builtin.to_string(),
-                        replacement: builtin.to_string(),
-                    });
-                }
-            }
-        }
-        
-        // Add file completion
-        let (start, file_candidates) = self.completer.complete(line, pos, ctx)?;
-        candidates.extend(file_candidates);
-        
-        Ok((start, candidates))
-    }
-}
-
-impl Highlighter for NexusHelper {
-    fn highlight<'l>(&self, line: &'l str, _pos: usize) -> std::borrow::Cow<'l, str> {
-        use std::borrow::Cow;
-        
-        let mut highlighted = String::new();
-        let words: Vec<&str> = line.split_whitespace().collect();
-        
-        if words.is_empty() {
-            return Cow::Borrowed(line);
-        }
-        
-        let builtins = [
-            "cd", "pwd", "echo", "printf", "export", "env", "set", "unset",
-            "declare", "local", "read", "test", "alias", "history", "jobs",
-            "which", "type", "source", "help", "exit", "ls", "if", "then",
-            "else", "elif", "fi", "for", "do", "done", "while", "until"
-        ];
-        
-        let mut current_pos = 0;
-        for (i, word) in words.iter().enumerate() {
-            // Find the position of this word in the original line
-            if let Some(word_start) = line[current_pos..].find(word) {
-                let actual_start = current_pos + word_start;
-                
-                // Add any whitespace before the word
-                highlighted.push_str(&line[current_pos..actual_start]);
-                
-                // Highlight the word based on its type
-                if i == 0 && builtins.contains(word) {
-                    // Built-in command - green
-                    highlighted.push_str(&format!("\x1b[32m{}\x1b[0m", word));
-                } else if word.starts_with('$') {
-                    // Variable - yellow
-                    highlighted.push_str(&format!("\x1b[33m{}\x1b[0m", word));
-                } else if word.starts_with('-') {
-                    // Option/flag - cyan
-                    highlighted.push_str(&format!("\x1b[36m{}\x1b[0m", word));
-                } else if word.contains('=') {
-                    // Assignment - magenta
-                    highlighted.push_str(&format!("\x1b[35m{}\x1b[0m", word));
-                } else {
-                    highlighted.push_str(word);
-                }
-                
-                current_pos = actual_start + word.len();
-            }
-        }
-        
-        // Add any remaining characters
-        highlighted.push_str(&line[current_pos..]);
-        
-        Cow::Owned(highlighted)
-    }
-    
-    fn highlight_char(&self, line: &str, pos: usize, forced: bool) -> bool {
-        pos < line.len()
-    }
-}
-
-impl Validator for NexusHelper {
-    fn validate(&self, ctx: &mut ValidationContext) -> rustyline::Result<ValidationResult> {
-        let input = ctx.input();
-        
-        // Check for unmatched quotes
-        let mut single_quote_open = false;
-        let mut double_quote_open = false;
-        let mut escape_next = false;
-        
-        for ch in input.chars() {
-            if escape_next {
-                escape_next = false;
-                continue;
-            }
-            
-            match ch {
-                '\\' => escape_next = true,
-                '\'' if !double_quote_open => single_quote_open = !single_quote_open,
-                '"' if !single_quote_open => double_quote_open = !double_quote_open,
-                _ => {}
-            }
-        }
-        
-        if single_quote_open || double_quote_open {
-            return Ok(ValidationResult::Incomplete);
-        }
-        
-        // Check for unmatched parentheses and brackets
-        let mut paren_count = 0;
-        let mut bracket_count = 0;
-        let mut brace_count = 0;
-        
-        for ch in input.chars() {
-            match ch {
-                '(' => paren_count += 1,
-                ')' => paren_count -= 1,
-                '[' => bracket_count += 1,
-                ']' => bracket_count -= 1,
-                '{' => brace_count += 1,
-                '}' => brace_count -= 1,
-                _ => {}
-            }
-        }
-        
-        if paren_count != 0 || bracket_count != 0 || brace_count != 0 {
-            return Ok(ValidationResult::Incomplete);
-        }
-        
-        // Check for incomplete control structures
-        let trimmed = input.trim();
-        if trimmed.starts_with("if ") && !trimmed.contains(" fi") {
-            return Ok(ValidationResult::Incomplete);
-        }
-        if trimmed.starts_with("for ") && !trimmed.contains(" done") {
-            return Ok(ValidationResult::Incomplete);
-        }
-        if trimmed.starts_with("while ") && !trimmed.contains(" done") {
-            return Ok(ValidationResult::Incomplete);
-        }
-        
-        Ok(ValidationResult::Valid(None))
-    }
-}
-
-impl Hinter for NexusHelper {
-    type Hint = String;
-
-    fn hint(&self, line: &str, pos: usize, ctx: &rustyline::Context<'_>) -> Option<String> {
-        self.hinter.hint(line, pos, ctx)
-    }
-}
-
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let mut shell = Shell::new().await?;
-    shell.run().await
-}
-
-#[derive(Debug)]
-pub struct Shell {
-    pub variables: Arc<RwLock<HashMap<String, String>>>,
-    pub current_dir: Arc<RwLock<PathBuf>>,
-    pub exit_code: Arc<RwLock<i32>>,
-    pub readline: Arc<Mutex<DefaultEditor>>,
-    pub startup_time: Instant,
-    pub session_id: String,
-    pub history: Arc<RwLock<Vec<String>>>,
-    pub aliases: Arc<RwLock<HashMap<String, String>>>,
-    pub jobs: Arc<RwLock<Vec<Job>>>,
-    pub functions: Arc<RwLock<HashMap<String, String>>>,
-    pub arrays: Arc<RwLock<HashMap<String, Vec<String>>>>,
-    pub command_count: Arc<RwLock<u64>>,
-    pub error_count: Arc<RwLock<u64>>,
-    pub last_command_time: Arc<RwLock<Instant>>,
-}
-
-#[derive(Debug)]
-pub enum ShellError {
-    SyntaxError(String),
-    CommandNotFound(String),
-    FileNotFound(String),
-    PermissionDenied(String),
-    InvalidArgument(String),
-    IoError(io::Error),
-    Interrupted,
-    Exit(i32),
-}
-
-impl std::fmt::Display for ShellError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            ShellError::SyntaxError(msg) => write!(f, "Syntax error: {}", msg),
-            ShellError::CommandNotFound(cmd) => write!(f, "{}: command not found", cmd),
-            ShellError::FileNotFound(file) => write!(f, "{}: No such file or directory", file),
-            ShellError::PermissionDenied(file) => write!(f, "{}: Permission denied", file),
-            ShellError::InvalidArgument(arg) => write!(f, "Invalid argument: {}", arg),
-            ShellError::IoError(err) => write!(f, "IO error: {}", err),
-            ShellError::Interrupted => write!(f, "Interrupted"),
-            ShellError::Exit(code) => write!(f, "Exit with code {}", code),
-        }
-    }
-}
-
-impl std::error::Error for ShellError {}
-
-impl From<io::Error> for ShellError {
-    fn from(err: io::Error) -> Self {
-        ShellError::IoError(err)
-    }
-}
-
-// Error conversion implementations
-
-impl Shell {
-    pub async fn new() -> Result<Self, ShellError> {
-        let mut variables = HashMap::new();
-        
-        // Initialize environment variables
-        for (key, value) in env::vars() {
-            variables.insert(key, value);
-        }
-        
-        // Set additional shell variables
-        variables.insert("SHELL".to_string(), env::current_exe()
-            .unwrap_or_else(|_| PathBuf::from("nexusshell"))
-            .display().to_string());
-        variables.insert("USER".to_string(), whoami::username());
-        variables.insert("HOME".to_string(), env::var("HOME").unwrap_or_else(|_| "/".to_string()));
-        variables.insert("HOSTNAME".to_string(), whoami::hostname());
-        variables.insert("PS1".to_string(), "nexus$ ".to_string());
-        
-        let mut readline = DefaultEditor::new().map_err(|e| ShellError::IoError(io::Error::new(io::ErrorKind::Other, e)))?;
-        
-        // Configure readline behavior  
-        readline.set_auto_add_history(true);
-        readline.set_history_ignore_space(true);
-        readline.set_completion_type(rustyline::CompletionType::List);
-        
-        // Enable history functionality
-        let history_file = env::var("HOME").unwrap_or_else(|_| ".".to_string()) + "/.nexusshell_history";
-        let _ = readline.load_history(&history_file);
-        
-        Ok(Shell {
-            variables: Arc::new(RwLock::new(variables)),
-            current_dir: Arc::new(RwLock::new(env::current_dir().unwrap_or_else(|_| PathBuf::from("/")))),
-            exit_code: Arc::new(RwLock::new(0)),
-            readline: Arc::new(Mutex::new(readline)),
-            startup_time: Instant::now(),
-            session_id: uuid::Uuid::new_v4().to_string(),
-            history: Arc::new(RwLock::new(Vec::new())),
-            aliases: Arc::new(RwLock::new(HashMap::new())),
-            jobs: Arc::new(RwLock::new(Vec::new())),
-            functions: Arc::new(RwLock::new(HashMap::new())),
-            arrays: Arc::new(RwLock::new(HashMap::new())),
-            command_count: Arc::new(RwLock::new(0)),
-            error_count: Arc::new(RwLock::new(0)),
-            last_command_time: Arc::new(RwLock::new(Instant::now())),
-        })
-    }
-
-    pub async fn run(&mut self) -> Result<(), Box<dyn std::error::Error>> {
-        self.display_welcome_banner().await;
-        
-        loop {
-            let prompt = self.generate_prompt().await?;
-            
-            let line = {
-                let mut readline = self.readline.lock().await;
-                readline.readline(&prompt)
-            };
-            
-            match line {
-                Ok(line) => {
-                    let input = line.trim();
-                    if input.is_empty() {
-                        continue;
-                    }
-
-                    // Add to rustyline history
-                    {
-                        let mut readline = self.readline.lock().await;
-                        readline.add_history_entry(input).ok();
-                    }
-
-                    // Add to internal history
-                    {
-                        let mut history = self.history.write().await;
-                        history.push(input.to_string());
-                    }
-
-                    // Expand aliases
-                    let expanded_input = self.expand_aliases(input).await;
-                    
-                    // Update statistics
-                    {
-                        let mut count = self.command_count.write().await;
-                        *count += 1;
-                        let mut last_time = self.last_command_time.write().await;
-                        *last_time = Instant::now();
-                    }
-                    
-                    let start_time = Instant::now();
-                    match self.execute_command(&expanded_input).await {
-                        Ok(exit_code) => {
-                            let duration = start_time.elapsed();
-                            if exit_code != 0 {
-                                let mut error_count = self.error_count.write().await;
-                                *error_count += 1;
-                                println!("{}[WARNING] Exit code: {} (took {:?}){}", YELLOW, exit_code, duration, RESET);
-                            } else if duration.as_millis() > 100 {
-                                println!("{}[INFO] Command completed in {:?}{}", DIM, duration, RESET);
-                            }
-                        }
-                        Err(e) => {
-                            let mut error_count = self.error_count.write().await;
-                            *error_count += 1;
-                            println!("{}[ERROR] Error: {}{}", RED, e, RESET);
-                        }
-                    }
-                }
-                Err(_) => {
-                    // Save history before exit
-                    self.save_history().await;
-                    println!("\n{}[EXIT] Goodbye from NexusShell!{}", BRIGHT_CYAN, RESET);
-                    break;
-                }
-            }
-        }
-        
-        Ok(())
-    }
-
-    async fn display_welcome_banner(&self) {
-        println!("{}", BRIGHT_CYAN);
-        println!("╔══════════════════════════════════════════════════════════════════════════╗");
-        println!("║                                                                          ║");
-        println!("║  {}>> NexusShell v1.0.0 - World's Most Beautiful Command Shell <<{}        ║", BRIGHT_YELLOW, BRIGHT_CYAN);
-        println!("║                                                                          ║");
-        println!("║  {}* Features: Full POSIX compatibility with modern UI *{}                ║", BRIGHT_GREEN, BRIGHT_CYAN);
-        println!("║                                                                          ║");
-        println!("║  {}[?] Type 'help' for commands  [*] Beautiful colors enabled [?]{}        ║", BLUE, BRIGHT_CYAN);
-        println!("║                                                                          ║");
-        println!("╚══════════════════════════════════════════════════════════════════════════╝");
-        println!("{}", RESET);
-        println!("{}>> Pro tip: Try 'help', 'env', or any command!{}", DIM, RESET);
-        println!();
-    }
-
-    async fn generate_prompt(&self) -> Result<String, ShellError> {
-        let current_dir = self.current_dir.read().await;
-        let username = whoami::username();
-        let hostname = whoami::hostname();
-        
-        // Get current directory name (not full path)
-        let dir_name = current_dir
-            .file_name()
-            .and_then(|n| n.to_str())
-            .unwrap_or("~");
-        
-        // Create clean and beautiful prompt
-        let prompt = format!(
-            "{}{}{}@{}{} {}{}{}{}> ",
-            BRIGHT_GREEN, username, RESET,
-            BRIGHT_CYAN, hostname, RESET,
-            BRIGHT_YELLOW, dir_name, RESET
-        );
-        
-        Ok(prompt)
-    }
-
-    async fn execute_command(&mut self, input: &str) -> Result<i32, ShellError> {
-        let input = input.trim();
-        
-        // Skip empty commands
-        if input.is_empty() {
-            return Ok(0);
-        }
-        
-        // Handle comments
-        if input.starts_with('#') {
-            return Ok(0);
-        }
-        
-        // Handle command chaining with && and ||
-        if input.contains("&&") {
-            return self.execute_and_chain(input).await;
-        }
-        if input.contains("||") {
-            return self.execute_or_chain(input).await;
-        }
-        
-        // Handle command sequences with ;
-        if input.contains(';') && !input.starts_with("for ") && !input.starts_with("while ") {
-            return self.execute_sequence(input).await;
-        }
-        
-        // Handle background execution
-        if input.ends_with(" &") {
-            let cmd = &input[..input.len() - 1].trim();
-            return self.execute_background_command(cmd).await;
-        }
-        
-        // Handle brace expansion
-        if input.contains('{') && input.contains('}') {
-            let expanded = self.expand_braces(input).await?;
-            if expanded != input {
-                return Box::pin(self.execute_command(&expanded)).await;
-            }
-        }
-        
-        // Handle glob patterns
-        if input.contains('*') || input.contains('?') || input.contains('[') {
-            let expanded = self.expand_globs(input).await?;
-            if expanded != input {
-                return Box::pin(self.execute_command(&expanded)).await;
-            }
-        }
-        
-        // Handle control structures
-        if input.starts_with("if ") {
-            return self.execute_if_statement(input).await;
-        } else if input.starts_with("for ") {
-            return self.execute_for_loop(input).await;
-        } else if input.starts_with("while ") {
-            return self.execute_while_loop(input).await;
-        } else if input.starts_with("case ") {
-            return self.execute_case_statement(input).await;
-        }
-        
-        // Handle command substitution
-        if input.contains("$(") || input.contains("`") {
-            let expanded = self.expand_command_substitution(input).await?;
-            return Box::pin(self.execute_command(&expanded)).await;
-        }
-        
-        // Handle arithmetic expansion
-        if input.contains("$((") {
-            let expanded = self.expand_arithmetic(input).await?;
-            return Box::pin(self.execute_command(&expanded)).await;
-        }
-        
-        // Handle pipelines
-        if input.contains('|') {
-            return self.execute_pipeline(input).await;
-        }
-        
-        // Handle redirections
-        if input.contains('>') || input.contains('<') {
-            return self.execute_with_redirection(input).await;
-        }
-        
-        // Handle variable assignment
-        if input.contains('=') && !input.starts_with("echo ") && !input.starts_with("export ") {
-            return self.handle_variable_assignment(input).await;
-        }
-        
-        // Handle built-in commands
-        if input.starts_with("cd ") || input == "cd" {
-            let args = if input == "cd" { "" } else { &input[3..] };
-            return self.builtin_cd(args).await;
-        } else if input == "pwd" {
-            return self.builtin_pwd().await;
-        } else if input.starts_with("echo ") {
-            return self.builtin_echo(&input[5..]).await;
-        } else if input == "help" {
-            return self.builtin_help().await;
-        } else if input.starts_with("export ") {
-            return self.builtin_export(&input[7..]).await;
-        } else if input == "env" {
-            return self.builtin_env().await;
-        } else if input.starts_with("ls") {
-            return self.builtin_ls(input).await;
-        } else if input == "exit" {
-            self.save_history().await;
-            std::process::exit(0);
-        } else if input.starts_with("history") {
-            return self.builtin_history().await;
-        } else if input.starts_with("alias ") {
-            return self.builtin_alias(&input[6..]).await;
-        } else if input == "alias" {
-            return self.builtin_show_aliases().await;
-        } else if input.starts_with("unset ") {
-            return self.builtin_unset(&input[6..]).await;
-        } else if input.starts_with("which ") {
-            return self.builtin_which(&input[6..]).await;
-        } else if input.starts_with("type ") {
-            return self.builtin_type(&input[5..]).await;
-        } else if input == "jobs" {
-            return self.builtin_jobs().await;
-        } else if input.starts_with("test ") || input.starts_with("[ ") {
-            return self.builtin_test(input).await;
-        } else if input.starts_with("read ") {
-            return self.builtin_read(&input[5..]).await;
-        } else if input.starts_with("printf ") {
-            return self.builtin_printf(&input[7..]).await;
-        } else if input.starts_with("source ") || input.starts_with(". ") {
-            return self.builtin_source(input).await;
-        } else if input.starts_with("function ") {
-            return self.builtin_function(&input[9..]).await;
-        } else if input.starts_with("return") {
-            return self.builtin_return(input).await;
-        } else if input == "set" {
-            return self.builtin_set().await;
-        } else if input.starts_with("declare ") || input.starts_with("local ") {
-            return self.builtin_declare(input).await;
-        } else if input.starts_with("[[") && input.ends_with("]]") {
-            return self.builtin_conditional_expression(input).await;
-        } else if input.starts_with("pushd ") {
-            return self.builtin_pushd(&input[6..]).await;
-        } else if input == "popd" {
-            return self.builtin_popd().await;
-        } else if input == "dirs" {
-            return self.builtin_dirs().await;
-        } else if input.starts_with("exec ") {
-            return self.builtin_exec(&input[5..]).await;
-        } else if input.starts_with("eval ") {
-            return self.builtin_eval(&input[5..]).await;
-        } else if input == "stats" || input == "statistics" {
-            return self.builtin_stats().await;
-        }
-        
-        // Try to execute as external command
-        Box::pin(self.execute_external_command(input)).await
-    }
-
-    async fn execute_pipeline(&mut self, input: &str) -> Result<i32, ShellError> {
-        let commands: Vec<&str> = input.split('|').map(|s| s.trim()).collect();
-        
-        if commands.len() < 2 {
-            return Box::pin(self.execute_command(input)).await;
-        }
-        
-        println!("Executing pipeline: {:?}", commands);
-        
-        let mut processes = Vec::new();
-        let mut previous_stdout: Option<std::process::Stdio> = None;
-        
-        for (i, cmd) in commands.iter().enumerate() {
-            let parts: Vec<&str> = cmd.split_whitespace().collect();
-            if parts.is_empty() {
-                continue;
-            }
-            
-            let mut command = std::process::Command::new(parts[0]);
-            command.args(&parts[1..]);
-            command.current_dir(&*self.current_dir.read().await);
-            
-            // Set up stdin from previous command
-            if let Some(stdout) = previous_stdout.take() {
-                command.stdin(stdout);
-            }
-            
-            // Set up stdout for next command (except for last command)
-            if i < commands.len() - 1 {
-                command.stdout(std::process::Stdio::piped());
-            }
-            
-            match command.spawn() {
-                Ok(mut child) => {
-                    if i < commands.len() - 1 {
-                        previous_stdout = child.stdout.take().map(std::process::Stdio::from);
-                    }
-                    processes.push(child);
-                }
-                Err(_) => {
-                    eprintln!("{}: command not found", parts[0]);
-                    return Ok(127);
-                }
-            }
-        }
-        
-        // Wait for all processes to complete
-        let mut last_exit_code = 0;
-        for mut process in processes {
-            match process.wait() {
-                Ok(status) => {
-                    last_exit_code = status.code().unwrap_or(-1);
-                }
-                Err(e) => {
-                    eprintln!("Pipeline error: {}", e);
-                    return Ok(1);
-                }
-            }
-        }
-        
-        Ok(last_exit_code)
-    }
-
-    async fn execute_with_redirection(&mut self, input: &str) -> Result<i32, ShellError> {
-        // Handle append redirection >>
-        if let Some(pos) = input.find(" >> ") {
-            let cmd_part = input[..pos].trim();
-            let file_part = input[pos + 4..].trim();
-            
-            let parts: Vec<&str> = cmd_part.split_whitespace().collect();
-            if parts.is_empty() {
-                return Ok(0);
-            }
-            
-            let mut command = std::process::Command::new(parts[0]);
-            command.args(&parts[1..]);
-            command.current_dir(&*self.current_dir.read().await);
-            
-            let file = std::fs::OpenOptions::new()
-                .create(true)
-                .append(true)
-                .open(file_part)?;
-            command.stdout(std::process::Stdio::from(file));
-            
-            match command.status() {
-                Ok(status) => Ok(status.code().unwrap_or(-1)),
-                Err(_) => {
-                    eprintln!("{}: command not found", parts[0]);
-                    Ok(127)
-                }
-            }
-        }
-        // Handle input redirection <
-        else if let Some(pos) = input.find(" < ") {
-            let cmd_part = input[..pos].trim();
-            let file_part = input[pos + 3..].trim();
-            
-            let parts: Vec<&str> = cmd_part.split_whitespace().collect();
-            if parts.is_empty() {
-                return Ok(0);
-            }
-            
-            let mut command = std::process::Command::new(parts[0]);
-            command.args(&parts[1..]);
-            command.current_dir(&*self.current_dir.read().await);
-            
-            let file = std::fs::File::open(file_part)?;
-            command.stdin(std::process::Stdio::from(file));
-            
-            match command.status() {
-                Ok(status) => Ok(status.code().unwrap_or(-1)),
-                Err(_) => {
-                    eprintln!("{}: command not found", parts[0]);
-                    Ok(127)
-                }
-            }
-        }
-        // Handle output redirection >
-        else if let Some(pos) = input.find(" > ") {
-            let cmd_part = input[..pos].trim();
-            let file_part = input[pos + 3..].trim();
-            
-            let parts: Vec<&str> = cmd_part.split_whitespace().collect();
-            if parts.is_empty() {
-                return Ok(0);
-            }
-            
-            let mut command = std::process::Command::new(parts[0]);
-            command.args(&parts[1..]);
-            command.current_dir(&*self.current_dir.read().await);
-            
-            let file = std::fs::File::create(file_part)?;
-            command.stdout(std::process::Stdio::from(file));
-            
-            match command.status() {
-                Ok(status) => Ok(status.code().unwrap_or(-1)),
-                Err(_) => {
-                    eprintln!("{}: command not found", parts[0]);
-                    Ok(127)
-                }
-            }
-        } else {
-            Box::pin(self.execute_external_command(input)).await
-        }
-    }
-
-    async fn handle_variable_assignment(&mut self, input: &str) -> Result<i32, ShellError> {
-        if let Some(eq_pos) = input.find('=') {
-            let key = input[..eq_pos].trim().to_string();
-            let value = input[eq_pos + 1..].trim().to_string();
-            
-            // Expand variables in value
-            let expanded_value = self.expand_variables(&value).await?;
-            
-            let mut variables = self.variables.write().await;
-            variables.insert(key, expanded_value);
-            Ok(0)
-        } else {
-            Ok(1)
-        }
-    }
-
-    async fn expand_variables(&self, text: &str) -> Result<String, ShellError> {
-        let mut result = text.to_string();
-        let variables = self.variables.read().await;
-        
-        // Handle ${VAR} parameter expansion
-        let re = Regex::new(r"\$\{([^}]+)\}").unwrap();
-        let text_clone = text.to_string();
-        let captures: Vec<_> = re.captures_iter(&text_clone).collect();
-        for capture in captures {
-            let var_expr = capture.get(1).unwrap().as_str();
-            let placeholder = capture.get(0).unwrap().as_str();
-            
-            // Handle parameter expansion features
-            let expanded = if var_expr.contains(":-") {
-                // ${VAR:-default}
-                let parts: Vec<&str> = var_expr.splitn(2, ":-").collect();
-                let var_name = parts[0];
-                let default_value = if parts.len() > 1 { parts[1] } else { "" };
-                variables.get(var_name).unwrap_or(&default_value.to_string()).clone()
-            } else if var_expr.contains(":=") {
-                // ${VAR:=default}
-                let parts: Vec<&str> = var_expr.splitn(2, ":=").collect();
-                let var_name = parts[0];
-                let default_value = if parts.len() > 1 { parts[1] } else { "" };
-                variables.get(var_name).unwrap_or(&default_value.to_string()).clone()
-            } else if var_expr.contains(":?") {
-                // ${VAR:?error}
-                let parts: Vec<&str> = var_expr.splitn(2, ":?").collect();
-                let var_name = parts[0];
-                if let Some(value) = variables.get(var_name) {
-                    value.clone()
-                } else {
-                    let error_msg = if parts.len() > 1 { parts[1] } else { "parameter null or not set" };
-                    eprintln!("{}: {}", var_name, error_msg);
-                    return Err(ShellError::InvalidArgument(format!("{}: {}", var_name, error_msg)));
-                }
-            } else if var_expr.contains("#") {
-                // ${#VAR} - length
-                let var_name = &var_expr[1..];
-                if let Some(value) = variables.get(var_name) {
-                    value.len().to_string()
-                } else {
-                    "0".to_string()
-                }
-            } else {
-                // Simple ${VAR}
-                variables.get(var_expr).unwrap_or(&String::new()).clone()
-            };
-            
-            result = result.replace(placeholder, &expanded);
-        }
-        
-        // Handle simple $VAR expansion
-        let re = Regex::new(r"\$([A-Za-z_][A-Za-z0-9_]*)").unwrap();
-        let result_clone = result.clone();
-        let captures: Vec<_> = re.captures_iter(&result_clone).collect();
-        for capture in captures {
-            let var_name = capture.get(1).unwrap().as_str();
-            let placeholder = capture.get(0).unwrap().as_str();
-            if let Some(value) = variables.get(var_name) {
-                result = result.replace(placeholder, value);
-            }
-        }
-        
-        Ok(result)
-    }
-
-    async fn builtin_env(&self) -> Result<i32, ShellError> {
-        let variables = self.variables.read().await;
-        println!("{}[ENV] Environment Variables:{}", BRIGHT_GREEN, RESET);
-        println!("{}═══════════════════════════{}", BRIGHT_GREEN, RESET);
-        for (key, value) in variables.iter() {
-            println!("{}{}{}={}{}{}", CYAN, key, RESET, YELLOW, value, RESET);
-        }
-        Ok(0)
-    }
-
-    async fn builtin_ls(&self, input: &str) -> Result<i32, ShellError> {
-        let parts: Vec<&str> = input.split_whitespace().collect();
-        let path = if parts.len() > 1 { parts[1] } else { "." };
-        
-        match std::fs::read_dir(path) {
-            Ok(entries) => {
-                for entry in entries {
-                    if let Ok(entry) = entry {
-                        let name = entry.file_name();
-                        println!("{}", name.to_string_lossy());
-                    }
-                }
-                Ok(0)
-            }
-            Err(_) => {
-                eprintln!("ls: {}: No such file or directory", path);
-                Ok(1)
-            }
-        }
-    }
-
-    async fn builtin_cd(&mut self, args: &str) -> Result<i32, ShellError> {
-        let target = if args.trim().is_empty() {
-            let variables = self.variables.read().await;
-            variables.get("HOME").cloned().unwrap_or_else(|| "/".to_string())
-        } else {
-            args.trim().to_string()
-        };
-        
-        let path = Path::new(&target);
-        if path.exists() && path.is_dir() {
-            let mut current_dir = self.current_dir.write().await;
-            *current_dir = path.canonicalize()?;
-            env::set_current_dir(&*current_dir)?;
-            Ok(0)
-        } else {
-            eprintln!("cd: {}: No such file or directory", target);
-            Ok(1)
-        }
-    }
-
-    async fn builtin_pwd(&self) -> Result<i32, ShellError> {
-        let current_dir = self.current_dir.read().await;
-        println!("{}[DIR] {}{}", BRIGHT_BLUE, current_dir.display(), RESET);
-        Ok(0)
-    }
-
-    async fn builtin_echo(&self, args: &str) -> Result<i32, ShellError> {
-        let mut output = String::new();
-        let mut interpret_escapes = false;
-        let mut no_newline = false;
-        
-        let parts: Vec<&str> = args.split_whitespace().collect();
-        let mut i = 0;
-        
-        // Parse options
-        while i < parts.len() && parts[i].starts_with('-') {
-            match parts[i] {
-                "-e" => interpret_escapes = true,
-                "-n" => no_newline = true,
-                "-ne" | "-en" => {
-                    interpret_escapes = true;
-                    no_newline = true;
-                }
-                _ => break,
-            }
-            i += 1;
-        }
-        
-        // Join remaining arguments
-        if i < parts.len() {
-            output = parts[i..].join(" ");
-        }
-        
-        // Expand variables
-        output = self.expand_variables(&output).await?;
-        
-        // Interpret escape sequences if -e flag is used
-        if interpret_escapes {
-            output = output
-                .replace("\\n", "\n")
-                .replace("\\t", "\t")
-                .replace("\\r", "\r")
-                .replace("\\\\", "\\")
-                .replace("\\\"", "\"")
-                .replace("\\'", "'");
-        }
-        
-        if no_newline {
-            print!("{}", output);
-        } else {
-            println!("{}", output);
-        }
-        
-        Ok(0)
-    }
-
-    async fn builtin_export(&mut self, args: &str) -> Result<i32, ShellError> {
-        if let Some(eq_pos) = args.find('=') {
-            let key = args[..eq_pos].trim().to_string();
-            let value = args[eq_pos + 1..].trim().to_string();
-            
-            let mut variables = self.variables.write().await;
-            variables.insert(key.clone(), value.clone());
-            env::set_var(&key, &value);
-            Ok(0)
-        } else {
-            eprintln!("export: usage: export VAR=value");
-            Ok(1)
-        }
-    }
-
-    async fn builtin_help(&self) -> Result<i32, ShellError> {
-        println!();
-        println!("{}╔══════════════════════════════════════════════════════════════════════════╗{}", BRIGHT_CYAN, RESET);
-        println!("{}║{} {}>> NexusShell - World's Most Complete Command Shell >>{} {}║{}", BRIGHT_CYAN, RESET, BRIGHT_YELLOW, RESET, BRIGHT_CYAN, RESET);
-        println!("{}╠══════════════════════════════════════════════════════════════════════════╣{}", BRIGHT_CYAN, RESET);
-        println!("{}║{} {}[*] Built-in Commands:{} {}                                              ║{}", BRIGHT_CYAN, RESET, BOLD, RESET, BRIGHT_CYAN, RESET);
-        println!("{}║{}                                                                          {}║{}", BRIGHT_CYAN, RESET, BRIGHT_CYAN, RESET);
-        
-        let commands = [
-            ("cd [DIR]", "Change directory", "[>]"),
-            ("pwd", "Print working directory", "[/]"),
-            ("echo [OPTIONS] TEXT", "Print text with options (-e, -n)", "[*]"),
-            ("printf FORMAT [ARGS]", "Formatted output", "[P]"),
-            ("export VAR=value", "Set environment variable", "[E]"),
-            ("env", "Display environment variables", "[?]"),
-            ("set", "Display all variables", "[S]"),
-            ("unset VAR", "Remove variable", "[X]"),
-            ("declare VAR=value", "Declare variable", "[D]"),
-            ("local VAR=value", "Declare local variable", "[L]"),
-            ("read VAR", "Read input into variable", "[R]"),
-            ("test / [ ]", "Test conditions", "[T]"),
-            ("[[ ]]", "Advanced conditional expressions", "[C]"),
-            ("alias NAME=VALUE", "Create command alias", "[A]"),
-            ("history", "Show command history", "[H]"),
-            ("jobs", "Show active jobs", "[J]"),
-            ("which COMMAND", "Locate command", "[W]"),
-            ("type COMMAND", "Show command type", "[#]"),
-            ("source FILE", "Execute file in current shell", "[.]"),
-            ("stats", "Show performance statistics", "[S]"),
-            ("help", "Show this help message", "[?]"),
-            ("exit", "Exit the shell", "[Q]"),
-        ];
-        
-        for (cmd, desc, icon) in &commands {
-            println!("{}║{} {}{} {:<20}{} - {:<30} {}║{}", 
-                BRIGHT_CYAN, RESET, icon, GREEN, cmd, RESET, desc, BRIGHT_CYAN, RESET);
-        }
-        
-        println!("{}║{}                                                                          {}║{}", BRIGHT_CYAN, RESET, BRIGHT_CYAN, RESET);
-        println!("{}║{} {}[+] Advanced Features:{} {}                                              ║{}", BRIGHT_CYAN, RESET, BOLD, RESET, BRIGHT_CYAN, RESET);
-        println!("{}║{}                                                                          {}║{}", BRIGHT_CYAN, RESET, BRIGHT_CYAN, RESET);
-        
-        let features = [
-            ("[|] Pipelines", "cmd1 | cmd2 | cmd3"),
-            ("[>] Redirections", "cmd > file, cmd >> file, cmd < file"),
-            ("[$] Variables", "VAR=value, $VAR, ${VAR}"),
-            ("[%] Parameter Exp", "${VAR:-default}, ${VAR:=default}, ${#VAR}"),
-            ("[&] Command Sub", "$(command), `command`"),
-            ("[#] Arithmetic", "$((expression))"),
-            ("[~] Background Jobs", "command &"),
-            ("[^] Control Flow", "if/then/fi, for/do/done, while/do/done"),
-            ("[{}] Brace Expansion", "{a,b,c}"),
-            ("[*] Glob Patterns", "*.txt, file?.log"),
-            ("[@] Arrays", "arr=(a b c), ${arr[0]}"),
-            ("[f] Functions", "function name() { commands; }"),
-        ];
-        
-        for (feature, desc) in &features {
-            println!("{}║{} {:<18} - {:<35} {}║{}", 
-                BRIGHT_CYAN, RESET, feature, desc, BRIGHT_CYAN, RESET);
-        }
-        
-        println!("{}║{}                                                                          {}║{}", BRIGHT_CYAN, RESET, BRIGHT_CYAN, RESET);
-        println!("{}║{} {}[*] POSIX Compatibility Level: 96%+{} {}                                 ║{}", BRIGHT_CYAN, RESET, BRIGHT_GREEN, RESET, BRIGHT_CYAN, RESET);
-        println!("{}║{} {}[+] Enterprise-grade performance and reliability!{} {}                  ║{}", BRIGHT_CYAN, RESET, BRIGHT_YELLOW, RESET, BRIGHT_CYAN, RESET);
-        println!("{}╚══════════════════════════════════════════════════════════════════════════╝{}", BRIGHT_CYAN, RESET);
-        println!();
-        Ok(0)
-    }
-
-    async fn execute_external_command(&self, command: &str) -> Result<i32, ShellError> {
-        let parts: Vec<&str> = command.split_whitespace().collect();
-        if parts.is_empty() {
-            return Ok(0);
-        }
-
-        let cmd_name = parts[0];
-        let args = &parts[1..];
-
-        let mut cmd = Command::new(cmd_name);
-        cmd.args(args);
-        cmd.current_dir(&*self.current_dir.read().await);
-
-        match cmd.status() {
-            Ok(status) => {
-                let exit_code = status.code().unwrap_or(-1);
-                *self.exit_code.write().await = exit_code;
-                Ok(exit_code)
-            }
-            Err(_) => {
-                eprintln!("{}: command not found", cmd_name);
-                Ok(127)
-            }
-        }
-    }
-
-    async fn builtin_history(&self) -> Result<i32, ShellError> {
-        let history = self.history.read().await;
-        println!("{}[HIST] Command History:{}", BRIGHT_MAGENTA, RESET);
-        println!("{}══════════════════{}", BRIGHT_MAGENTA, RESET);
-        for (i, cmd) in history.iter().enumerate() {
-            println!("{}{:4}{} {}{}{}", DIM, i + 1, RESET, BRIGHT_WHITE, cmd, RESET);
-        }
-        Ok(0)
-    }
-
-    async fn builtin_alias(&mut self, args: &str) -> Result<i32, ShellError> {
-        if let Some(eq_pos) = args.find('=') {
-            let alias_name = args[..eq_pos].trim().to_string();
-            let alias_value = args[eq_pos + 1..].trim().to_string();
-            
-            let mut aliases = self.aliases.write().await;
-            aliases.insert(alias_name, alias_value);
-            Ok(0)
-        } else {
-            eprintln!("alias: usage: alias name=value");
-            Ok(1)
-        }
-    }
-
-    async fn builtin_show_aliases(&self) -> Result<i32, ShellError> {
-        let aliases = self.aliases.read().await;
-        for (name, value) in aliases.iter() {
-            println!("alias {}='{}'", name, value);
-        }
-        Ok(0)
-    }
-
-    async fn builtin_unset(&mut self, args: &str) -> Result<i32, ShellError> {
-        let var_name = args.trim();
-        let mut variables = self.variables.write().await;
-        variables.remove(var_name);
-        Ok(0)
-    }
-
-    async fn builtin_which(&self, args: &str) -> Result<i32, ShellError> {
-        let command = args.trim();
-        
-        // Check if it's a builtin
-        match command {
-            "cd" | "pwd" | "echo" | "help" | "export" | "env" | "ls" | "exit" | 
-            "history" | "alias" | "unset" | "which" | "type" | "jobs" => {
-                println!("{}[BUILTIN] {}: shell builtin{}", BRIGHT_GREEN, command, RESET);
-                return Ok(0);
-            }
-            _ => {}
-        }
-        
-        // Check PATH
-        if let Ok(path_var) = std::env::var("PATH") {
-            for path_dir in path_var.split(if cfg!(windows) { ';' } else { ':' }) {
-                let executable = if cfg!(windows) {
-                    format!("{}/{}.exe", path_dir, command)
-                } else {
-                    format!("{}/{}", path_dir, command)
-                };
-                
-                if std::path::Path::new(&executable).exists() {
-                    println!("{}[PATH] {}{}", BRIGHT_BLUE, executable, RESET);
-                    return Ok(0);
-                }
-            }
-        }
-        
-        println!("{}[ERROR] {}: not found{}", RED, command, RESET);
-        Ok(1)
-    }
-
-    async fn builtin_type(&self, args: &str) -> Result<i32, ShellError> {
-        let command = args.trim();
-        
-        // Check if it's a builtin
-        match command {
-            "cd" | "pwd" | "echo" | "help" | "export" | "env" | "ls" | "exit" | 
-            "history" | "alias" | "unset" | "which" | "type" | "jobs" => {
-                println!("{} is a shell builtin", command);
-                return Ok(0);
-            }
-            _ => {}
-        }
-        
-        // Check aliases
-        let aliases = self.aliases.read().await;
-        if let Some(alias_value) = aliases.get(command) {
-            println!("{} is aliased to `{}'", command, alias_value);
-            return Ok(0);
-        }
-        
-        // Check PATH
-        if let Ok(path_var) = std::env::var("PATH") {
-            for path_dir in path_var.split(if cfg!(windows) { ';' } else { ':' }) {
-                let executable = if cfg!(windows) {
-                    format!("{}/{}.exe", path_dir, command)
-                } else {
-                    format!("{}/{}", path_dir, command)
-                };
-                
-                if std::path::Path::new(&executable).exists() {
-                    println!("{} is {}", command, executable);
-                    return Ok(0);
-                }
-            }
-        }
-        
-        eprintln!("{}: not found", command);
-        Ok(1)
-    }
-
-    async fn builtin_jobs(&self) -> Result<i32, ShellError> {
-        let jobs = self.jobs.read().await;
-        if jobs.is_empty() {
-            println!("{}[JOBS] No active jobs{}", BRIGHT_BLUE, RESET);
-        } else {
-            println!("{}[JOBS] Active Jobs:{}", BRIGHT_GREEN, RESET);
-            println!("{}═══════════════{}", BRIGHT_GREEN, RESET);
-            for (i, job) in jobs.iter().enumerate() {
-                println!("{}[{}]{} {}{}{} {}{}{}", 
-                    BRIGHT_BLUE, i + 1, RESET,
-                    GREEN, job.status, RESET,
-                    BRIGHT_WHITE, job.command, RESET);
-            }
-        }
-        Ok(0)
-    }
-
-    async fn expand_aliases(&self, input: &str) -> String {
-        let mut result = input.to_string();
-        let aliases = self.aliases.read().await;
-        
-        for (name, value) in aliases.iter() {
-            let pattern = format!("{}", name);
-            result = result.replace(&pattern, value);
-        }
-        
-        result
-    }
-
-    async fn execute_if_statement(&mut self, input: &str) -> Result<i32, ShellError> {
-        // Simple if statement parsing: if condition; then commands; fi
-        let re = Regex::new(r"if\s+(.+?);\s*then\s+(.+?);\s*fi").unwrap();
-        
-        if let Some(captures) = re.captures(input) {
-            let condition = captures.get(1).unwrap().as_str();
-            let commands = captures.get(2).unwrap().as_str();
-            
-            // Execute condition
-            let condition_result = Box::pin(self.execute_command(condition)).await?;
-            
-            // If condition succeeded (exit code 0), execute commands
-            if condition_result == 0 {
-                Box::pin(self.execute_command(commands)).await
-            } else {
-                Ok(0)
-            }
-        } else {
-            eprintln!("if: syntax error");
-            Ok(1)
-        }
-    }
-
-    async fn execute_for_loop(&mut self, input: &str) -> Result<i32, ShellError> {
-        // Simple for loop: for var in list; do commands; done
-        let re = Regex::new(r"for\s+(\w+)\s+in\s+(.+?);\s*do\s+(.+?);\s*done").unwrap();
-        
-        if let Some(captures) = re.captures(input) {
-            let var_name = captures.get(1).unwrap().as_str();
-            let list = captures.get(2).unwrap().as_str();
-            let commands = captures.get(3).unwrap().as_str();
-            
-            // Parse list (simple space-separated for now)
-            let items: Vec<&str> = list.split_whitespace().collect();
-            
-            let mut last_exit_code = 0;
-            for item in items {
-                // Set loop variable
-                {
-                    let mut variables = self.variables.write().await;
-                    variables.insert(var_name.to_string(), item.to_string());
-                }
-                
-                // Execute commands
-                last_exit_code = Box::pin(self.execute_command(commands)).await?;
-            }
-            
-            Ok(last_exit_code)
-        } else {
-            eprintln!("for: syntax error");
-            Ok(1)
-        }
-    }
-
-    async fn execute_while_loop(&mut self, input: &str) -> Result<i32, ShellError> {
-        // Simple while loop: while condition; do commands; done
-        let re = Regex::new(r"while\s+(.+?);\s*do\s+(.+?);\s*done").unwrap();
-        
-        if let Some(captures) = re.captures(input) {
-            let condition = captures.get(1).unwrap().as_str();
-            let commands = captures.get(2).unwrap().as_str();
-            
-            let mut last_exit_code = 0;
-            loop {
-                // Execute condition
-                let condition_result = Box::pin(self.execute_command(condition)).await?;
-                
-                // If condition failed, break
-                if condition_result != 0 {
-                    break;
-                }
-                
-                // Execute commands
-                last_exit_code = Box::pin(self.execute_command(commands)).await?;
-            }
-            
-            Ok(last_exit_code)
-        } else {
-            eprintln!("while: syntax error");
-            Ok(1)
-        }
-    }
-
-    async fn execute_case_statement(&mut self, _input: &str) -> Result<i32, ShellError> {
-        // Basic case statement implementation
-        eprintln!("case: not fully implemented yet");
-        Ok(1)
-    }
-
-    async fn expand_command_substitution(&self, input: &str) -> Result<String, ShellError> {
-        let mut result = input.to_string();
-        
-        // Handle $(...) command substitution
-        let re = Regex::new(r"\$\(([^)]+)\)").unwrap();
-        let input_clone = input.to_string();
-        let captures: Vec<_> = re.captures_iter(&input_clone).collect();
-        for capture in captures {
-            let command = capture.get(1).unwrap().as_str();
-            let output = self.execute_command_for_output(command).await?;
-            let placeholder = capture.get(0).unwrap().as_str();
-            result = result.replace(placeholder, &output.trim());
-        }
-        
-        // Handle `...` command substitution
-        let re = Regex::new(r"`([^`]+)`").unwrap();
-        let result_clone = result.clone();
-        let captures: Vec<_> = re.captures_iter(&result_clone).collect();
-        for capture in captures {
-            let command = capture.get(1).unwrap().as_str();
-            let output = self.execute_command_for_output(command).await?;
-            let placeholder = capture.get(0).unwrap().as_str();
-            result = result.replace(placeholder, &output.trim());
-        }
-        
-        Ok(result)
-    }
-
-    async fn expand_arithmetic(&self, input: &str) -> Result<String, ShellError> {
-        let mut result = input.to_string();
-        
-        // Handle $((...)) arithmetic expansion
-        let re = Regex::new(r"\$\(\(([^)]+)\)\)").unwrap();
-        for captures in re.captures_iter(input) {
-            let expression = captures.get(1).unwrap().as_str();
-            let value = self.evaluate_arithmetic(expression).await?;
-            let placeholder = captures.get(0).unwrap().as_str();
-            result = result.replace(placeholder, &value.to_string());
-        }
-        
-        Ok(result)
-    }
-
-    async fn execute_command_for_output(&self, command: &str) -> Result<String, ShellError> {
-        let parts: Vec<&str> = command.split_whitespace().collect();
-        if parts.is_empty() {
-            return Ok(String::new());
-        }
-
-        let mut cmd = Command::new(parts[0]);
-        cmd.args(&parts[1..]);
-        cmd.current_dir(&*self.current_dir.read().await);
-
-        match cmd.output() {
-            Ok(output) => {
-                Ok(String::from_utf8_lossy(&output.stdout).to_string())
-            }
-            Err(_) => {
-                Err(ShellError::CommandNotFound(parts[0].to_string()))
-            }
-        }
-    }
-
-    async fn evaluate_arithmetic(&self, expression: &str) -> Result<i64, ShellError> {
-        // Simple arithmetic evaluation
-        // This is a basic implementation - a full one would need proper parsing
-        let expression = expression.trim();
-        
-        // Handle simple operations
-        if let Some(pos) = expression.find('+') {
-            let left = expression[..pos].trim().parse::<i64>().unwrap_or(0);
-            let right = expression[pos + 1..].trim().parse::<i64>().unwrap_or(0);
-            return Ok(left + right);
-        } else if let Some(pos) = expression.find('-') {
-            let left = expression[..pos].trim().parse::<i64>().unwrap_or(0);
-            let right = expression[pos + 1..].trim().parse::<i64>().unwrap_or(0);
-            return Ok(left - right);
-        } else if let Some(pos) = expression.find('*') {
-            let left = expression[..pos].trim().parse::<i64>().unwrap_or(0);
-            let right = expression[pos + 1..].trim().parse::<i64>().unwrap_or(0);
-            return Ok(left * right);
-        } else if let Some(pos) = expression.find('/') {
-            let left = expression[..pos].trim().parse::<i64>().unwrap_or(0);
-            let right = expression[pos + 1..].trim().parse::<i64>().unwrap_or(1);
-            return Ok(left / right);
-        }
-        
-        // Try to parse as a simple number
-        Ok(expression.parse::<i64>().unwrap_or(0))
-    }
-
-    async fn execute_background_command(&mut self, command: &str) -> Result<i32, ShellError> {
-        let parts: Vec<&str> = command.split_whitespace().collect();
-        if parts.is_empty() {
-            return Ok(0);
-        }
-        
-        let mut cmd = std::process::Command::new(parts[0]);
-        cmd.args(&parts[1..]);
-        cmd.current_dir(&*self.current_dir.read().await);
-        
-        match cmd.spawn() {
-            Ok(child) => {
-                let job = Job {
-                    id: self.jobs.read().await.len() as u32 + 1,
-                    command: command.to_string(),
-                    status: "Running".to_string(),
-                    pid: Some(child.id()),
-                };
-                
-                let mut jobs = self.jobs.write().await;
-                jobs.push(job);
-                
-                println!("[{}] {}", jobs.len(), child.id());
-                Ok(0)
-            }
-            Err(e) => {
-                eprintln!("{}: command not found", parts[0]);
-                Err(ShellError::IoError(e))
-            }
-        }
-    }
-
-    async fn expand_braces(&self, input: &str) -> Result<String, ShellError> {
-        // Simple brace expansion implementation
-        Ok(input.to_string())
-    }
-
-    fn expand_braces_helper(&self, pattern: &str) -> Result<String, ShellError> {
-        // Placeholder implementation
-        Ok(pattern.to_string())
-    }
-
-    async fn expand_globs(&self, input: &str) -> Result<String, ShellError> {
-        // Simple glob expansion implementation
-        Ok(input.to_string())
-    }
-
-    fn expand_glob_helper(&self, pattern: &str) -> Result<String, ShellError> {
-        // Placeholder implementation
-        Ok(pattern.to_string())
-    }
-
-    async fn builtin_test(&self, input: &str) -> Result<i32, ShellError> {
-        // Basic test command implementation
-        let args = if input.starts_with("test ") {
-            &input[5..]
-        } else if input.starts_with("[ ") && input.ends_with(" ]") {
-            &input[2..input.len() - 2]
-        } else {
-            input
-        };
-        
-        let parts: Vec<&str> = args.split_whitespace().collect();
-        
-        if parts.is_empty() {
-            return Ok(1);
-        }
-        
-        match parts.len() {
-            1 => {
-                // Test if string is non-empty
-                Ok(if parts[0].is_empty() { 1 } else { 0 })
-            }
-            3 => {
-                let left = parts[0];
-                let op = parts[1];
-                let right = parts[2];
-                
-                match op {
-                    "=" | "==" => Ok(if left == right { 0 } else { 1 }),
-                    "!=" => Ok(if left != right { 0 } else { 1 }),
-                    "-eq" => {
-                        let l: i32 = left.parse().unwrap_or(0);
-                        let r: i32 = right.parse().unwrap_or(0);
-                        Ok(if l == r { 0 } else { 1 })
-                    }
-                    "-ne" => {
-                        let l: i32 = left.parse().unwrap_or(0);
-                        let r: i32 = right.parse().unwrap_or(0);
-                        Ok(if l != r { 0 } else { 1 })
-                    }
-                    "-lt" => {
-                        let l: i32 = left.parse().unwrap_or(0);
-                        let r: i32 = right.parse().unwrap_or(0);
-                        Ok(if l < r { 0 } else { 1 })
-                    }
-                    "-gt" => {
-                        let l: i32 = left.parse().unwrap_or(0);
-                        let r: i32 = right.parse().unwrap_or(0);
-                        Ok(if l > r { 0 } else { 1 })
-                    }
-                    "-f" => {
-                        // Test if file exists and is regular file
-                        Ok(if std::path::Path::new(right).is_file() { 0 } else { 1 })
-                    }
-                    "-d" => {
-                        // Test if directory exists
-                        Ok(if std::path::Path::new(right).is_dir() { 0 } else { 1 })
-                    }
-                    _ => Ok(1),
-                }
-            }
-            _ => Ok(1),
-        }
-    }
-
-    async fn builtin_read(&mut self, args: &str) -> Result<i32, ShellError> {
-        let var_name = args.trim();
-        if var_name.is_empty() {
-            eprintln!("read: usage: read variable_name");
-            return Ok(1);
-        }
-        
-        let mut input = String::new();
-        match std::io::stdin().read_line(&mut input) {
-            Ok(_) => {
-                let value = input.trim().to_string();
-                let mut variables = self.variables.write().await;
-                variables.insert(var_name.to_string(), value);
-                Ok(0)
-            }
-            Err(_) => Ok(1),
-        }
-    }
-
-    async fn builtin_printf(&self, args: &str) -> Result<i32, ShellError> {
-        // Basic printf implementation
-        let parts: Vec<&str> = args.splitn(2, ' ').collect();
-        if parts.is_empty() {
-            return Ok(0);
-        }
-        
-        let format_str = parts[0];
-        let _args_str = if parts.len() > 1 { parts[1] } else { "" };
-        
-        // Simple format string processing
-        let output = format_str
-            .replace("\\n", "\n")
-            .replace("\\t", "\t")
-            .replace("\\r", "\r")
-            .replace("\\\\", "\\");
-        
-        // Replace %s with arguments (basic implementation)
-        // if output.contains("%s") && !args_str.is_empty() {
-        //     output = output.replace("%s", args_str);
-        // }
-        
-        print!("{}", output);
-        Ok(0)
-    }
-
-    async fn builtin_source(&mut self, input: &str) -> Result<i32, ShellError> {
-        let filename = if input.starts_with("source ") {
-            &input[7..]
-        } else if input.starts_with(". ") {
-            &input[2..]
-        } else {
-            input
-        };
-        
-        let filename = filename.trim();
-        
-        match std::fs::read_to_string(filename) {
-            Ok(content) => {
-                // Execute each line
-                for line in content.lines() {
-                    let line = line.trim();
-                    if !line.is_empty() && !line.starts_with('#') {
-                        Box::pin(self.execute_command(line)).await?;
-                    }
-                }
-                Ok(0)
-            }
-            Err(_) => {
-                eprintln!("source: {}: No such file or directory", filename);
-                Ok(1)
-            }
-        }
-    }
-
-    async fn builtin_function(&mut self, _args: &str) -> Result<i32, ShellError> {
-        // Basic function definition (placeholder)
-        eprintln!("function: not fully implemented yet");
-        Ok(0)
-    }
-
-    async fn builtin_return(&self, args: &str) -> Result<i32, ShellError> {
-        let code = if args.trim() == "return" {
-            0
-        } else {
-            args.trim()
-                .strip_prefix("return ")
-                .unwrap_or("0")
-                .parse::<i32>()
-                .unwrap_or(0)
-        };
-        Ok(code)
-    }
-
-    async fn builtin_set(&self) -> Result<i32, ShellError> {
-        let variables = self.variables.read().await;
-        for (key, value) in variables.iter() {
-            println!("{}={}", key, value);
-        }
-        Ok(0)
-    }
-
-    async fn builtin_declare(&mut self, input: &str) -> Result<i32, ShellError> {
-        // Basic declare/local implementation
-        if let Some(eq_pos) = input.find('=') {
-            let key = input[..eq_pos].trim().to_string();
-            let value = input[eq_pos + 1..].trim().to_string();
-            
-            let mut variables = self.variables.write().await;
-            variables.insert(key, value);
-            Ok(0)
-        } else {
-            eprintln!("declare: usage: declare VAR=value");
-            Ok(1)
-        }
-    }
-
-    async fn builtin_conditional_expression(&self, _input: &str) -> Result<i32, ShellError> {
-        // Placeholder implementation
-        eprintln!("conditional_expression: not fully implemented yet");
-        Ok(1)
-    }
-
-    async fn builtin_pushd(&self, _args: &str) -> Result<i32, ShellError> {
-        // Placeholder implementation
-        eprintln!("pushd: not fully implemented yet");
-        Ok(1)
-    }
-
-    async fn builtin_popd(&self) -> Result<i32, ShellError> {
-        // Placeholder implementation
-        eprintln!("popd: not fully implemented yet");
-        Ok(1)
-    }
-
-    async fn builtin_dirs(&self) -> Result<i32, ShellError> {
-        // Placeholder implementation
-        eprintln!("dirs: not fully implemented yet");
-        Ok(1)
-    }
-
-    async fn builtin_exec(&self, _args: &str) -> Result<i32, ShellError> {
-        // Placeholder implementation
-        eprintln!("exec: not fully implemented yet");
-        Ok(1)
-    }
-
-    async fn builtin_eval(&self, _args: &str) -> Result<i32, ShellError> {
-        // Placeholder implementation
-        eprintln!("eval: not fully implemented yet");
-        Ok(1)
-    }
-
-    async fn save_history(&self) {
-        let _history = self.history.read().await;
-        let history_file = env::var("HOME").unwrap_or_else(|_| ".".to_string()) + "/.nexusshell_history";
-        let mut readline = self.readline.lock().await;
-        readline.save_history(&history_file).ok();
-    }
-
-    async fn execute_and_chain(&mut self, input: &str) -> Result<i32, ShellError> {
-        let commands: Vec<&str> = input.split("&&").map(|s| s.trim()).collect();
-        let mut last_exit_code = 0;
-        
-        for cmd in commands {
-            last_exit_code = Box::pin(self.execute_command(cmd)).await?;
-            if last_exit_code != 0 {
-                break; // Stop on first failure
-            }
-        }
-        
-        Ok(last_exit_code)
-    }
-
-    async fn execute_or_chain(&mut self, input: &str) -> Result<i32, ShellError> {
-        let commands: Vec<&str> = input.split("||").map(|s| s.trim()).collect();
-        let mut last_exit_code = 1;
-        
-        for cmd in commands {
-            last_exit_code = Box::pin(self.execute_command(cmd)).await?;
-            if last_exit_code == 0 {
-                break; // Stop on first success
-            }
-        }
-        
-        Ok(last_exit_code)
-    }
-
-    async fn execute_sequence(&mut self, input: &str) -> Result<i32, ShellError> {
-        let commands: Vec<&str> = input.split(';').map(|s| s.trim()).collect();
-        let mut last_exit_code = 0;
-        
-        for cmd in commands {
-            if !cmd.is_empty() {
-                last_exit_code = Box::pin(self.execute_command(cmd)).await?;
-            }
-        }
-        
-        Ok(last_exit_code)
-    }
-
-    async fn builtin_stats(&self) -> Result<i32, ShellError> {
-        let command_count = *self.command_count.read().await;
-        let error_count = *self.error_count.read().await;
-        let uptime = self.startup_time.elapsed();
-        let last_command_time = *self.last_command_time.read().await;
-        let time_since_last = last_command_time.elapsed();
-        
-        println!();
-        println!("{}╔══════════════════════════════════════════════════════════════════════════╗{}", BRIGHT_CYAN, RESET);
-        println!("{}║{} {}[STATS] NexusShell Performance Statistics{} {}                         ║{}", BRIGHT_CYAN, RESET, BRIGHT_YELLOW, RESET, BRIGHT_CYAN, RESET);
-        println!("{}╠══════════════════════════════════════════════════════════════════════════╣{}", BRIGHT_CYAN, RESET);
-        println!("{}║{}                                                                          {}║{}", BRIGHT_CYAN, RESET, BRIGHT_CYAN, RESET);
-        
-        // Session info
-        println!("{}║{} {}Session Information:{} {}                                              ║{}", BRIGHT_CYAN, RESET, BOLD, RESET, BRIGHT_CYAN, RESET);
-        println!("{}║{} Session ID: {:<50} {}║{}", BRIGHT_CYAN, RESET, self.session_id, BRIGHT_CYAN, RESET);
-        println!("{}║{} Uptime: {:<54} {}║{}", BRIGHT_CYAN, RESET, format!("{:?}", uptime), BRIGHT_CYAN, RESET);
-        println!("{}║{} Time since last command: {:<38} {}║{}", BRIGHT_CYAN, RESET, format!("{:?}", time_since_last), BRIGHT_CYAN, RESET);
-        
-        println!("{}║{}                                                                          {}║{}", BRIGHT_CYAN, RESET, BRIGHT_CYAN, RESET);
-        
-        // Command statistics
-        println!("{}║{} {}Command Statistics:{} {}                                               ║{}", BRIGHT_CYAN, RESET, BOLD, RESET, BRIGHT_CYAN, RESET);
-        println!("{}║{} Total commands executed: {:<41} {}║{}", BRIGHT_CYAN, RESET, command_count, BRIGHT_CYAN, RESET);
-        println!("{}║{} Total errors: {:<50} {}║{}", BRIGHT_CYAN, RESET, error_count, BRIGHT_CYAN, RESET);
-        
-        let success_rate = if command_count > 0 {
-            ((command_count - error_count) as f64 / command_count as f64) * 100.0
-        } else {
-            100.0
-        };
-        println!("{}║{} Success rate: {:<48} {}║{}", BRIGHT_CYAN, RESET, format!("{:.1}%", success_rate), BRIGHT_CYAN, RESET);
-        
-        let commands_per_minute = if uptime.as_secs() > 0 {
-            (command_count as f64 / uptime.as_secs() as f64) * 60.0
-        } else {
-            0.0
-        };
-        println!("{}║{} Commands per minute: {:<41} {}║{}", BRIGHT_CYAN, RESET, format!("{:.1}", commands_per_minute), BRIGHT_CYAN, RESET);
-        
-        println!("{}║{}                                                                          {}║{}", BRIGHT_CYAN, RESET, BRIGHT_CYAN, RESET);
-        
-        // Memory and performance info
-        println!("{}║{} {}Memory & Performance:{} {}                                             ║{}", BRIGHT_CYAN, RESET, BOLD, RESET, BRIGHT_CYAN, RESET);
-        
-        let history_count = self.history.read().await.len();
-        let alias_count = self.aliases.read().await.len();
-        let job_count = self.jobs.read().await.len();
-        let function_count = self.functions.read().await.len();
-        let array_count = self.arrays.read().await.len();
-        
-        println!("{}║{} History entries: {:<45} {}║{}", BRIGHT_CYAN, RESET, history_count, BRIGHT_CYAN, RESET);
-        println!("{}║{} Active aliases: {:<46} {}║{}", BRIGHT_CYAN, RESET, alias_count, BRIGHT_CYAN, RESET);
-        println!("{}║{} Background jobs: {:<45} {}║{}", BRIGHT_CYAN, RESET, job_count, BRIGHT_CYAN, RESET);
-        println!("{}║{} Defined functions: {:<43} {}║{}", BRIGHT_CYAN, RESET, function_count, BRIGHT_CYAN, RESET);
-        println!("{}║{} Arrays: {:<56} {}║{}", BRIGHT_CYAN, RESET, array_count, BRIGHT_CYAN, RESET);
-        
-        println!("{}║{}                                                                          {}║{}", BRIGHT_CYAN, RESET, BRIGHT_CYAN, RESET);
-        println!("{}║{} {}[INFO] Type 'help' for commands or 'exit' to quit{} {}                 ║{}", BRIGHT_CYAN, RESET, BRIGHT_GREEN, RESET, BRIGHT_CYAN, RESET);
-        println!("{}╚══════════════════════════════════════════════════════════════════════════╝{}", BRIGHT_CYAN, RESET);
-        println!();
-        
-        Ok(0)
-    }
+mod arith;
+mod arrays;
+mod ast;
+mod braces;
+mod dynamic;
+mod expand;
+mod functions;
+mod jobs;
+mod lexer;
+mod options;
+mod parser;
+mod pathname;
+mod pattern;
+mod redirect;
+mod script;
+mod signals;
+mod streams;
+mod substitution;
+mod traps;
+mod variables;
+
+use std::collections::HashMap;
+use std::env;
+use std::io::{self, Write};
+use std::os::unix::io::RawFd;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use clap::Parser as _;
+use std::time::Instant;
+use tokio::sync::{RwLock, Mutex};
+use rustyline::Editor;
+use rustyline::error::ReadlineError;
+use rustyline::completion::{Completer as RustylineCompleter, FilenameCompleter, Pair};
+use rustyline::hint::{HistoryHinter, Hinter};
+use rustyline::highlight::Highlighter;
+use rustyline::history::DefaultHistory;
+use rustyline::validate::{Validator, ValidationResult, ValidationContext};
+use rustyline::Helper;
+use rustyline::config::Configurer;
+
+use crate::ast::{AndOr, Assignment, CaseItem, CaseTerminator, Command, CompoundCommand, Connector, FunctionDef, List, Pipeline, Redirect, SimpleCommand, Word};
+use crate::arrays::Array;
+use crate::dynamic::DynamicVariables;
+use crate::functions::Frame;
+use crate::jobs::{Job, JobSetup, JobTable, Terminal};
+use crate::options::{ShellOptions, ShoptOptions};
+use crate::pattern::Pattern;
+use crate::script::ScriptSource;
+use crate::redirect::{flush_stdio, ReservedFds, SavedFds};
+use crate::streams::Streams;
+use crate::substitution::ProcessSubstitution;
+use crate::traps::{Condition, Traps};
+use crate::variables::Attributes;
+use nix::fcntl::OFlag;
+use nix::sys::signal::{SigHandler, Signal};
+use nix::unistd::{ForkResult, Pid};
+use crate::parser::ParseError;
+
+// ANSI color codes for beautiful output
+const RESET: &str = "\x1b[0m";
+const BOLD: &str = "\x1b[1m";
+const DIM: &str = "\x1b[2m";
+const RED: &str = "\x1b[31m";
+const GREEN: &str = "\x1b[32m";
+const YELLOW: &str = "\x1b[33m";
+const BLUE: &str = "\x1b[34m";
+const CYAN: &str = "\x1b[36m";
+const BRIGHT_GREEN: &str = "\x1b[92m";
+const BRIGHT_BLUE: &str = "\x1b[94m";
+const BRIGHT_CYAN: &str = "\x1b[96m";
+const BRIGHT_YELLOW: &str = "\x1b[93m";
+const BRIGHT_MAGENTA: &str = "\x1b[95m";
+const BRIGHT_WHITE: &str = "\x1b[97m";
+
+// Commands executed inside the shell process rather than spawned
+const BUILTINS: &[&str] = &[
+    "cd", "pwd", "echo", "help", "export", "env", "ls", "exit", "break", "continue", "history", "alias",
+    "unset", "readonly", "shift", "which", "type", "jobs", "fg", "bg", "wait", "kill", "disown", "test", "[", "read", "printf", "source", ".",
+    "return", "set", "shopt", "declare", "local", "pushd", "popd", "dirs", "exec",
+    "eval", "let", "trap", "stats", "statistics",
+];
+
+fn is_builtin(name: &str) -> bool {
+    BUILTINS.contains(&name)
+}
+
+type LineEditor = Editor<NexusHelper, DefaultHistory>;
+
+// Custom completion helper for NexusShell
+pub struct NexusHelper {
+    completer: FilenameCompleter,
+    hinter: HistoryHinter,
+}
+
+impl Default for NexusHelper {
+    fn default() -> Self {
+        NexusHelper {
+            completer: FilenameCompleter::new(),
+            hinter: HistoryHinter::new(),
+        }
+    }
+}
+
+impl Helper for NexusHelper {}
+
+impl RustylineCompleter for NexusHelper {
+    type Candidate = Pair;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        ctx: &rustyline::Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let mut candidates = Vec::new();
+        
+        // Built-in commands completion
+        let builtins = [
+            "cd", "pwd", "echo", "printf", "export", "readonly", "env", "set", "unset", "shift",
+            "declare", "local", "read", "test", "alias", "history", "jobs", "fg", "bg",
+            "wait", "kill", "disown",
+            "which", "type", "source", "help", "exit", "trap", "break", "continue", "ls", "pushd", "popd", 
+            "dirs", "exec", "eval", "let", "function", "return", "if", "then", "else", 
+            "elif", "fi", "for", "do", "done", "while", "until", "case", "esac", "stats"
+        ];
+        
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let current_word = if line.ends_with(' ') { "" } else { words.last().map_or("", |v| *v) };
+        
+        // Complete built-in commands if it's the first word
+        if words.len() <= 1 && !line.ends_with(' ') {
+            for builtin in &builtins {
+                if builtin.starts_with(current_word) {
+                    candidates.push(Pair {
+                        display: builtin.to_string(),
+                        replacement: builtin.to_string(),
+                    });
+                }
+            }
+        }
+        
+        // Add file completion
+        let (start, file_candidates) = self.completer.complete(line, pos, ctx)?;
+        candidates.extend(file_candidates);
+        
+        Ok((start, candidates))
+    }
+}
+
+impl Highlighter for NexusHelper {
+    fn highlight<'l>(&self, line: &'l str, _pos: usize) -> std::borrow::Cow<'l, str> {
+        use std::borrow::Cow;
+        
+        let mut highlighted = String::new();
+        let words: Vec<&str> = line.split_whitespace().collect();
+        
+        if words.is_empty() {
+            return Cow::Borrowed(line);
+        }
+        
+        let builtins = [
+            "cd", "pwd", "echo", "printf", "export", "readonly", "env", "set", "unset", "shift",
+            "declare", "local", "read", "test", "alias", "history", "jobs",
+            "fg", "bg", "wait", "kill", "disown", "which", "type", "source", "help", "exit", "trap", "break", "continue", "ls", "if", "then",
+            "else", "elif", "fi", "for", "do", "done", "while", "until"
+        ];
+        
+        let mut current_pos = 0;
+        for (i, word) in words.iter().enumerate() {
+            // Find the position of this word in the original line
+            if let Some(word_start) = line[current_pos..].find(word) {
+                let actual_start = current_pos + word_start;
+                
+                // Add any whitespace before the word
+                highlighted.push_str(&line[current_pos..actual_start]);
+                
+                // Highlight the word based on its type
+                if i == 0 && builtins.contains(word) {
+                    // Built-in command - green
+                    highlighted.push_str(&format!("\x1b[32m{}\x1b[0m", word));
+                } else if word.starts_with('$') {
+                    // Variable - yellow
+                    highlighted.push_str(&format!("\x1b[33m{}\x1b[0m", word));
+                } else if word.starts_with('-') {
+                    // Option/flag - cyan
+                    highlighted.push_str(&format!("\x1b[36m{}\x1b[0m", word));
+                } else if word.contains('=') {
+                    // Assignment - magenta
+                    highlighted.push_str(&format!("\x1b[35m{}\x1b[0m", word));
+                } else {
+                    highlighted.push_str(word);
+                }
+                
+                current_pos = actual_start + word.len();
+            }
+        }
+        
+        // Add any remaining characters
+        highlighted.push_str(&line[current_pos..]);
+        
+        Cow::Owned(highlighted)
+    }
+    
+    fn highlight_char(&self, line: &str, pos: usize, _forced: bool) -> bool {
+        pos < line.len()
+    }
+}
+
+impl Validator for NexusHelper {
+    fn validate(&self, ctx: &mut ValidationContext) -> rustyline::Result<ValidationResult> {
+        // Keep reading lines while the parser reports an unfinished construct
+        // (open quotes, `if` without `fi`, trailing `&&` or `|`, ...).  Real
+        // syntax errors are reported when the command is executed.
+        match parser::parse(ctx.input()) {
+            Err(ParseError::Incomplete(_)) => Ok(ValidationResult::Incomplete),
+            _ => Ok(ValidationResult::Valid(None)),
+        }
+    }
+}
+
+impl Hinter for NexusHelper {
+    type Hint = String;
+
+    fn hint(&self, line: &str, pos: usize, ctx: &rustyline::Context<'_>) -> Option<String> {
+        self.hinter.hint(line, pos, ctx)
+    }
+}
+
+/// Command line of the shell, following `sh`.
+#[derive(clap::Parser, Debug)]
+#[command(name = "nexusshell", version, about = "NexusShell - a modern POSIX-style shell")]
+pub struct Cli {
+    /// Run COMMAND; the first argument after it sets $0, the rest $1, $2, ...
+    #[arg(short = 'c', value_name = "COMMAND")]
+    command: Option<String>,
+    /// Run interactively even when stdin is not a terminal
+    #[arg(short = 'i')]
+    interactive: bool,
+    /// Act as a login shell, reading /etc/profile and ~/.profile first
+    #[arg(short = 'l', long = "login")]
+    login: bool,
+    /// Read commands from stdin; all arguments become positional parameters
+    #[arg(short = 's')]
+    stdin: bool,
+    /// Script file followed by its arguments
+    #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+    args: Vec<String>,
+}
+
+/// Nested commands and recursive functions are evaluated recursively, so
+/// the shell runs on a thread with a generous stack.
+const SHELL_STACK_SIZE: usize = 512 * 1024 * 1024;
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let cli = Cli::parse();
+    let reserved = ReservedFds::take();
+    let runtime = tokio::runtime::Runtime::new()?;
+    let shell = std::thread::Builder::new()
+        .name("nexusshell".to_string())
+        .stack_size(SHELL_STACK_SIZE)
+        .spawn(move || {
+            // Tokio's cooperative budget would make the shell yield every so
+            // many lock operations, and each yield polls the whole chain of
+            // nested futures again, which makes deep recursion quadratic
+            runtime.block_on(tokio::task::unconstrained(async {
+                let mut shell = Shell::new().await.map_err(|e| e.to_string())?;
+                shell.start_reaper().map_err(|e| e.to_string())?;
+                drop(reserved);
+                shell.start(cli).await.map_err(|e| e.to_string())
+            }))
+        })?;
+    let status = match shell.join() {
+        Ok(Ok(status)) => status,
+        Ok(Err(e)) => {
+            eprintln!("nexusshell: {}", e);
+            1
+        }
+        // The panic message has already been printed
+        Err(_) => 1,
+    };
+    flush_stdio();
+    std::process::exit(status)
+}
+
+#[derive(Debug)]
+pub struct Shell {
+    pub variables: Arc<RwLock<HashMap<String, String>>>,
+    /// Attributes of variables, including declared ones that are not set.
+    pub attributes: Arc<RwLock<HashMap<String, Attributes>>>,
+    pub current_dir: Arc<RwLock<PathBuf>>,
+    pub exit_code: Arc<RwLock<i32>>,
+    pub readline: Arc<Mutex<LineEditor>>,
+    pub startup_time: Instant,
+    pub session_id: String,
+    pub history: Arc<RwLock<Vec<String>>>,
+    pub aliases: Arc<RwLock<HashMap<String, String>>>,
+    pub jobs: Arc<RwLock<JobTable>>,
+    /// Set when the shell does job control on its terminal.
+    pub terminal: Option<Terminal>,
+    pub functions: Arc<RwLock<HashMap<String, Arc<FunctionDef>>>>,
+    /// Calls of shell functions in progress, innermost last.
+    pub frames: Arc<RwLock<Vec<Frame>>>,
+    pub arrays: Arc<RwLock<HashMap<String, Array>>>,
+    pub options: Arc<RwLock<ShellOptions>>,
+    pub shopt: Arc<RwLock<ShoptOptions>>,
+    /// `$0`: the shell or script name.
+    pub arg0: String,
+    /// `$1`, `$2`, ...
+    pub positional: Arc<RwLock<Vec<String>>>,
+    /// Number of loops `break` and `continue` can reach.
+    pub loop_depth: Arc<RwLock<usize>>,
+    /// Number of `source` commands in progress, which `return` may end.
+    pub sourcing: Arc<RwLock<usize>>,
+    /// Number of conditions and other contexts in progress where `set -e`
+    /// does not apply.
+    pub errexit_suppressed: Arc<RwLock<usize>>,
+    pub traps: Arc<RwLock<Traps>>,
+    /// `$$`: the process id of the shell, which subshells keep.
+    pub shell_pid: u32,
+    /// `$!`: the last process started in the background.
+    pub last_background: Arc<RwLock<Option<Pid>>>,
+    pub interactive: bool,
+    /// Line at which the running script command starts, less one, and the
+    /// line of the running simple command within it; `LINENO` is their sum.
+    pub line: Arc<RwLock<(usize, usize)>>,
+    pub dynamic: Arc<RwLock<DynamicVariables>>,
+    /// Status of the last command substitution in the command being
+    /// expanded, which an assignment-only command returns.
+    pub substitution_status: Arc<RwLock<Option<i32>>>,
+    pub process_substitutions: Arc<RwLock<Vec<ProcessSubstitution>>>,
+    pub command_count: Arc<RwLock<u64>>,
+    pub error_count: Arc<RwLock<u64>>,
+    pub last_command_time: Arc<RwLock<Instant>>,
+}
+
+#[derive(Debug)]
+pub enum ShellError {
+    SyntaxError(String),
+    CommandNotFound(String),
+    FileNotFound(String),
+    PermissionDenied(String),
+    InvalidArgument(String),
+    IoError(io::Error),
+    Redirection(String),
+    Interrupted,
+    Exit(i32),
+    /// `return` unwinding to the function call.
+    Return(i32),
+    /// `break N` unwinding through the given number of loops.
+    Break(usize),
+    /// `${VAR:?message}` and other expansion errors that end a script.
+    Expansion(String),
+    /// A glob matched no file while `failglob` was set.
+    NoMatch(String),
+    /// An arithmetic expression that could not be evaluated.
+    Arithmetic(String),
+    /// `continue N`: the innermost of the loops goes on with its next pass.
+    Continue(usize),
+    /// An assignment to a readonly variable.
+    ReadOnly(String),
+}
+
+impl std::fmt::Display for ShellError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ShellError::SyntaxError(msg) => write!(f, "Syntax error: {}", msg),
+            ShellError::CommandNotFound(cmd) => write!(f, "{}: command not found", cmd),
+            ShellError::FileNotFound(file) => write!(f, "{}: No such file or directory", file),
+            ShellError::PermissionDenied(file) => write!(f, "{}: Permission denied", file),
+            ShellError::InvalidArgument(arg) => write!(f, "Invalid argument: {}", arg),
+            ShellError::IoError(err) => write!(f, "IO error: {}", err),
+            ShellError::Redirection(msg) => write!(f, "{}", msg),
+            ShellError::Interrupted => write!(f, "Interrupted"),
+            ShellError::Exit(code) => write!(f, "Exit with code {}", code),
+            ShellError::Return(code) => write!(f, "Return with code {}", code),
+            ShellError::Expansion(msg) => write!(f, "{}", msg),
+            ShellError::NoMatch(pattern) => write!(f, "no match: {}", pattern),
+            ShellError::Arithmetic(msg) => write!(f, "{}", msg),
+            ShellError::Break(count) => write!(f, "Break out of {} loops", count),
+            ShellError::Continue(count) => write!(f, "Continue loop {} levels out", count),
+            ShellError::ReadOnly(name) => write!(f, "{}: readonly variable", name),
+        }
+    }
+}
+
+impl std::error::Error for ShellError {}
+
+impl From<io::Error> for ShellError {
+    fn from(err: io::Error) -> Self {
+        ShellError::IoError(err)
+    }
+}
+
+impl From<lexer::LexError> for ShellError {
+    fn from(err: lexer::LexError) -> Self {
+        ShellError::SyntaxError(err.to_string())
+    }
+}
+
+/// Checks file accessibility for `test -r/-w/-x`.
+fn file_access(path: &str, mode: libc::c_int) -> bool {
+    match std::ffi::CString::new(path) {
+        Ok(path) => unsafe { libc::access(path.as_ptr(), mode) == 0 },
+        Err(_) => false,
+    }
+}
+
+/// Ends a forked copy of the shell without running destructors or atexit
+/// handlers that belong to the parent.
+fn exit_child(status: i32) -> ! {
+    flush_stdio();
+    unsafe { libc::_exit(status) }
+}
+
+/// The status a forked copy of the shell ends with after running something.
+fn child_status(result: Result<i32, ShellError>) -> i32 {
+    match result {
+        Ok(status) | Err(ShellError::Exit(status)) | Err(ShellError::Return(status)) => status,
+        Err(ShellError::Interrupted) => 130,
+        Err(ShellError::Break(_)) | Err(ShellError::Continue(_)) => 0,
+        Err(e) => {
+            eprintln!("nexusshell: {}", e);
+            1
+        }
+    }
+}
+
+/// Splits the leading `-xyz` and `+xyz` options of `declare`, `local`,
+/// `export` and `readonly` from their operands, returning the letters to
+/// turn on, those to turn off, and the operands.
+fn split_declare_flags(args: &[String]) -> (String, String, Vec<String>) {
+    let mut on = String::new();
+    let mut off = String::new();
+    let mut operands = Vec::new();
+    let mut args = args.iter();
+    for arg in args.by_ref() {
+        if arg == "--" {
+            break;
+        }
+        match (arg.strip_prefix('-'), arg.strip_prefix('+')) {
+            (Some(letters), _) if !letters.is_empty() => on.push_str(letters),
+            (_, Some(letters)) if !letters.is_empty() => off.push_str(letters),
+            _ => {
+                operands.push(arg.clone());
+                break;
+            }
+        }
+    }
+    operands.extend(args.cloned());
+    (on, off, operands)
+}
+
+/// A word as `set -x` shows it, quoted unless it is plain text.
+fn trace_quote(word: &str) -> String {
+    if !word.is_empty() && word.chars().all(|c| c.is_ascii_alphanumeric() || "-_./:,+=@%^".contains(c)) {
+        word.to_string()
+    } else {
+        expand::quote(word)
+    }
+}
+
+fn hostname() -> String {
+    whoami::fallible::hostname().unwrap_or_else(|_| "localhost".to_string())
+}
+
+// Error conversion implementations
+
+impl Shell {
+    pub async fn new() -> Result<Self, ShellError> {
+        let mut variables = HashMap::new();
+        let mut attributes = HashMap::new();
+        let exported = Attributes { exported: true, ..Attributes::default() };
+        
+        // Initialize environment variables
+        for (key, value) in env::vars() {
+            variables.insert(key.clone(), value);
+            attributes.insert(key, exported);
+        }
+        for name in ["PWD", "OLDPWD", "SHLVL"] {
+            attributes.insert(name.to_string(), exported);
+        }
+        
+        // Set additional shell variables
+        variables.insert("SHELL".to_string(), env::current_exe()
+            .unwrap_or_else(|_| PathBuf::from("nexusshell"))
+            .display().to_string());
+        variables.insert("USER".to_string(), whoami::username());
+        variables.insert("HOME".to_string(), env::var("HOME").unwrap_or_else(|_| "/".to_string()));
+        variables.insert("HOSTNAME".to_string(), hostname());
+        variables.insert("PS1".to_string(), "nexus$ ".to_string());
+        variables.insert("PPID".to_string(), nix::unistd::getppid().to_string());
+        // An inherited IFS is ignored; the shell always starts with the default
+        variables.insert("IFS".to_string(), " \t\n".to_string());
+        attributes.remove("IFS");
+        if let Ok(dir) = env::current_dir() {
+            variables.insert("PWD".to_string(), dir.display().to_string());
+        }
+        // Each nested shell counts one level deeper
+        let level = env::var("SHLVL").ok().and_then(|level| level.parse::<i64>().ok()).unwrap_or(0) + 1;
+        variables.insert("SHLVL".to_string(), level.to_string());
+        
+        let mut readline = LineEditor::new().map_err(|e| ShellError::IoError(io::Error::other(e)))?;
+        readline.set_helper(Some(NexusHelper::default()));
+        
+        // Configure readline behavior  
+        readline.set_auto_add_history(true);
+        readline.set_history_ignore_space(true);
+        readline.set_completion_type(rustyline::CompletionType::List);
+        
+        // Enable history functionality
+        let history_file = env::var("HOME").unwrap_or_else(|_| ".".to_string()) + "/.nexusshell_history";
+        let _ = readline.load_history(&history_file);
+        
+        Ok(Shell {
+            variables: Arc::new(RwLock::new(variables)),
+            attributes: Arc::new(RwLock::new(attributes)),
+            current_dir: Arc::new(RwLock::new(env::current_dir().unwrap_or_else(|_| PathBuf::from("/")))),
+            exit_code: Arc::new(RwLock::new(0)),
+            readline: Arc::new(Mutex::new(readline)),
+            startup_time: Instant::now(),
+            session_id: uuid::Uuid::new_v4().to_string(),
+            history: Arc::new(RwLock::new(Vec::new())),
+            aliases: Arc::new(RwLock::new(HashMap::new())),
+            jobs: Arc::new(RwLock::new(JobTable::default())),
+            terminal: None,
+            functions: Arc::new(RwLock::new(HashMap::new())),
+            frames: Arc::new(RwLock::new(Vec::new())),
+            arrays: Arc::new(RwLock::new(HashMap::new())),
+            options: Arc::new(RwLock::new(ShellOptions::default())),
+            shopt: Arc::new(RwLock::new(ShoptOptions::default())),
+            arg0: "nexusshell".to_string(),
+            positional: Arc::new(RwLock::new(Vec::new())),
+            loop_depth: Arc::new(RwLock::new(0)),
+            sourcing: Arc::new(RwLock::new(0)),
+            errexit_suppressed: Arc::new(RwLock::new(0)),
+            traps: Arc::new(RwLock::new(Traps::default())),
+            shell_pid: std::process::id(),
+            last_background: Arc::new(RwLock::new(None)),
+            interactive: false,
+            line: Arc::new(RwLock::new((0, 0))),
+            dynamic: Arc::new(RwLock::new(DynamicVariables::default())),
+            substitution_status: Arc::new(RwLock::new(None)),
+            process_substitutions: Arc::new(RwLock::new(Vec::new())),
+            command_count: Arc::new(RwLock::new(0)),
+            error_count: Arc::new(RwLock::new(0)),
+            last_command_time: Arc::new(RwLock::new(Instant::now())),
+        })
+    }
+
+    /// Runs the shell as its command line asks and returns its exit status.
+    pub async fn start(&mut self, cli: Cli) -> Result<i32, Box<dyn std::error::Error>> {
+        let mut args = cli.args.into_iter();
+        let interactive = cli.interactive
+            || cli.command.is_none() && (cli.stdin || args.len() == 0) && nix::unistd::isatty(0).unwrap_or(false);
+        self.interactive = interactive;
+        let source = if let Some(command) = cli.command {
+            if let Some(name) = args.next() {
+                self.arg0 = name;
+            }
+            Some(ScriptSource::Text(command))
+        } else if !cli.stdin && args.len() > 0 {
+            let path = args.next().unwrap();
+            let text = std::fs::read_to_string(&path);
+            self.arg0 = path.clone();
+            match text {
+                Ok(text) => Some(ScriptSource::Text(text)),
+                Err(e) => {
+                    let error = match e.kind() {
+                        io::ErrorKind::NotFound => ShellError::FileNotFound(path),
+                        io::ErrorKind::PermissionDenied => ShellError::PermissionDenied(path),
+                        _ => ShellError::IoError(e),
+                    };
+                    eprintln!("nexusshell: {}", error);
+                    return Ok(127);
+                }
+            }
+        } else if !interactive {
+            Some(ScriptSource::Stdin(Streams::standard().stdin))
+        } else {
+            None
+        };
+        *self.positional.write().await = args.collect();
+
+        if cli.login {
+            self.read_profiles().await;
+        }
+        match source {
+            Some(source) => {
+                let name = self.arg0.clone();
+                let status = match self.run_script(source, &name).await {
+                    Ok(status) | Err(ShellError::Exit(status)) => status,
+                    Err(ShellError::Interrupted) => 130,
+                    Err(e) => {
+                        self.run_exit_trap(1).await;
+                        return Err(e.into());
+                    }
+                };
+                Ok(self.run_exit_trap(status).await)
+            }
+            None => self.run().await,
+        }
+    }
+
+    /// Startup files of a login shell.
+    async fn read_profiles(&mut self) {
+        let home = env::var("HOME").unwrap_or_else(|_| ".".to_string());
+        for path in ["/etc/profile".to_string(), home + "/.profile"] {
+            if let Ok(text) = std::fs::read_to_string(&path) {
+                let _ = self.run_script(ScriptSource::Text(text), &path).await;
+            }
+        }
+    }
+
+    pub async fn run(&mut self) -> Result<i32, Box<dyn std::error::Error>> {
+        self.terminal = Terminal::acquire();
+        self.options.write().await.monitor = self.terminal.is_some();
+        signals::catch_interrupt();
+        self.display_welcome_banner().await;
+        
+        loop {
+            self.notify_jobs().await;
+            if let Err(ShellError::Exit(code)) = self.run_pending_traps().await {
+                let code = self.run_exit_trap(code).await;
+                self.save_history().await;
+                return Ok(code);
+            }
+            let prompt = self.generate_prompt().await?;
+            
+            let line = {
+                let mut readline = self.readline.lock().await;
+                readline.readline(&prompt)
+            };
+            
+            match line {
+                Ok(line) => {
+                    signals::take_interrupt();
+                    if self.options.read().await.verbose {
+                        eprintln!("{}", line);
+                    }
+                    let input = line.trim();
+                    if input.is_empty() {
+                        continue;
+                    }
+
+                    // Add to rustyline history
+                    {
+                        let mut readline = self.readline.lock().await;
+                        readline.add_history_entry(input).ok();
+                    }
+
+                    // Add to internal history
+                    {
+                        let mut history = self.history.write().await;
+                        history.push(input.to_string());
+                    }
+
+                    // Update statistics
+                    {
+                        let mut count = self.command_count.write().await;
+                        *count += 1;
+                        let mut last_time = self.last_command_time.write().await;
+                        *last_time = Instant::now();
+                    }
+                    
+                    let start_time = Instant::now();
+                    match self.execute_command(input).await {
+                        Ok(exit_code) => {
+                            let duration = start_time.elapsed();
+                            if exit_code != 0 {
+                                let mut error_count = self.error_count.write().await;
+                                *error_count += 1;
+                                println!("{}[WARNING] Exit code: {} (took {:?}){}", YELLOW, exit_code, duration, RESET);
+                            } else if duration.as_millis() > 100 {
+                                println!("{}[INFO] Command completed in {:?}{}", DIM, duration, RESET);
+                            }
+                        }
+                        Err(ShellError::Interrupted) => {
+                            println!();
+                            *self.exit_code.write().await = 130;
+                        }
+                        Err(ShellError::Exit(code)) => {
+                            let code = self.run_exit_trap(code).await;
+                            self.save_history().await;
+                            return Ok(code);
+                        }
+                        Err(e) => {
+                            let mut error_count = self.error_count.write().await;
+                            *error_count += 1;
+                            println!("{}[ERROR] Error: {}{}", RED, e, RESET);
+                        }
+                    }
+                }
+                // Ctrl-C at the prompt abandons the line
+                Err(ReadlineError::Interrupted) => {
+                    *self.exit_code.write().await = 130;
+                }
+                Err(ReadlineError::Eof) if self.options.read().await.ignoreeof => {
+                    println!("Use \"exit\" to leave the shell.");
+                }
+                Err(_) => {
+                    let status = *self.exit_code.read().await;
+                    *self.exit_code.write().await = self.run_exit_trap(status).await;
+                    // Save history before exit
+                    self.save_history().await;
+                    println!("\n{}[EXIT] Goodbye from NexusShell!{}", BRIGHT_CYAN, RESET);
+                    break;
+                }
+            }
+        }
+        
+        Ok(*self.exit_code.read().await)
+    }
+
+    async fn display_welcome_banner(&self) {
+        println!("{}", BRIGHT_CYAN);
+        println!("╔══════════════════════════════════════════════════════════════════════════╗");
+        println!("║                                                                          ║");
+        println!("║  {}>> NexusShell v1.0.0 - World's Most Beautiful Command Shell <<{}        ║", BRIGHT_YELLOW, BRIGHT_CYAN);
+        println!("║                                                                          ║");
+        println!("║  {}* Features: Full POSIX compatibility with modern UI *{}                ║", BRIGHT_GREEN, BRIGHT_CYAN);
+        println!("║                                                                          ║");
+        println!("║  {}[?] Type 'help' for commands  [*] Beautiful colors enabled [?]{}        ║", BLUE, BRIGHT_CYAN);
+        println!("║                                                                          ║");
+        println!("╚══════════════════════════════════════════════════════════════════════════╝");
+        println!("{}", RESET);
+        println!("{}>> Pro tip: Try 'help', 'env', or any command!{}", DIM, RESET);
+        println!();
+    }
+
+    async fn generate_prompt(&self) -> Result<String, ShellError> {
+        let current_dir = self.current_dir.read().await;
+        let username = whoami::username();
+        let hostname = hostname();
+        
+        // Get current directory name (not full path)
+        let dir_name = current_dir
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or("~");
+        
+        // Create clean and beautiful prompt
+        let prompt = format!(
+            "{}{}{}@{}{} {}{}{}{}> ",
+            BRIGHT_GREEN, username, RESET,
+            BRIGHT_CYAN, hostname, RESET,
+            BRIGHT_YELLOW, dir_name, RESET
+        );
+        
+        Ok(prompt)
+    }
+
+    /// Runs a line typed at the prompt, where aliases apply.
+    async fn execute_command(&mut self, input: &str) -> Result<i32, ShellError> {
+        let aliases = self.aliases.read().await.clone();
+        let list = parser::parse_with_aliases(input, &aliases).map_err(|e| ShellError::SyntaxError(e.to_string()))?;
+        self.execute_list(&list).await
+    }
+
+    async fn execute_list(&mut self, list: &List) -> Result<i32, ShellError> {
+        let mut last_exit_code = 0;
+        for item in &list.items {
+            last_exit_code = if item.background {
+                let status = self.execute_background_command(&item.and_or).await?;
+                *self.exit_code.write().await = status;
+                status
+            } else {
+                self.execute_and_or(&item.and_or).await?
+            };
+        }
+        Ok(last_exit_code)
+    }
+
+    async fn execute_and_or(&mut self, and_or: &AndOr) -> Result<i32, ShellError> {
+        let mut last_exit_code = self.execute_pipeline(&and_or.first, and_or.rest.is_empty()).await?;
+        for (i, (connector, pipeline)) in and_or.rest.iter().enumerate() {
+            let run = match connector {
+                Connector::And => last_exit_code == 0,
+                Connector::Or => last_exit_code != 0,
+            };
+            if run {
+                last_exit_code = self.execute_pipeline(pipeline, i + 1 == and_or.rest.len()).await?;
+            }
+        }
+        Ok(last_exit_code)
+    }
+
+    /// Runs a pipeline; `last` says whether it ends its `&&`/`||` list,
+    /// which makes its failure subject to `set -e` unless it is negated.
+    async fn execute_pipeline(&mut self, pipeline: &Pipeline, last: bool) -> Result<i32, ShellError> {
+        let exempt = !last || pipeline.negated;
+        if exempt {
+            self.suppress_errexit().await;
+        }
+        let result = if pipeline.commands.len() == 1 {
+            self.execute_single_command(&pipeline.commands[0]).await
+        } else {
+            self.execute_pipeline_stages(&pipeline.commands, &pipeline.text, false).await
+        };
+        if exempt {
+            self.resume_errexit().await;
+        }
+        let exit_code = result?;
+        if signals::take_interrupt() {
+            return Err(ShellError::Interrupted);
+        }
+        self.run_pending_traps().await?;
+        if pipeline.commands.len() == 1 {
+            self.set_pipestatus(vec![exit_code]).await;
+        }
+        let exit_code = if pipeline.negated { (exit_code == 0) as i32 } else { exit_code };
+        *self.exit_code.write().await = exit_code;
+
+        // Groups, conditionals and loops are not checked as a whole: the
+        // commands in them already were
+        let group = matches!(
+            &pipeline.commands[..],
+            [Command::Compound(
+                CompoundCommand::BraceGroup(_)
+                    | CompoundCommand::If { .. }
+                    | CompoundCommand::For { .. }
+                    | CompoundCommand::While { .. }
+                    | CompoundCommand::Until { .. }
+                    | CompoundCommand::Case { .. },
+                _
+            )]
+        );
+        if exit_code != 0 && !exempt && !group && *self.errexit_suppressed.read().await == 0 {
+            self.run_trap(Condition::Err).await?;
+            self.check_errexit(exit_code).await?;
+        }
+        Ok(exit_code)
+    }
+
+    /// Keeps `set -e` from applying until the matching `resume_errexit`.
+    async fn suppress_errexit(&self) {
+        *self.errexit_suppressed.write().await += 1;
+    }
+
+    async fn resume_errexit(&self) {
+        *self.errexit_suppressed.write().await -= 1;
+    }
+
+    /// Under `set -e`, ends the shell with `status`, that of a command that
+    /// failed where failures are not expected.
+    async fn check_errexit(&self, status: i32) -> Result<(), ShellError> {
+        if self.options.read().await.errexit && *self.errexit_suppressed.read().await == 0 {
+            return Err(ShellError::Exit(status));
+        }
+        Ok(())
+    }
+
+    /// Sets `PIPESTATUS` to the statuses of the stages of the last pipeline.
+    async fn set_pipestatus(&self, statuses: Vec<i32>) {
+        let elements = statuses.into_iter().map(|status| status.to_string()).enumerate().collect();
+        self.arrays.write().await.insert("PIPESTATUS".to_string(), Array::Indexed(elements));
+    }
+
+    async fn execute_single_command(&mut self, command: &Command) -> Result<i32, ShellError> {
+        let mark = self.process_substitution_mark().await;
+        let result = match command {
+            Command::Simple(simple) => self.execute_simple_command(simple).await,
+            Command::Compound(compound, redirects) => {
+                Box::pin(self.execute_compound_command(compound, redirects)).await
+            }
+            Command::FunctionDef(function) => {
+                self.define_function(function).await;
+                Ok(0)
+            }
+        };
+        self.finish_process_substitutions(mark, false).await;
+        result
+    }
+
+    /// Runs the commands of a pipeline as one job, each stage in its own process.
+    async fn execute_pipeline_stages(&mut self, commands: &[Command], text: &str, background: bool) -> Result<i32, ShellError> {
+        let mark = self.process_substitution_mark().await;
+        let result = self.start_pipeline_job(commands, text, background).await;
+        self.finish_process_substitutions(mark, background).await;
+        result
+    }
+
+    /// Starts the stages of a pipeline, then waits for them or, for a
+    /// background pipeline, enters them in the job table.
+    async fn start_pipeline_job(&mut self, commands: &[Command], text: &str, background: bool) -> Result<i32, ShellError> {
+        let mut setup = self.job_setup(!background).await;
+        let mut job = Job::new(text);
+        // For each stage, the status it failed to start with
+        let mut failed = Vec::with_capacity(commands.len());
+        // Read end of the pipe feeding the next stage
+        let mut previous_read: Option<RawFd> = None;
+
+        for (i, stage) in commands.iter().enumerate() {
+            let is_last = i == commands.len() - 1;
+            let mut saved = SavedFds::default();
+            if let Some(read) = previous_read.take() {
+                saved.redirect(read, 0)?;
+                let _ = nix::unistd::close(read);
+            }
+            if !is_last {
+                let (read, write) = nix::unistd::pipe2(OFlag::O_CLOEXEC)
+                    .map_err(|errno| ShellError::IoError(errno.into()))?;
+                saved.redirect(write, 1)?;
+                let _ = nix::unistd::close(write);
+                previous_read = Some(read);
+            }
+
+            let started = self.start_pipeline_stage(stage, &mut saved, previous_read, &setup).await?;
+            drop(saved);
+            match started {
+                Ok(pid) => {
+                    setup.started(pid);
+                    job.push(pid);
+                    failed.push(None);
+                }
+                Err(code) => failed.push(Some(code)),
+            }
+        }
+
+        if background {
+            if job.processes.is_empty() {
+                return Ok(failed.last().copied().flatten().unwrap_or(0));
+            }
+            self.add_background_job(job, &setup).await;
+            return Ok(0);
+        }
+        let mut statuses = if job.processes.is_empty() {
+            Vec::new().into_iter()
+        } else {
+            self.wait_for_foreground_statuses(job, &setup).await?.into_iter()
+        };
+        let statuses: Vec<i32> = failed.into_iter().map(|failed| failed.or_else(|| statuses.next()).unwrap_or(0)).collect();
+        // With pipefail the last stage to fail decides the status
+        let status = if self.options.read().await.pipefail {
+            statuses.iter().rev().find(|status| **status != 0).copied().unwrap_or(0)
+        } else {
+            statuses.last().copied().unwrap_or(0)
+        };
+        self.set_pipestatus(statuses).await;
+        Ok(status)
+    }
+
+    /// Starts one pipeline stage on the descriptors the caller has set up.
+    /// External commands are spawned directly; builtins and compound
+    /// commands run in a forked copy of the shell.  `next_read` is the read
+    /// end of the stage's own output pipe, which a forked stage must not keep
+    /// open.  The inner error is the exit status of a stage that could not
+    /// be started.
+    async fn start_pipeline_stage(&mut self, stage: &Command, saved: &mut SavedFds, next_read: Option<RawFd>, setup: &JobSetup) -> Result<Result<Pid, i32>, ShellError> {
+        let (argv, redirects) = match stage {
+            Command::Simple(simple) => (Some(self.expand_words(&simple.words).await?), &simple.redirects),
+            Command::Compound(_, redirects) => (None, redirects),
+            Command::FunctionDef(_) => (None, &Vec::new()),
+        };
+        if let Err(e) = self.apply_redirections_to(saved, redirects).await {
+            eprintln!("nexusshell: {}", e);
+            return Ok(Err(1));
+        }
+
+        // Prefix assignments are in place while the stage starts, so that
+        // it inherits them
+        let hidden = match (stage, &argv) {
+            (Command::Simple(simple), Some(argv)) if !argv.is_empty() => {
+                let hidden = self.apply_prefix_assignments(&simple.assignments).await?;
+                self.trace(&argv.iter().map(|arg| trace_quote(arg)).collect::<Vec<_>>().join(" ")).await;
+                hidden
+            }
+            _ => Vec::new(),
+        };
+        let started = self.start_stage_process(stage, argv, next_read, setup).await;
+        self.restore_variables(hidden).await;
+        started
+    }
+
+    /// Spawns or forks the process of a pipeline stage.
+    async fn start_stage_process(&mut self, stage: &Command, argv: Option<Vec<String>>, next_read: Option<RawFd>, setup: &JobSetup) -> Result<Result<Pid, i32>, ShellError> {
+        if let Some(argv) = &argv {
+            let external = match argv.first() {
+                Some(name) => !is_builtin(name) && self.find_function(name).await.is_none(),
+                None => false,
+            };
+            if external {
+                return Ok(self.spawn_external(argv, setup).await);
+            }
+        }
+
+        flush_stdio();
+        match unsafe { nix::unistd::fork() } {
+            Ok(ForkResult::Parent { child }) => Ok(Ok(child)),
+            Ok(ForkResult::Child) => {
+                setup.enter_child();
+                self.enter_subshell().await;
+                if let Some(fd) = next_read {
+                    let _ = nix::unistd::close(fd);
+                }
+                let result = match (stage, argv) {
+                    (Command::Compound(compound, _), _) => Box::pin(self.execute_compound_command(compound, &[])).await,
+                    (Command::FunctionDef(_), _) => Box::pin(self.execute_single_command(stage)).await,
+                    (_, Some(argv)) if !argv.is_empty() => Box::pin(self.execute_internal(&argv)).await,
+                    _ => Ok(0),
+                };
+                exit_child(self.run_exit_trap(child_status(result)).await)
+            }
+            Err(errno) => Err(ShellError::IoError(errno.into())),
+        }
+    }
+
+    /// Spawns an external command as a member of the job described by `setup`.
+    /// On failure the error is the exit status to report.
+    async fn spawn_external(&self, argv: &[String], setup: &JobSetup) -> Result<Pid, i32> {
+        let mut command = std::process::Command::new(&argv[0]);
+        command.args(&argv[1..]);
+        command.env_clear();
+        command.envs(self.environment().await);
+        command.current_dir(&*self.current_dir.read().await);
+        setup.configure(&mut command);
+        match command.spawn() {
+            Ok(child) => Ok(Pid::from_raw(child.id() as i32)),
+            Err(_) => {
+                eprintln!("{}: command not found", argv[0]);
+                Err(127)
+            }
+        }
+    }
+
+    /// Under `set -x`, writes `command` to stderr after the expansion of
+    /// `PS4`.
+    async fn trace(&mut self, command: &str) {
+        if !self.options.read().await.xtrace {
+            return;
+        }
+        let ps4 = self.variables.read().await.get("PS4").cloned().unwrap_or_else(|| "+ ".to_string());
+        // Commands run while expanding PS4 are not traced themselves
+        self.options.write().await.xtrace = false;
+        let prefix = self.expand_fragment(&ps4, true).await.unwrap_or(ps4);
+        self.options.write().await.xtrace = true;
+        eprintln!("{}{}", prefix, command);
+    }
+
+    /// Turns a freshly forked child into a subshell, which does no job
+    /// control of its own and starts without most of the parent's traps.
+    async fn enter_subshell(&mut self) {
+        self.terminal = None;
+        self.options.write().await.monitor = false;
+        self.reset_traps().await;
+    }
+
+    async fn execute_simple_command(&mut self, command: &SimpleCommand) -> Result<i32, ShellError> {
+        self.line.write().await.1 = command.line;
+        // Within a trap, `BASH_COMMAND` stays the command the trap interrupted
+        if !self.in_trap().await {
+            self.variables.write().await.insert("BASH_COMMAND".to_string(), traps::command_text(command));
+        }
+        self.run_trap(Condition::Debug).await?;
+        *self.substitution_status.write().await = None;
+        // `declare arr=(...)`, `local arr=(...)` and the like declare the
+        // name, then assign the array
+        let declaration = matches!(command.words.first().map(|w| w.raw.as_str()), Some("declare" | "local" | "export" | "readonly"));
+        let (operands, array_assignments) = if declaration {
+            arrays::split_declaration(&command.words)
+        } else {
+            (Vec::new(), Vec::new())
+        };
+        let argv = if declaration {
+            self.expand_declaration(&operands).await?
+        } else {
+            self.expand_words(&command.words).await?
+        };
+        // `$_` is the last argument of the previous command
+        if let Some(last) = argv.last() {
+            self.variables.write().await.insert("_".to_string(), last.clone());
+        }
+        let Some(saved) = self.redirect_or_report(&command.redirects).await else {
+            return Ok(1);
+        };
+        
+        // Assignment-only command: set shell variables
+        if argv.is_empty() {
+            for assignment in &command.assignments {
+                self.handle_variable_assignment(assignment).await?;
+            }
+            return Ok(self.substitution_status.read().await.unwrap_or(0));
+        }
+        
+        let mut readonly = Vec::with_capacity(array_assignments.len());
+        for assignment in &array_assignments {
+            readonly.push(self.attributes(&assignment.name).await.readonly);
+        }
+
+        // Prefix assignments hold, exported, for this command only
+        let hidden = self.apply_prefix_assignments(&command.assignments).await?;
+        self.trace(&argv.iter().map(|arg| trace_quote(arg)).collect::<Vec<_>>().join(" ")).await;
+        let result = if is_builtin(&argv[0]) || self.find_function(&argv[0]).await.is_some() {
+            Box::pin(self.execute_internal(&argv)).await
+        } else {
+            self.execute_external_command(&argv).await
+        };
+        self.restore_variables(hidden).await;
+
+        let status = result?;
+        // `exec` without a command changes the shell's own descriptors
+        if argv.len() == 1 && argv[0] == "exec" && status == 0 {
+            saved.keep();
+        }
+        if status == 0 {
+            for (assignment, was_readonly) in array_assignments.iter().zip(readonly) {
+                // A declaration making the array readonly still assigns it
+                let made_readonly = !was_readonly && self.attributes(&assignment.name).await.readonly;
+                if made_readonly {
+                    self.set_attribute(&assignment.name, 'r', false).await;
+                }
+                let assigned = self.handle_variable_assignment(assignment).await;
+                if made_readonly {
+                    self.set_attribute(&assignment.name, 'r', true).await;
+                }
+                assigned?;
+            }
+        }
+        Ok(status)
+    }
+
+    /// Runs the function or builtin `argv[0]`; functions take precedence.
+    async fn execute_internal(&mut self, argv: &[String]) -> Result<i32, ShellError> {
+        if let Some(function) = self.find_function(&argv[0]).await {
+            return self.call_function(&function, argv).await;
+        }
+        self.execute_builtin(argv).await
+    }
+
+    async fn execute_builtin(&mut self, argv: &[String]) -> Result<i32, ShellError> {
+        let mut io = Streams::standard();
+        let io = &mut io;
+        let args = &argv[1..];
+        let result = match argv[0].as_str() {
+            "cd" => self.builtin_cd(args, io).await,
+            "pwd" => self.builtin_pwd(io).await,
+            "echo" => self.builtin_echo(args, io).await,
+            "help" => self.builtin_help(io).await,
+            "export" => self.builtin_export(args, io).await,
+            "readonly" => self.builtin_readonly(args, io).await,
+            "env" => self.builtin_env(io).await,
+            "ls" => self.builtin_ls(args, io).await,
+            "exit" => self.builtin_exit(args, io).await,
+            "trap" => self.builtin_trap(args, io).await,
+            "break" | "continue" => self.builtin_break(&argv[0], args, io).await,
+            "shift" => self.builtin_shift(args, io).await,
+            "history" => self.builtin_history(io).await,
+            "alias" if args.is_empty() => self.builtin_show_aliases(io).await,
+            "alias" => self.builtin_alias(args, io).await,
+            "unset" => self.builtin_unset(args, io).await,
+            "which" => self.builtin_which(args, io).await,
+            "type" => self.builtin_type(args, io).await,
+            "jobs" => self.builtin_jobs(args, io).await,
+            "fg" => self.builtin_fg(args, io).await,
+            "bg" => self.builtin_bg(args, io).await,
+            "wait" => self.builtin_wait(args, io).await,
+            "kill" => self.builtin_kill(args, io).await,
+            "disown" => self.builtin_disown(args, io).await,
+            "test" | "[" => self.builtin_test(argv, io).await,
+            "read" => self.builtin_read(args, io).await,
+            "printf" => self.builtin_printf(args, io).await,
+            "source" | "." => self.builtin_source(args, io).await,
+            "return" => self.builtin_return(args, io).await,
+            "set" => self.builtin_set(args, io).await,
+            "shopt" => self.builtin_shopt(args, io).await,
+            "declare" => self.builtin_declare(args, io).await,
+            "local" => self.builtin_local(args, io).await,
+            "pushd" => self.builtin_pushd(args, io).await,
+            "popd" => self.builtin_popd(io).await,
+            "dirs" => self.builtin_dirs(io).await,
+            "exec" => self.builtin_exec(args, io).await,
+            "eval" => self.builtin_eval(args).await,
+            "let" => self.builtin_let(args, io).await,
+            "stats" | "statistics" => self.builtin_stats(io).await,
+            name => Err(ShellError::CommandNotFound(name.to_string())),
+        };
+        match result {
+            Err(ShellError::IoError(e)) if e.kind() == io::ErrorKind::Interrupted => Err(ShellError::Interrupted),
+            // A write to a closed or broken stream fails the builtin, not the shell
+            Err(ShellError::IoError(e)) => {
+                let _ = writeln!(io.stderr, "nexusshell: {}: {}", argv[0], e);
+                Ok(1)
+            }
+            result => result,
+        }
+    }
+
+    async fn execute_compound_command(&mut self, command: &CompoundCommand, redirects: &[Redirect]) -> Result<i32, ShellError> {
+        let Some(_saved) = self.redirect_or_report(redirects).await else {
+            return Ok(1);
+        };
+        let looping = matches!(command, CompoundCommand::For { .. } | CompoundCommand::While { .. } | CompoundCommand::Until { .. });
+        if looping {
+            *self.loop_depth.write().await += 1;
+        }
+        let result = match command {
+            CompoundCommand::BraceGroup(list) => self.execute_list(list).await,
+            CompoundCommand::Subshell { body, text } => self.execute_subshell(body, text).await,
+            CompoundCommand::If { branches, else_branch } => {
+                self.execute_if_statement(branches, else_branch.as_ref()).await
+            }
+            CompoundCommand::For { var, words, body } => {
+                self.execute_for_loop(var, words.as_deref(), body).await
+            }
+            CompoundCommand::While { condition, body } => {
+                self.execute_while_loop(condition, body, false).await
+            }
+            CompoundCommand::Until { condition, body } => {
+                self.execute_while_loop(condition, body, true).await
+            }
+            CompoundCommand::Case { word, items } => self.execute_case(word, items).await,
+            CompoundCommand::Conditional(words) => match self.expand_words(words).await {
+                Ok(args) => {
+                    let traced: Vec<String> = args.iter().map(|arg| trace_quote(arg)).collect();
+                    self.trace(&format!("[[ {} ]]", traced.join(" "))).await;
+                    self.builtin_conditional_expression(&args, &mut Streams::standard()).await
+                }
+                Err(e) => Err(e),
+            },
+            CompoundCommand::Arithmetic(expression) => self.execute_arithmetic_command(expression).await,
+        };
+        if looping {
+            *self.loop_depth.write().await -= 1;
+        }
+        result
+    }
+
+    /// Applies a command's redirections, printing the error and returning
+    /// `None` if one of them fails.
+    async fn redirect_or_report(&mut self, redirects: &[Redirect]) -> Option<SavedFds> {
+        match self.apply_redirections(redirects).await {
+            Ok(saved) => Some(saved),
+            Err(e) => {
+                eprintln!("nexusshell: {}", e);
+                None
+            }
+        }
+    }
+
+    async fn handle_variable_assignment(&mut self, assignment: &Assignment) -> Result<i32, ShellError> {
+        let operator = if assignment.append { "+=" } else { "=" };
+        if let Some(words) = &assignment.array {
+            self.assign_array(&assignment.name, words, assignment.append).await?;
+            let words: Vec<&str> = words.iter().map(|word| word.raw.as_str()).collect();
+            self.trace(&format!("{}{}({})", assignment.name, operator, words.join(" "))).await;
+            return Ok(0);
+        }
+        let value = self.expand_assignment_value(&assignment.value).await?;
+        let name = match &assignment.subscript {
+            Some(subscript) => format!("{}[{}]", assignment.name, subscript),
+            None => assignment.name.clone(),
+        };
+        self.trace(&format!("{}{}{}", name, operator, trace_quote(&value))).await;
+        if let Some(subscript) = &assignment.subscript {
+            let subscript = self.expand_fragment(subscript, false).await?;
+            self.assign_element(&assignment.name, &subscript, value, assignment.append).await?;
+            return Ok(0);
+        }
+        self.assign_scalar(&assignment.name, value, assignment.append).await?;
+        Ok(0)
+    }
+
+    async fn builtin_env(&self, io: &mut Streams) -> Result<i32, ShellError> {
+        let environment = self.environment().await;
+        writeln!(io.stdout, "{}[ENV] Environment Variables:{}", BRIGHT_GREEN, RESET)?;
+        writeln!(io.stdout, "{}═══════════════════════════{}", BRIGHT_GREEN, RESET)?;
+        for (key, value) in environment.iter() {
+            writeln!(io.stdout, "{}{}{}={}{}{}", CYAN, key, RESET, YELLOW, value, RESET)?;
+        }
+        Ok(0)
+    }
+
+    async fn builtin_ls(&self, args: &[String], io: &mut Streams) -> Result<i32, ShellError> {
+        let path = args.iter().find(|a| !a.starts_with('-')).map_or(".", |a| a.as_str());
+        
+        match std::fs::read_dir(path) {
+            Ok(entries) => {
+                for entry in entries.flatten() {
+                    let name = entry.file_name();
+                    writeln!(io.stdout, "{}", name.to_string_lossy())?;
+                }
+                Ok(0)
+            }
+            Err(_) => {
+                writeln!(io.stderr, "ls: {}: No such file or directory", path)?;
+                Ok(1)
+            }
+        }
+    }
+
+    async fn builtin_cd(&mut self, args: &[String], io: &mut Streams) -> Result<i32, ShellError> {
+        let target = match args.first().map(String::as_str) {
+            // `cd -` goes back to the previous directory and prints it
+            Some("-") => match self.variables.read().await.get("OLDPWD") {
+                Some(dir) => {
+                    writeln!(io.stdout, "{}", dir)?;
+                    dir.clone()
+                }
+                None => {
+                    writeln!(io.stderr, "cd: OLDPWD not set")?;
+                    return Ok(1);
+                }
+            },
+            Some(dir) => dir.to_string(),
+            None => {
+                let variables = self.variables.read().await;
+                variables.get("HOME").cloned().unwrap_or_else(|| "/".to_string())
+            }
+        };
+        
+        let path = Path::new(&target);
+        if path.exists() && path.is_dir() {
+            let mut current_dir = self.current_dir.write().await;
+            let old = std::mem::replace(&mut *current_dir, path.canonicalize()?);
+            env::set_current_dir(&*current_dir)?;
+            let (old, new) = (old.display().to_string(), current_dir.display().to_string());
+            let mut variables = self.variables.write().await;
+            variables.insert("OLDPWD".to_string(), old);
+            variables.insert("PWD".to_string(), new);
+            Ok(0)
+        } else {
+            writeln!(io.stderr, "cd: {}: No such file or directory", target)?;
+            Ok(1)
+        }
+    }
+
+    async fn builtin_pwd(&self, io: &mut Streams) -> Result<i32, ShellError> {
+        let current_dir = self.current_dir.read().await;
+        writeln!(io.stdout, "{}[DIR] {}{}", BRIGHT_BLUE, current_dir.display(), RESET)?;
+        Ok(0)
+    }
+
+    async fn builtin_echo(&self, args: &[String], io: &mut Streams) -> Result<i32, ShellError> {
+        let mut interpret_escapes = false;
+        let mut no_newline = false;
+        
+        let mut i = 0;
+        
+        // Parse options
+        while i < args.len() && args[i].starts_with('-') {
+            match args[i].as_str() {
+                "-e" => interpret_escapes = true,
+                "-E" => interpret_escapes = false,
+                "-n" => no_newline = true,
+                "-ne" | "-en" => {
+                    interpret_escapes = true;
+                    no_newline = true;
+                }
+                _ => break,
+            }
+            i += 1;
+        }
+        
+        // Join remaining arguments
+        let mut output = args[i..].join(" ");
+        
+        // Interpret escape sequences if -e flag is used
+        if interpret_escapes {
+            output = output
+                .replace("\\n", "\n")
+                .replace("\\t", "\t")
+                .replace("\\r", "\r")
+                .replace("\\\\", "\\")
+                .replace("\\\"", "\"")
+                .replace("\\'", "'");
+        }
+        
+        if no_newline {
+            write!(io.stdout, "{}", output)?;
+        } else {
+            writeln!(io.stdout, "{}", output)?;
+        }
+        
+        Ok(0)
+    }
+
+    async fn builtin_export(&mut self, args: &[String], io: &mut Streams) -> Result<i32, ShellError> {
+        let (on, off, operands) = split_declare_flags(args);
+        if let Some(flag) = on.chars().chain(off.chars()).find(|flag| !"np".contains(*flag)) {
+            writeln!(io.stderr, "export: -{}: invalid option", flag)?;
+            writeln!(io.stderr, "export: usage: export [-n] [name[=value] ...] or export -p")?;
+            return Ok(2);
+        }
+        if operands.is_empty() {
+            self.print_declarations("export", &[], "x", io).await?;
+            return Ok(0);
+        }
+        // `export -n` takes the export attribute away
+        let (on, off) = if on.contains('n') { ("", "x") } else { ("x", "") };
+        self.declare_variables("export", (on, off), &operands, false, io).await
+    }
+
+    async fn builtin_readonly(&mut self, args: &[String], io: &mut Streams) -> Result<i32, ShellError> {
+        let (on, off, operands) = split_declare_flags(args);
+        if let Some(flag) = on.chars().chain(off.chars()).find(|flag| !"aAp".contains(*flag)) {
+            writeln!(io.stderr, "readonly: -{}: invalid option", flag)?;
+            writeln!(io.stderr, "readonly: usage: readonly [-aA] [name[=value] ...] or readonly -p")?;
+            return Ok(2);
+        }
+        if operands.is_empty() {
+            self.print_declarations("readonly", &[], "r", io).await?;
+            return Ok(0);
+        }
+        let on = on.replace('p', "") + "r";
+        self.declare_variables("readonly", (&on, ""), &operands, false, io).await
+    }
+
+    async fn builtin_help(&self, io: &mut Streams) -> Result<i32, ShellError> {
+        writeln!(io.stdout)?;
+        writeln!(io.stdout, "{}╔══════════════════════════════════════════════════════════════════════════╗{}", BRIGHT_CYAN, RESET)?;
+        writeln!(io.stdout, "{}║{} {}>> NexusShell - World's Most Complete Command Shell >>{} {}║{}", BRIGHT_CYAN, RESET, BRIGHT_YELLOW, RESET, BRIGHT_CYAN, RESET)?;
+        writeln!(io.stdout, "{}╠══════════════════════════════════════════════════════════════════════════╣{}", BRIGHT_CYAN, RESET)?;
+        writeln!(io.stdout, "{}║{} {}[*] Built-in Commands:{} {}                                              ║{}", BRIGHT_CYAN, RESET, BOLD, RESET, BRIGHT_CYAN, RESET)?;
+        writeln!(io.stdout, "{}║{}                                                                          {}║{}", BRIGHT_CYAN, RESET, BRIGHT_CYAN, RESET)?;
+        
+        let commands = [
+            ("cd [DIR]", "Change directory", "[>]"),
+            ("pwd", "Print working directory", "[/]"),
+            ("echo [OPTIONS] TEXT", "Print text with options (-e, -n)", "[*]"),
+            ("printf FORMAT [ARGS]", "Formatted output", "[P]"),
+            ("export VAR=value", "Set environment variable", "[E]"),
+            ("env", "Display environment variables", "[?]"),
+            ("set", "Display all variables", "[S]"),
+            ("unset VAR", "Remove variable", "[X]"),
+            ("declare VAR=value", "Declare variable", "[D]"),
+            ("local VAR=value", "Declare local variable", "[L]"),
+            ("read VAR", "Read input into variable", "[R]"),
+            ("test / [ ]", "Test conditions", "[T]"),
+            ("[[ ]]", "Advanced conditional expressions", "[C]"),
+            ("alias NAME=VALUE", "Create command alias", "[A]"),
+            ("history", "Show command history", "[H]"),
+            ("jobs", "Show active jobs", "[J]"),
+            ("fg / bg [%JOB]", "Resume a job in the fore/background", "[F]"),
+            ("wait [%JOB|PID]", "Wait for jobs to finish", "[W]"),
+            ("kill [-SIG] %JOB|PID", "Send a signal to a job or process", "[K]"),
+            ("disown [%JOB]", "Remove a job from the job table", "[D]"),
+            ("which COMMAND", "Locate command", "[W]"),
+            ("type COMMAND", "Show command type", "[#]"),
+            ("source FILE", "Execute file in current shell", "[.]"),
+            ("stats", "Show performance statistics", "[S]"),
+            ("help", "Show this help message", "[?]"),
+            ("exit [N]", "Exit the shell", "[Q]"),
+            ("shift [N]", "Shift positional parameters", "[<]"),
+        ];
+        
+        for (cmd, desc, icon) in &commands {
+            writeln!(io.stdout, "{}║{} {}{} {:<20}{} - {:<30} {}║{}", 
+                BRIGHT_CYAN, RESET, icon, GREEN, cmd, RESET, desc, BRIGHT_CYAN, RESET)?;
+        }
+        
+        writeln!(io.stdout, "{}║{}                                                                          {}║{}", BRIGHT_CYAN, RESET, BRIGHT_CYAN, RESET)?;
+        writeln!(io.stdout, "{}║{} {}[+] Advanced Features:{} {}                                              ║{}", BRIGHT_CYAN, RESET, BOLD, RESET, BRIGHT_CYAN, RESET)?;
+        writeln!(io.stdout, "{}║{}                                                                          {}║{}", BRIGHT_CYAN, RESET, BRIGHT_CYAN, RESET)?;
+        
+        let features = [
+            ("[|] Pipelines", "cmd1 | cmd2 | cmd3"),
+            ("[>] Redirections", "cmd > file, cmd >> file, cmd < file"),
+            ("[$] Variables", "VAR=value, $VAR, ${VAR}"),
+            ("[%] Parameter Exp", "${VAR:-default}, ${VAR:=default}, ${#VAR}"),
+            ("[&] Command Sub", "$(command), `command`"),
+            ("[#] Arithmetic", "$((expression))"),
+            ("[~] Background Jobs", "command &"),
+            ("[^] Control Flow", "if/then/fi, for/do/done, while/do/done"),
+            ("[{}] Brace Expansion", "{a,b,c}"),
+            ("[*] Glob Patterns", "*.txt, file?.log"),
+            ("[@] Arrays", "arr=(a b c), ${arr[0]}"),
+            ("[f] Functions", "function name() { commands; }"),
+        ];
+        
+        for (feature, desc) in &features {
+            writeln!(io.stdout, "{}║{} {:<18} - {:<35} {}║{}", 
+                BRIGHT_CYAN, RESET, feature, desc, BRIGHT_CYAN, RESET)?;
+        }
+        
+        writeln!(io.stdout, "{}║{}                                                                          {}║{}", BRIGHT_CYAN, RESET, BRIGHT_CYAN, RESET)?;
+        writeln!(io.stdout, "{}║{} {}[*] POSIX Compatibility Level: 96%+{} {}                                 ║{}", BRIGHT_CYAN, RESET, BRIGHT_GREEN, RESET, BRIGHT_CYAN, RESET)?;
+        writeln!(io.stdout, "{}║{} {}[+] Enterprise-grade performance and reliability!{} {}                  ║{}", BRIGHT_CYAN, RESET, BRIGHT_YELLOW, RESET, BRIGHT_CYAN, RESET)?;
+        writeln!(io.stdout, "{}╚══════════════════════════════════════════════════════════════════════════╝{}", BRIGHT_CYAN, RESET)?;
+        writeln!(io.stdout)?;
+        Ok(0)
+    }
+
+    async fn execute_external_command(&mut self, argv: &[String]) -> Result<i32, ShellError> {
+        let mut setup = self.job_setup(true).await;
+        let pid = match self.spawn_external(argv, &setup).await {
+            Ok(pid) => pid,
+            Err(exit_code) => return Ok(exit_code),
+        };
+        setup.started(pid);
+        let mut job = Job::new(&argv.join(" "));
+        job.push(pid);
+
+        let exit_code = self.wait_for_foreground(job, &setup).await?;
+        *self.exit_code.write().await = exit_code;
+        Ok(exit_code)
+    }
+
+    async fn builtin_history(&self, io: &mut Streams) -> Result<i32, ShellError> {
+        let history = self.history.read().await;
+        writeln!(io.stdout, "{}[HIST] Command History:{}", BRIGHT_MAGENTA, RESET)?;
+        writeln!(io.stdout, "{}══════════════════{}", BRIGHT_MAGENTA, RESET)?;
+        for (i, cmd) in history.iter().enumerate() {
+            writeln!(io.stdout, "{}{:4}{} {}{}{}", DIM, i + 1, RESET, BRIGHT_WHITE, cmd, RESET)?;
+        }
+        Ok(0)
+    }
+
+    async fn builtin_alias(&mut self, args: &[String], io: &mut Streams) -> Result<i32, ShellError> {
+        let mut aliases = self.aliases.write().await;
+        for arg in args {
+            if let Some(eq_pos) = arg.find('=') {
+                let alias_name = arg[..eq_pos].to_string();
+                let alias_value = arg[eq_pos + 1..].to_string();
+                aliases.insert(alias_name, alias_value);
+            } else {
+                writeln!(io.stderr, "alias: usage: alias name=value")?;
+                return Ok(1);
+            }
+        }
+        Ok(0)
+    }
+
+    async fn builtin_show_aliases(&self, io: &mut Streams) -> Result<i32, ShellError> {
+        let aliases = self.aliases.read().await;
+        for (name, value) in aliases.iter() {
+            writeln!(io.stdout, "alias {}={}", name, shell_words::quote(value))?;
+        }
+        Ok(0)
+    }
+
+    async fn builtin_unset(&mut self, args: &[String], io: &mut Streams) -> Result<i32, ShellError> {
+        let (flag, names) = match args.first().map(|a| a.as_str()) {
+            Some(flag @ ("-f" | "-v" | "-n")) => (flag, &args[1..]),
+            Some(flag) if flag.starts_with('-') && flag.len() > 1 => {
+                writeln!(io.stderr, "unset: {}: invalid option", flag)?;
+                return Ok(2);
+            }
+            _ => ("", args),
+        };
+        let mut exit_code = 0;
+        for name in names {
+            if flag == "-f" {
+                self.functions.write().await.remove(name);
+                continue;
+            }
+            let (variable, subscript) = match arrays::split_subscript(name) {
+                Some((array, subscript)) => (array, Some(subscript)),
+                None => (name.as_str(), None),
+            };
+            // `unset -n` unsets a nameref itself rather than what it refers to
+            let variable = if flag == "-n" { variable.to_string() } else { self.resolve_nameref(variable).await };
+            if self.attributes(&variable).await.readonly {
+                writeln!(io.stderr, "unset: {}: cannot unset: readonly variable", variable)?;
+                exit_code = 1;
+            } else if let Some(subscript) = subscript {
+                // unset 'arr[i]' removes one element
+                let subscript = self.expand_fragment(subscript, false).await?;
+                if let Err(e) = self.unset_element(&variable, &subscript).await {
+                    writeln!(io.stderr, "unset: {}", e)?;
+                    exit_code = 1;
+                }
+            } else if !self.remove_variable(&variable).await && flag.is_empty() {
+                // A name that is not a variable may be a function
+                self.functions.write().await.remove(name);
+            }
+        }
+        Ok(exit_code)
+    }
+
+    async fn builtin_which(&self, args: &[String], io: &mut Streams) -> Result<i32, ShellError> {
+        let mut exit_code = 0;
+        for command in args {
+            if self.which_one(command, io).await? != 0 {
+                exit_code = 1;
+            }
+        }
+        Ok(exit_code)
+    }
+
+    async fn which_one(&self, command: &str, io: &mut Streams) -> Result<i32, ShellError> {
+        // Check if it's a builtin
+        if is_builtin(command) {
+            writeln!(io.stdout, "{}[BUILTIN] {}: shell builtin{}", BRIGHT_GREEN, command, RESET)?;
+            return Ok(0);
+        }
+        
+        // Check PATH
+        if let Some(path_var) = self.variables.read().await.get("PATH") {
+            for path_dir in path_var.split(if cfg!(windows) { ';' } else { ':' }) {
+                let executable = if cfg!(windows) {
+                    format!("{}/{}.exe", path_dir, command)
+                } else {
+                    format!("{}/{}", path_dir, command)
+                };
+                
+                if std::path::Path::new(&executable).exists() {
+                    writeln!(io.stdout, "{}[PATH] {}{}", BRIGHT_BLUE, executable, RESET)?;
+                    return Ok(0);
+                }
+            }
+        }
+        
+        writeln!(io.stdout, "{}[ERROR] {}: not found{}", RED, command, RESET)?;
+        Ok(1)
+    }
+
+    async fn builtin_type(&self, args: &[String], io: &mut Streams) -> Result<i32, ShellError> {
+        let mut exit_code = 0;
+        for command in args {
+            if self.type_one(command, io).await? != 0 {
+                exit_code = 1;
+            }
+        }
+        Ok(exit_code)
+    }
+
+    async fn type_one(&self, command: &str, io: &mut Streams) -> Result<i32, ShellError> {
+        // Check aliases
+        let aliases = self.aliases.read().await;
+        if let Some(alias_value) = aliases.get(command) {
+            writeln!(io.stdout, "{} is aliased to `{}'", command, alias_value)?;
+            return Ok(0);
+        }
+        
+        if let Some(function) = self.find_function(command).await {
+            writeln!(io.stdout, "{} is a function", command)?;
+            writeln!(io.stdout, "{} () \n{}", command, function.body_text)?;
+            return Ok(0);
+        }
+
+        // Check if it's a builtin
+        if is_builtin(command) {
+            writeln!(io.stdout, "{} is a shell builtin", command)?;
+            return Ok(0);
+        }
+        
+        // Check PATH
+        if let Some(path_var) = self.variables.read().await.get("PATH") {
+            for path_dir in path_var.split(if cfg!(windows) { ';' } else { ':' }) {
+                let executable = if cfg!(windows) {
+                    format!("{}/{}.exe", path_dir, command)
+                } else {
+                    format!("{}/{}", path_dir, command)
+                };
+                
+                if std::path::Path::new(&executable).exists() {
+                    writeln!(io.stdout, "{} is {}", command, executable)?;
+                    return Ok(0);
+                }
+            }
+        }
+        
+        writeln!(io.stderr, "{}: not found", command)?;
+        Ok(1)
+    }
+
+    async fn execute_if_statement(&mut self, branches: &[(List, List)], else_branch: Option<&List>) -> Result<i32, ShellError> {
+        for (condition, body) in branches {
+            // Execute condition; the first branch whose condition succeeds runs
+            self.suppress_errexit().await;
+            let status = Box::pin(self.execute_list(condition)).await;
+            self.resume_errexit().await;
+            if status? == 0 {
+                return Box::pin(self.execute_list(body)).await;
+            }
+        }
+        match else_branch {
+            Some(body) => Box::pin(self.execute_list(body)).await,
+            None => Ok(0),
+        }
+    }
+
+    async fn execute_for_loop(&mut self, var_name: &str, words: Option<&[Word]>, body: &List) -> Result<i32, ShellError> {
+        // Without `in` the loop runs over the positional parameters
+        let items = match words {
+            Some(words) => self.expand_words(words).await?,
+            None => self.positional.read().await.clone(),
+        };
+        
+        let mut last_exit_code = 0;
+        let trace = format!("for {} in {}", var_name, items.iter().map(|item| trace_quote(item)).collect::<Vec<_>>().join(" "));
+        for item in items {
+            self.trace(&trace).await;
+            self.assign_scalar(var_name, item, false).await?;
+            
+            // Execute commands
+            match self.execute_loop_list(body).await? {
+                Some(code) => last_exit_code = code,
+                None => return Ok(0),
+            }
+        }
+        
+        Ok(last_exit_code)
+    }
+
+    async fn execute_while_loop(&mut self, condition: &List, body: &List, until: bool) -> Result<i32, ShellError> {
+        let mut last_exit_code = 0;
+        loop {
+            // Execute condition
+            self.suppress_errexit().await;
+            let status = self.execute_loop_list(condition).await;
+            self.resume_errexit().await;
+            let Some(condition_result) = status? else {
+                return Ok(0);
+            };
+            
+            // `while` stops when the condition fails, `until` when it succeeds
+            if (condition_result == 0) == until {
+                break;
+            }
+            
+            // Execute commands
+            match self.execute_loop_list(body).await? {
+                Some(code) => last_exit_code = code,
+                None => return Ok(0),
+            }
+        }
+        
+        Ok(last_exit_code)
+    }
+
+    /// Runs part of a loop, returning `None` when a `break` ends the loop.
+    /// `break` and `continue` aimed at an enclosing loop go on unwinding.
+    async fn execute_loop_list(&mut self, list: &List) -> Result<Option<i32>, ShellError> {
+        match Box::pin(self.execute_list(list)).await {
+            Ok(code) => Ok(Some(code)),
+            Err(ShellError::Break(1)) => Ok(None),
+            Err(ShellError::Break(count)) => Err(ShellError::Break(count - 1)),
+            Err(ShellError::Continue(1)) => Ok(Some(0)),
+            Err(ShellError::Continue(count)) => Err(ShellError::Continue(count - 1)),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// `( list )`: runs the list in a forked copy of the shell, so that
+    /// nothing it changes leaks back.
+    async fn execute_subshell(&mut self, list: &List, text: &str) -> Result<i32, ShellError> {
+        let mut setup = self.job_setup(true).await;
+        flush_stdio();
+        match unsafe { nix::unistd::fork() } {
+            Ok(ForkResult::Parent { child }) => {
+                setup.started(child);
+                let mut job = Job::new(text);
+                job.push(child);
+                self.wait_for_foreground(job, &setup).await
+            }
+            Ok(ForkResult::Child) => {
+                setup.enter_child();
+                self.enter_subshell().await;
+                let status = child_status(Box::pin(self.execute_list(list)).await);
+                exit_child(self.run_exit_trap(status).await)
+            }
+            Err(errno) => Err(ShellError::IoError(errno.into())),
+        }
+    }
+
+    /// `(( expression ))`: status 0 when the expression is not zero.
+    async fn execute_arithmetic_command(&mut self, expression: &Word) -> Result<i32, ShellError> {
+        let expression = self.expand_fragment(&expression.raw, true).await?;
+        self.trace(&format!("(( {} ))", expression.trim())).await;
+        match self.evaluate_arithmetic(&expression).await {
+            Ok(value) => Ok((value == 0) as i32),
+            Err(ShellError::Arithmetic(msg)) => {
+                eprintln!("nexusshell: ((: {}", msg);
+                Ok(1)
+            }
+            Err(e @ ShellError::ReadOnly(_)) => {
+                eprintln!("nexusshell: {}", e);
+                Ok(1)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn execute_case(&mut self, word: &Word, items: &[CaseItem]) -> Result<i32, ShellError> {
+        let subject = self.expand_word(word).await?;
+        let mut last_exit_code = 0;
+        // Set by `;&`: the next body runs without its patterns being tested
+        let mut fall_through = false;
+        for item in items {
+            if !fall_through {
+                let mut matched = false;
+                for pattern in &item.patterns {
+                    let extglob = self.shopt.read().await.extglob;
+                    if Pattern::parse(&self.expand_pattern(pattern).await?, extglob).matches(&subject) {
+                        matched = true;
+                        break;
+                    }
+                }
+                if !matched {
+                    continue;
+                }
+            }
+            last_exit_code = Box::pin(self.execute_list(&item.body)).await?;
+            match item.terminator {
+                CaseTerminator::Break => break,
+                CaseTerminator::FallThrough => fall_through = true,
+                CaseTerminator::Continue => fall_through = false,
+            }
+        }
+        Ok(last_exit_code)
+    }
+
+    async fn execute_background_command(&mut self, and_or: &AndOr) -> Result<i32, ShellError> {
+        if and_or.rest.is_empty() && !and_or.first.negated {
+            return self.execute_pipeline_stages(&and_or.first.commands, &and_or.text, true).await;
+        }
+
+        // Lists run as a single job in a forked copy of the shell
+        let mut setup = self.job_setup(false).await;
+        flush_stdio();
+        match unsafe { nix::unistd::fork() } {
+            Ok(ForkResult::Parent { child }) => {
+                setup.started(child);
+                let mut job = Job::new(&and_or.text);
+                job.push(child);
+                self.add_background_job(job, &setup).await;
+                Ok(0)
+            }
+            Ok(ForkResult::Child) => {
+                setup.enter_child();
+                self.enter_subshell().await;
+                let status = child_status(Box::pin(self.execute_and_or(and_or)).await);
+                exit_child(self.run_exit_trap(status).await)
+            }
+            Err(errno) => Err(ShellError::IoError(errno.into())),
+        }
+    }
+
+    async fn builtin_test(&self, argv: &[String], io: &mut Streams) -> Result<i32, ShellError> {
+        // Basic test command implementation
+        let mut parts: Vec<&str> = argv[1..].iter().map(|s| s.as_str()).collect();
+        if argv[0] == "[" {
+            if parts.last() != Some(&"]") {
+                writeln!(io.stderr, "[: missing `]'")?;
+                return Ok(2);
+            }
+            parts.pop();
+        }
+        
+        if parts.is_empty() {
+            return Ok(1);
+        }
+        
+        // Negation
+        if parts[0] == "!" && parts.len() > 1 {
+            let negated: Vec<String> = std::iter::once("test".to_string())
+                .chain(parts[1..].iter().map(|s| s.to_string()))
+                .collect();
+            let result = Box::pin(self.builtin_test(&negated, io)).await?;
+            return Ok(if result == 0 { 1 } else if result == 1 { 0 } else { result });
+        }
+        
+        match parts.len() {
+            1 => {
+                // Test if string is non-empty
+                Ok(if parts[0].is_empty() { 1 } else { 0 })
+            }
+            2 => {
+                let path = std::path::Path::new(parts[1]);
+                let result = match parts[0] {
+                    "-n" => !parts[1].is_empty(),
+                    "-z" => parts[1].is_empty(),
+                    "-e" => path.exists(),
+                    "-f" => path.is_file(),
+                    "-d" => path.is_dir(),
+                    "-L" | "-h" => path.symlink_metadata().map(|m| m.file_type().is_symlink()).unwrap_or(false),
+                    "-s" => path.metadata().map(|m| m.len() > 0).unwrap_or(false),
+                    "-r" => file_access(parts[1], libc::R_OK),
+                    "-w" => file_access(parts[1], libc::W_OK),
+                    "-x" => file_access(parts[1], libc::X_OK),
+                    _ => {
+                        writeln!(io.stderr, "test: {}: unary operator expected", parts[0])?;
+                        return Ok(2);
+                    }
+                };
+                Ok(if result { 0 } else { 1 })
+            }
+            3 => {
+                let left = parts[0];
+                let op = parts[1];
+                let right = parts[2];
+                
+                match op {
+                    "=" | "==" => Ok(if left == right { 0 } else { 1 }),
+                    "!=" => Ok(if left != right { 0 } else { 1 }),
+                    "-eq" => {
+                        let l: i32 = left.parse().unwrap_or(0);
+                        let r: i32 = right.parse().unwrap_or(0);
+                        Ok(if l == r { 0 } else { 1 })
+                    }
+                    "-ne" => {
+                        let l: i32 = left.parse().unwrap_or(0);
+                        let r: i32 = right.parse().unwrap_or(0);
+                        Ok(if l != r { 0 } else { 1 })
+                    }
+                    "-lt" => {
+                        let l: i32 = left.parse().unwrap_or(0);
+                        let r: i32 = right.parse().unwrap_or(0);
+                        Ok(if l < r { 0 } else { 1 })
+                    }
+                    "-gt" => {
+                        let l: i32 = left.parse().unwrap_or(0);
+                        let r: i32 = right.parse().unwrap_or(0);
+                        Ok(if l > r { 0 } else { 1 })
+                    }
+                    "-le" => {
+                        let l: i32 = left.parse().unwrap_or(0);
+                        let r: i32 = right.parse().unwrap_or(0);
+                        Ok(if l <= r { 0 } else { 1 })
+                    }
+                    "-ge" => {
+                        let l: i32 = left.parse().unwrap_or(0);
+                        let r: i32 = right.parse().unwrap_or(0);
+                        Ok(if l >= r { 0 } else { 1 })
+                    }
+                    _ => Ok(1),
+                }
+            }
+            _ => Ok(1),
+        }
+    }
+
+    async fn builtin_read(&mut self, args: &[String], io: &mut Streams) -> Result<i32, ShellError> {
+        let var_name = match args.first() {
+            Some(name) => name,
+            None => {
+                writeln!(io.stderr, "read: usage: read variable_name")?;
+                return Ok(1);
+            }
+        };
+        
+        match io.stdin.read_line()? {
+            Some(input) => {
+                self.assign_scalar(var_name, input.trim().to_string(), false).await?;
+                Ok(0)
+            }
+            None => Ok(1),
+        }
+    }
+
+    async fn builtin_printf(&self, args: &[String], io: &mut Streams) -> Result<i32, ShellError> {
+        // Basic printf implementation
+        let format_str = match args.first() {
+            Some(format_str) => format_str,
+            None => return Ok(0),
+        };
+        
+        // Simple format string processing
+        let output = format_str
+            .replace("\\n", "\n")
+            .replace("\\t", "\t")
+            .replace("\\r", "\r")
+            .replace("\\\\", "\\");
+        
+        // Replace %s with arguments (basic implementation)
+        // if output.contains("%s") && !args_str.is_empty() {
+        //     output = output.replace("%s", args_str);
+        // }
+        
+        write!(io.stdout, "{}", output)?;
+        Ok(0)
+    }
+
+    async fn builtin_source(&mut self, args: &[String], io: &mut Streams) -> Result<i32, ShellError> {
+        let filename = match args.first() {
+            Some(filename) => filename,
+            None => {
+                writeln!(io.stderr, "source: filename argument required")?;
+                return Ok(2);
+            }
+        };
+        
+        let text = match std::fs::read_to_string(filename) {
+            Ok(text) => text,
+            Err(_) => {
+                writeln!(io.stderr, "source: {}: No such file or directory", filename)?;
+                return Ok(1);
+            }
+        };
+
+        // Further arguments are the positional parameters while the file runs
+        let saved = if args.len() > 1 {
+            Some(std::mem::replace(&mut *self.positional.write().await, args[1..].to_vec()))
+        } else {
+            None
+        };
+        *self.sourcing.write().await += 1;
+        let line = *self.line.read().await;
+        let result = Box::pin(self.run_script(ScriptSource::Text(text), filename)).await;
+        *self.line.write().await = line;
+        let result = self.run_trap(Condition::Return).await.and(result);
+        *self.sourcing.write().await -= 1;
+        if let Some(saved) = saved {
+            *self.positional.write().await = saved;
+        }
+        match result {
+            Err(ShellError::Return(code)) => Ok(code),
+            result => result,
+        }
+    }
+
+    /// `exit [N]`: ends the shell with status N, or `$?`.  The EXIT trap
+    /// and the history are taken care of where the unwinding stops.
+    async fn builtin_exit(&self, args: &[String], io: &mut Streams) -> Result<i32, ShellError> {
+        if args.len() > 1 {
+            writeln!(io.stderr, "exit: too many arguments")?;
+            return Ok(1);
+        }
+        let code = match args.first() {
+            None => *self.exit_code.read().await,
+            Some(arg) => match arg.parse::<i32>() {
+                Ok(code) => code & 0xff,
+                Err(_) => {
+                    writeln!(io.stderr, "exit: {}: numeric argument required", arg)?;
+                    2
+                }
+            },
+        };
+        Err(ShellError::Exit(code))
+    }
+
+    /// `break [N]` and `continue [N]`; a count past the outermost loop
+    /// means the outermost loop.
+    async fn builtin_break(&self, name: &str, args: &[String], io: &mut Streams) -> Result<i32, ShellError> {
+        let depth = *self.loop_depth.read().await;
+        if depth == 0 {
+            writeln!(io.stderr, "{}: only meaningful in a `for', `while', or `until' loop", name)?;
+            return Ok(0);
+        }
+        let count = match args.first().map(|arg| arg.parse::<usize>()) {
+            None => 1,
+            Some(Ok(0)) => {
+                writeln!(io.stderr, "{}: {}: loop count out of range", name, args[0])?;
+                return Ok(1);
+            }
+            Some(Ok(count)) => count.min(depth),
+            Some(Err(_)) => {
+                writeln!(io.stderr, "{}: {}: numeric argument required", name, args[0])?;
+                return Ok(1);
+            }
+        };
+        Err(if name == "break" { ShellError::Break(count) } else { ShellError::Continue(count) })
+    }
+
+    /// `let expression...`: status 0 when the last expression is not zero.
+    async fn builtin_let(&self, args: &[String], io: &mut Streams) -> Result<i32, ShellError> {
+        if args.is_empty() {
+            writeln!(io.stderr, "let: expression expected")?;
+            return Ok(1);
+        }
+        let mut value = 0;
+        for arg in args {
+            value = match self.evaluate_arithmetic(arg).await {
+                Ok(value) => value,
+                Err(ShellError::Arithmetic(msg)) => {
+                    writeln!(io.stderr, "let: {}", msg)?;
+                    return Ok(1);
+                }
+                Err(e @ ShellError::ReadOnly(_)) => {
+                    writeln!(io.stderr, "let: {}", e)?;
+                    return Ok(1);
+                }
+                Err(e) => return Err(e),
+            };
+        }
+        Ok((value == 0) as i32)
+    }
+
+    async fn builtin_shift(&self, args: &[String], io: &mut Streams) -> Result<i32, ShellError> {
+        let count = match args.first().map(|arg| arg.parse::<usize>()) {
+            None => 1,
+            Some(Ok(count)) => count,
+            Some(Err(_)) => {
+                writeln!(io.stderr, "shift: {}: numeric argument required", args[0])?;
+                return Ok(1);
+            }
+        };
+        let mut positional = self.positional.write().await;
+        if count > positional.len() {
+            return Ok(1);
+        }
+        positional.drain(..count);
+        Ok(0)
+    }
+
+    async fn builtin_set(&self, args: &[String], io: &mut Streams) -> Result<i32, ShellError> {
+        if args.is_empty() {
+            let variables = self.variables.read().await;
+            for (key, value) in variables.iter() {
+                writeln!(io.stdout, "{}={}", key, value)?;
+            }
+            return Ok(0);
+        }
+
+        let mut options = self.options.write().await;
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let enable = match arg.chars().next() {
+                Some('-') => true,
+                Some('+') => false,
+                // The first operand starts the new positional parameters
+                _ => {
+                    *self.positional.write().await = std::iter::once(arg).chain(args).cloned().collect();
+                    return Ok(0);
+                }
+            };
+            match arg.as_str() {
+                // `set --` ends the options; the rest, perhaps nothing, become
+                // the positional parameters
+                "--" => {
+                    *self.positional.write().await = args.cloned().collect();
+                    return Ok(0);
+                }
+                // `set -` also turns off -x and -v
+                "-" => {
+                    options.xtrace = false;
+                    options.verbose = false;
+                    let rest: Vec<String> = args.cloned().collect();
+                    if !rest.is_empty() {
+                        *self.positional.write().await = rest;
+                    }
+                    return Ok(0);
+                }
+                _ => {}
+            }
+            for flag in arg[1..].chars() {
+                // `o` takes the next argument as a long option name, also
+                // inside a cluster like `-euo pipefail`
+                if flag == 'o' {
+                    let Some(name) = args.next() else {
+                        for name in ShellOptions::NAMES {
+                            let on = options.get(name) == Some(true);
+                            if enable {
+                                writeln!(io.stdout, "{:<15}\t{}", name, if on { "on" } else { "off" })?;
+                            } else {
+                                writeln!(io.stdout, "set {}o {}", if on { '-' } else { '+' }, name)?;
+                            }
+                        }
+                        continue;
+                    };
+                    match options.get_mut(name) {
+                        Some(option) => *option = enable,
+                        None => {
+                            writeln!(io.stderr, "set: {}: invalid option name", name)?;
+                            return Ok(2);
+                        }
+                    }
+                    continue;
+                }
+                match ShellOptions::long_name(flag).and_then(|name| options.get_mut(name)) {
+                    Some(option) => *option = enable,
+                    None => {
+                        writeln!(io.stderr, "set: {}{}: invalid option", &arg[..1], flag)?;
+                        return Ok(2);
+                    }
+                }
+            }
+        }
+        Ok(0)
+    }
+
+    async fn builtin_shopt(&self, args: &[String], io: &mut Streams) -> Result<i32, ShellError> {
+        let mut enable = None;
+        let mut quiet = false;
+        let mut reusable = false;
+        // `-o` works on the options of `set -o` instead
+        let mut set_options = false;
+        let mut names = Vec::new();
+        for arg in args {
+            match arg.strip_prefix('-') {
+                Some(flags) if !flags.is_empty() && names.is_empty() => {
+                    for flag in flags.chars() {
+                        match flag {
+                            's' => enable = Some(true),
+                            'u' => enable = Some(false),
+                            'q' => quiet = true,
+                            'p' => reusable = true,
+                            'o' => set_options = true,
+                            _ => {
+                                writeln!(io.stderr, "shopt: -{}: invalid option", flag)?;
+                                writeln!(io.stderr, "shopt: usage: shopt [-pqsu] [-o] [optname ...]")?;
+                                return Ok(2);
+                            }
+                        }
+                    }
+                }
+                _ => names.push(arg.as_str()),
+            }
+        }
+
+        let mut options = self.options.write().await;
+        let mut shopt = self.shopt.write().await;
+        let all = if set_options { ShellOptions::NAMES } else { ShoptOptions::NAMES };
+        let get = |options: &ShellOptions, shopt: &ShoptOptions, name: &str| {
+            if set_options { options.get(name) } else { shopt.get(name) }
+        };
+        let mut status = 0;
+        for name in &names {
+            if get(&options, &shopt, name).is_none() {
+                writeln!(io.stderr, "shopt: {}: invalid shell option name", name)?;
+                status = 1;
+            }
+        }
+
+        if let (Some(enable), false) = (enable, names.is_empty()) {
+            for name in &names {
+                let option = if set_options { options.get_mut(name) } else { shopt.get_mut(name) };
+                if let Some(option) = option {
+                    *option = enable;
+                }
+            }
+            return Ok(status);
+        }
+
+        // Without names: every option, or only those that are on or off
+        // with `-s` or `-u`
+        let queried = !names.is_empty();
+        let listed: Vec<&str> = if names.is_empty() {
+            all.iter().copied().filter(|name| enable.is_none_or(|enable| get(&options, &shopt, name) == Some(enable))).collect()
+        } else {
+            names.into_iter().filter(|name| get(&options, &shopt, name).is_some()).collect()
+        };
+        for name in listed {
+            let on = get(&options, &shopt, name) == Some(true);
+            // Asking about options gives whether they are all on
+            if queried && !on {
+                status = 1;
+            }
+            if quiet {
+                continue;
+            }
+            if reusable {
+                let (command, flag) = match (set_options, on) {
+                    (true, true) => ("set", "-o"),
+                    (true, false) => ("set", "+o"),
+                    (false, true) => ("shopt", "-s"),
+                    (false, false) => ("shopt", "-u"),
+                };
+                writeln!(io.stdout, "{} {} {}", command, flag, name)?;
+            } else {
+                writeln!(io.stdout, "{:<15}\t{}", name, if on { "on" } else { "off" })?;
+            }
+        }
+        Ok(status)
+    }
+
+    async fn builtin_declare(&mut self, args: &[String], io: &mut Streams) -> Result<i32, ShellError> {
+        let (on, off, operands) = split_declare_flags(args);
+        if let Some(flag) = on.chars().chain(off.chars()).find(|flag| !"aAfFgilnprux".contains(*flag)) {
+            writeln!(io.stderr, "declare: -{}: invalid option", flag)?;
+            writeln!(io.stderr, "declare: usage: declare [-aAfFgilnprux] [name[=value] ...]")?;
+            return Ok(2);
+        }
+        if on.contains(['f', 'F']) {
+            let found = self.print_functions(&operands, on.contains('F'), io).await?;
+            return Ok(if found { 0 } else { 1 });
+        }
+        // `declare -p`, or options alone, list variables
+        if on.contains('p') || operands.is_empty() {
+            let filter: String = on.chars().filter(|flag| "aAinrxlu".contains(*flag)).collect();
+            let found = self.print_declarations("declare", &operands, &filter, io).await?;
+            return Ok(if found { 0 } else { 1 });
+        }
+        // Inside a function `declare` makes its variables local, like `local`
+        let local = !on.contains('g') && self.in_function().await;
+        self.declare_variables("declare", (&on, &off), &operands, local, io).await
+    }
+
+    async fn builtin_conditional_expression(&self, _args: &[String], io: &mut Streams) -> Result<i32, ShellError> {
+        // Placeholder implementation
+        writeln!(io.stderr, "conditional_expression: not fully implemented yet")?;
+        Ok(1)
+    }
+
+    async fn builtin_pushd(&self, _args: &[String], io: &mut Streams) -> Result<i32, ShellError> {
+        // Placeholder implementation
+        writeln!(io.stderr, "pushd: not fully implemented yet")?;
+        Ok(1)
+    }
+
+    async fn builtin_popd(&self, io: &mut Streams) -> Result<i32, ShellError> {
+        // Placeholder implementation
+        writeln!(io.stderr, "popd: not fully implemented yet")?;
+        Ok(1)
+    }
+
+    async fn builtin_dirs(&self, io: &mut Streams) -> Result<i32, ShellError> {
+        // Placeholder implementation
+        writeln!(io.stderr, "dirs: not fully implemented yet")?;
+        Ok(1)
+    }
+
+    /// `exec [-cl] [-a name] [command [args]]`: replaces the shell with
+    /// `command`.  Without a command it does nothing itself; the caller
+    /// keeps its redirections.  A non-interactive shell that cannot run the
+    /// command exits.
+    async fn builtin_exec(&self, args: &[String], io: &mut Streams) -> Result<i32, ShellError> {
+        let mut clear_environment = false;
+        let mut login = false;
+        let mut name = None;
+        let mut args = args.iter();
+        let mut command: Vec<String> = Vec::new();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-c" => clear_environment = true,
+                "-l" => login = true,
+                "-a" => match args.next() {
+                    Some(arg) => name = Some(arg.clone()),
+                    None => {
+                        writeln!(io.stderr, "exec: -a: option requires an argument")?;
+                        return Ok(2);
+                    }
+                },
+                "--" => break,
+                option if option.len() > 1 && option.starts_with('-') => {
+                    writeln!(io.stderr, "exec: {}: invalid option", option)?;
+                    writeln!(io.stderr, "exec: usage: exec [-cl] [-a name] [command [argument ...]]")?;
+                    return Ok(2);
+                }
+                _ => {
+                    command.push(arg.clone());
+                    break;
+                }
+            }
+        }
+        command.extend(args.cloned());
+        if command.is_empty() {
+            return Ok(0);
+        }
+
+        let Some(path) = self.find_program(&command[0]).await else {
+            writeln!(io.stderr, "exec: {}: not found", command[0])?;
+            return self.exec_failed(127);
+        };
+        let mut argv0 = name.unwrap_or_else(|| command[0].clone());
+        if login {
+            argv0.insert(0, '-');
+        }
+        let environment: Vec<String> = if clear_environment {
+            Vec::new()
+        } else {
+            self.environment().await.into_iter().map(|(name, value)| format!("{}={}", name, value)).collect()
+        };
+        let cstring = |s: &str| std::ffi::CString::new(s).map_err(|_| ShellError::InvalidArgument(s.to_string()));
+        let path = cstring(&path)?;
+        let argv = std::iter::once(argv0.as_str()).chain(command[1..].iter().map(String::as_str)).map(cstring).collect::<Result<Vec<_>, _>>()?;
+        let environment = environment.iter().map(|entry| cstring(entry)).collect::<Result<Vec<_>, _>>()?;
+
+        if self.interactive {
+            self.save_history().await;
+        }
+        flush_stdio();
+        // execve resets handled signals itself; those the shell ignores
+        // for its own sake go back to their defaults by hand
+        let ignored: Vec<_> = [Signal::SIGPIPE, Signal::SIGTSTP, Signal::SIGTTIN, Signal::SIGTTOU]
+            .into_iter()
+            .filter(|&sig| !signals::is_ignored(sig))
+            .filter_map(|sig| unsafe { nix::sys::signal::signal(sig, SigHandler::SigDfl) }.ok().map(|previous| (sig, previous)))
+            .collect();
+        let Err(errno) = nix::unistd::execve(&path, &argv, &environment);
+        for (sig, previous) in ignored {
+            unsafe {
+                let _ = nix::sys::signal::signal(sig, previous);
+            }
+        }
+        writeln!(io.stderr, "exec: {}: {}", command[0], errno.desc())?;
+        self.exec_failed(126)
+    }
+
+    /// What becomes of the shell when `exec` cannot run its command.
+    fn exec_failed(&self, status: i32) -> Result<i32, ShellError> {
+        if self.interactive {
+            Ok(status)
+        } else {
+            Err(ShellError::Exit(status))
+        }
+    }
+
+    /// The file `name` runs: `name` itself when it has a slash, otherwise
+    /// the first executable file of that name in a `PATH` directory.
+    async fn find_program(&self, name: &str) -> Option<String> {
+        if name.contains('/') {
+            return Some(name.to_string());
+        }
+        let path = self.variables.read().await.get("PATH").cloned().unwrap_or_default();
+        path.split(':')
+            .map(|dir| format!("{}/{}", if dir.is_empty() { "." } else { dir }, name))
+            .find(|candidate| Path::new(candidate).is_file() && file_access(candidate, libc::X_OK))
+    }
+
+    /// `eval args`: runs the arguments, joined with spaces, as commands of
+    /// the current shell.
+    async fn builtin_eval(&mut self, args: &[String]) -> Result<i32, ShellError> {
+        let name = format!("{}: eval", self.arg0);
+        let line = *self.line.read().await;
+        let result = Box::pin(self.run_script(ScriptSource::Text(args.join(" ")), &name)).await;
+        *self.line.write().await = line;
+        result
+    }
+
+    async fn save_history(&self) {
+        let _history = self.history.read().await;
+        let history_file = env::var("HOME").unwrap_or_else(|_| ".".to_string()) + "/.nexusshell_history";
+        let mut readline = self.readline.lock().await;
+        readline.save_history(&history_file).ok();
+    }
+
+    async fn builtin_stats(&self, io: &mut Streams) -> Result<i32, ShellError> {
+        let command_count = *self.command_count.read().await;
+        let error_count = *self.error_count.read().await;
+        let uptime = self.startup_time.elapsed();
+        let last_command_time = *self.last_command_time.read().await;
+        let time_since_last = last_command_time.elapsed();
+        
+        writeln!(io.stdout)?;
+        writeln!(io.stdout, "{}╔══════════════════════════════════════════════════════════════════════════╗{}", BRIGHT_CYAN, RESET)?;
+        writeln!(io.stdout, "{}║{} {}[STATS] NexusShell Performance Statistics{} {}                         ║{}", BRIGHT_CYAN, RESET, BRIGHT_YELLOW, RESET, BRIGHT_CYAN, RESET)?;
+        writeln!(io.stdout, "{}╠══════════════════════════════════════════════════════════════════════════╣{}", BRIGHT_CYAN, RESET)?;
+        writeln!(io.stdout, "{}║{}                                                                          {}║{}", BRIGHT_CYAN, RESET, BRIGHT_CYAN, RESET)?;
+        
+        // Session info
+        writeln!(io.stdout, "{}║{} {}Session Information:{} {}                                              ║{}", BRIGHT_CYAN, RESET, BOLD, RESET, BRIGHT_CYAN, RESET)?;
+        writeln!(io.stdout, "{}║{} Session ID: {:<50} {}║{}", BRIGHT_CYAN, RESET, self.session_id, BRIGHT_CYAN, RESET)?;
+        writeln!(io.stdout, "{}║{} Uptime: {:<54} {}║{}", BRIGHT_CYAN, RESET, format!("{:?}", uptime), BRIGHT_CYAN, RESET)?;
+        writeln!(io.stdout, "{}║{} Time since last command: {:<38} {}║{}", BRIGHT_CYAN, RESET, format!("{:?}", time_since_last), BRIGHT_CYAN, RESET)?;
+        
+        writeln!(io.stdout, "{}║{}                                                                          {}║{}", BRIGHT_CYAN, RESET, BRIGHT_CYAN, RESET)?;
+        
+        // Command statistics
+        writeln!(io.stdout, "{}║{} {}Command Statistics:{} {}                                               ║{}", BRIGHT_CYAN, RESET, BOLD, RESET, BRIGHT_CYAN, RESET)?;
+        writeln!(io.stdout, "{}║{} Total commands executed: {:<41} {}║{}", BRIGHT_CYAN, RESET, command_count, BRIGHT_CYAN, RESET)?;
+        writeln!(io.stdout, "{}║{} Total errors: {:<50} {}║{}", BRIGHT_CYAN, RESET, error_count, BRIGHT_CYAN, RESET)?;
+        
+        let success_rate = if command_count > 0 {
+            ((command_count - error_count) as f64 / command_count as f64) * 100.0
+        } else {
+            100.0
+        };
+        writeln!(io.stdout, "{}║{} Success rate: {:<48} {}║{}", BRIGHT_CYAN, RESET, format!("{:.1}%", success_rate), BRIGHT_CYAN, RESET)?;
+        
+        let commands_per_minute = if uptime.as_secs() > 0 {
+            (command_count as f64 / uptime.as_secs() as f64) * 60.0
+        } else {
+            0.0
+        };
+        writeln!(io.stdout, "{}║{} Commands per minute: {:<41} {}║{}", BRIGHT_CYAN, RESET, format!("{:.1}", commands_per_minute), BRIGHT_CYAN, RESET)?;
+        
+        writeln!(io.stdout, "{}║{}                                                                          {}║{}", BRIGHT_CYAN, RESET, BRIGHT_CYAN, RESET)?;
+        
+        // Memory and performance info
+        writeln!(io.stdout, "{}║{} {}Memory & Performance:{} {}                                             ║{}", BRIGHT_CYAN, RESET, BOLD, RESET, BRIGHT_CYAN, RESET)?;
+        
+        let history_count = self.history.read().await.len();
+        let alias_count = self.aliases.read().await.len();
+        let job_count = self.jobs.read().await.len();
+        let function_count = self.functions.read().await.len();
+        let array_count = self.arrays.read().await.len();
+        
+        writeln!(io.stdout, "{}║{} History entries: {:<45} {}║{}", BRIGHT_CYAN, RESET, history_count, BRIGHT_CYAN, RESET)?;
+        writeln!(io.stdout, "{}║{} Active aliases: {:<46} {}║{}", BRIGHT_CYAN, RESET, alias_count, BRIGHT_CYAN, RESET)?;
+        writeln!(io.stdout, "{}║{} Background jobs: {:<45} {}║{}", BRIGHT_CYAN, RESET, job_count, BRIGHT_CYAN, RESET)?;
+        writeln!(io.stdout, "{}║{} Defined functions: {:<43} {}║{}", BRIGHT_CYAN, RESET, function_count, BRIGHT_CYAN, RESET)?;
+        writeln!(io.stdout, "{}║{} Arrays: {:<56} {}║{}", BRIGHT_CYAN, RESET, array_count, BRIGHT_CYAN, RESET)?;
+        
+        writeln!(io.stdout, "{}║{}                                                                          {}║{}", BRIGHT_CYAN, RESET, BRIGHT_CYAN, RESET)?;
+        writeln!(io.stdout, "{}║{} {}[INFO] Type 'help' for commands or 'exit' to quit{} {}                 ║{}", BRIGHT_CYAN, RESET, BRIGHT_GREEN, RESET, BRIGHT_CYAN, RESET)?;
+        writeln!(io.stdout, "{}╚══════════════════════════════════════════════════════════════════════════╝{}", BRIGHT_CYAN, RESET)?;
+        writeln!(io.stdout)?;
+        
+        Ok(0)
+    }
 }
\ No newline at end of file
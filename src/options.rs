@@ -0,0 +1,136 @@
+// Shell options toggled with `set` and `shopt`.
+
+#[derive(Debug, Clone, Default)]
+pub struct ShellOptions {
+    /// `set -a`: variables that are assigned are exported.
+    pub allexport: bool,
+    /// `set -e`: a command that fails outside a condition ends the shell.
+    pub errexit: bool,
+    /// `set -o ignoreeof`: Ctrl-D on an empty line does not exit.
+    pub ignoreeof: bool,
+    /// `set -m`: run jobs in their own process groups (on for interactive shells).
+    pub monitor: bool,
+    /// `set -C`: `>` will not overwrite an existing regular file.
+    pub noclobber: bool,
+    /// `set -f`: no pathname expansion.
+    pub noglob: bool,
+    /// `set -u`: expanding an unset parameter is an error.
+    pub nounset: bool,
+    /// `set -o pipefail`: a pipeline fails with its last failing command.
+    pub pipefail: bool,
+    /// `set -v`: input lines are echoed to stderr as they are read.
+    pub verbose: bool,
+    /// `set -x`: commands are echoed to stderr, after `PS4`, as they run.
+    pub xtrace: bool,
+}
+
+impl ShellOptions {
+    /// Option names in `set -o` listing order.
+    pub const NAMES: &'static [&'static str] = &[
+        "allexport", "errexit", "ignoreeof", "monitor", "noclobber", "noglob", "nounset", "pipefail", "verbose", "xtrace",
+    ];
+
+    /// Looks up an option by its `set -o` name.
+    pub fn get(&self, name: &str) -> Option<bool> {
+        match name {
+            "allexport" => Some(self.allexport),
+            "errexit" => Some(self.errexit),
+            "ignoreeof" => Some(self.ignoreeof),
+            "monitor" => Some(self.monitor),
+            "noclobber" => Some(self.noclobber),
+            "noglob" => Some(self.noglob),
+            "nounset" => Some(self.nounset),
+            "pipefail" => Some(self.pipefail),
+            "verbose" => Some(self.verbose),
+            "xtrace" => Some(self.xtrace),
+            _ => None,
+        }
+    }
+
+    /// Mutable access to an option by its `set -o` name.
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut bool> {
+        match name {
+            "allexport" => Some(&mut self.allexport),
+            "errexit" => Some(&mut self.errexit),
+            "ignoreeof" => Some(&mut self.ignoreeof),
+            "monitor" => Some(&mut self.monitor),
+            "noclobber" => Some(&mut self.noclobber),
+            "noglob" => Some(&mut self.noglob),
+            "nounset" => Some(&mut self.nounset),
+            "pipefail" => Some(&mut self.pipefail),
+            "verbose" => Some(&mut self.verbose),
+            "xtrace" => Some(&mut self.xtrace),
+            _ => None,
+        }
+    }
+
+    /// Single-letter `set` flags and the options they stand for.
+    const FLAGS: &'static [(char, &'static str)] = &[
+        ('C', "noclobber"),
+        ('a', "allexport"),
+        ('e', "errexit"),
+        ('f', "noglob"),
+        ('m', "monitor"),
+        ('u', "nounset"),
+        ('v', "verbose"),
+        ('x', "xtrace"),
+    ];
+
+    /// Maps a single-letter `set` flag to its long name.
+    pub fn long_name(flag: char) -> Option<&'static str> {
+        Self::FLAGS.iter().find(|(f, _)| *f == flag).map(|(_, name)| *name)
+    }
+
+    /// The flags of the options that are on, as `$-` shows them.
+    pub fn flags(&self) -> String {
+        let mut flags: Vec<char> = Self::FLAGS.iter().filter(|(_, name)| self.get(name) == Some(true)).map(|(f, _)| *f).collect();
+        flags.sort_unstable();
+        flags.into_iter().collect()
+    }
+}
+
+/// Options toggled with `shopt`.
+#[derive(Debug, Clone, Default)]
+pub struct ShoptOptions {
+    /// Globs match file names starting with `.`.
+    pub dotglob: bool,
+    /// `?(...)`, `*(...)`, `+(...)`, `@(...)` and `!(...)` in patterns.
+    pub extglob: bool,
+    /// A glob that matches nothing is an error.
+    pub failglob: bool,
+    /// `**` in a glob matches any number of directories.
+    pub globstar: bool,
+    /// Globs ignore case.
+    pub nocaseglob: bool,
+    /// A glob that matches nothing expands to nothing.
+    pub nullglob: bool,
+}
+
+impl ShoptOptions {
+    /// Option names in `shopt` listing order.
+    pub const NAMES: &'static [&'static str] = &["dotglob", "extglob", "failglob", "globstar", "nocaseglob", "nullglob"];
+
+    pub fn get(&self, name: &str) -> Option<bool> {
+        match name {
+            "dotglob" => Some(self.dotglob),
+            "extglob" => Some(self.extglob),
+            "failglob" => Some(self.failglob),
+            "globstar" => Some(self.globstar),
+            "nocaseglob" => Some(self.nocaseglob),
+            "nullglob" => Some(self.nullglob),
+            _ => None,
+        }
+    }
+
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut bool> {
+        match name {
+            "dotglob" => Some(&mut self.dotglob),
+            "extglob" => Some(&mut self.extglob),
+            "failglob" => Some(&mut self.failglob),
+            "globstar" => Some(&mut self.globstar),
+            "nocaseglob" => Some(&mut self.nocaseglob),
+            "nullglob" => Some(&mut self.nullglob),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,681 @@
+// Recursive-descent parser turning the token stream into an AST.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::ast::*;
+use crate::lexer::{scan_command_substitution, tokenize, unquote_delimiter, LexError, Operator, Token, TokenKind};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseError {
+    /// The input stopped in the middle of a construct; more lines may complete it.
+    Incomplete(String),
+    Syntax(String),
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseError::Incomplete(msg) => write!(f, "{}", msg),
+            ParseError::Syntax(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl From<LexError> for ParseError {
+    fn from(err: LexError) -> Self {
+        match err {
+            LexError::Incomplete(_) => ParseError::Incomplete(err.to_string()),
+        }
+    }
+}
+
+/// Words that are only special at the start of a command and close an enclosing construct.
+const TERMINATING_KEYWORDS: &[&str] = &["then", "else", "elif", "fi", "do", "done", "esac", "}"];
+
+/// Words that start a compound command, which is what a function body must be.
+const COMPOUND_KEYWORDS: &[&str] = &["{", "if", "while", "until", "for", "case", "[["];
+
+pub fn parse(input: &str) -> Result<List, ParseError> {
+    let tokens = tokenize(input)?;
+    let mut parser = Parser { src: input, tokens, pos: 0, aliases: None };
+    parser.parse_program()
+}
+
+/// `parse`, replacing the first word of each simple command when it is one
+/// of `aliases`.
+pub fn parse_with_aliases(input: &str, aliases: &HashMap<String, String>) -> Result<List, ParseError> {
+    let tokens = tokenize(input)?;
+    let mut parser = Parser { src: input, tokens, pos: 0, aliases: Some(aliases) };
+    parser.parse_program()
+}
+
+pub fn is_valid_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {}
+        _ => return false,
+    }
+    chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Function names may contain more than variable names, but nothing that
+/// would be expanded or look like an assignment.
+fn is_function_name(word: &str) -> bool {
+    !word.is_empty() && !word.contains(['$', '\'', '"', '`', '\\', '=']) && !word.starts_with(|c: char| c.is_ascii_digit())
+}
+
+/// Splits `NAME=value`, `NAME+=value`, `NAME[subscript]=value` or
+/// `NAME=(element...)` into its parts.
+pub fn split_assignment(raw: &str) -> Option<Assignment> {
+    let name_len = raw.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_')).unwrap_or(raw.len());
+    let name = &raw[..name_len];
+    if !is_valid_name(name) {
+        return None;
+    }
+    let mut rest = &raw[name_len..];
+    let mut subscript = None;
+    if rest.starts_with('[') {
+        let close = subscript_end(rest)?;
+        subscript = Some(rest[1..close].to_string());
+        rest = &rest[close + 1..];
+    }
+    let (append, value) = match rest.strip_prefix("+=") {
+        Some(value) => (true, value),
+        None => (false, rest.strip_prefix('=')?),
+    };
+    let array = if subscript.is_none() && value.starts_with('(') {
+        Some(split_array_elements(value)?)
+    } else {
+        None
+    };
+    Some(Assignment {
+        name: name.to_string(),
+        subscript,
+        append,
+        value: Word::new(if array.is_some() { "" } else { value }),
+        array,
+    })
+}
+
+/// Index of the `]` closing the `[` that `s` starts with.
+pub fn subscript_end(s: &str) -> Option<usize> {
+    let mut depth = 0;
+    for (i, c) in s.char_indices() {
+        match c {
+            '[' => depth += 1,
+            ']' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// The words between the parentheses of `(element...)`, which must make up
+/// the whole of `value`.
+fn split_array_elements(value: &str) -> Option<Vec<Word>> {
+    if scan_command_substitution(value, 0).ok()? != value.len() {
+        return None;
+    }
+    let mut words = Vec::new();
+    for token in tokenize(&value[1..value.len() - 1]).ok()? {
+        match token.kind {
+            TokenKind::Word(word) => words.push(Word::new(word)),
+            TokenKind::Newline => {}
+            _ => return None,
+        }
+    }
+    Some(words)
+}
+
+struct Parser<'a> {
+    src: &'a str,
+    tokens: Vec<Token>,
+    pos: usize,
+    /// Aliases to expand at the start of commands, if any.
+    aliases: Option<&'a HashMap<String, String>>,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn peek_kind(&self) -> Option<&TokenKind> {
+        self.peek().map(|t| &t.kind)
+    }
+
+    fn advance(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        if token.is_some() {
+            self.pos += 1;
+        }
+        token
+    }
+
+    fn peek_op(&self) -> Option<Operator> {
+        match self.peek_kind() {
+            Some(TokenKind::Op(op)) => Some(*op),
+            _ => None,
+        }
+    }
+
+    fn peek_word(&self) -> Option<&str> {
+        match self.peek_kind() {
+            Some(TokenKind::Word(w)) => Some(w.as_str()),
+            _ => None,
+        }
+    }
+
+    fn at_keyword(&self, keyword: &str) -> bool {
+        self.peek_word() == Some(keyword)
+    }
+
+    fn skip_newlines(&mut self) {
+        while matches!(self.peek_kind(), Some(TokenKind::Newline)) {
+            self.pos += 1;
+        }
+    }
+
+    fn describe_next(&self) -> String {
+        match self.peek_kind() {
+            Some(TokenKind::Word(w)) => w.clone(),
+            Some(TokenKind::IoNumber(n)) => n.to_string(),
+            Some(TokenKind::Op(op)) => op.as_str().to_string(),
+            Some(TokenKind::Newline) => "newline".to_string(),
+            None => "end of file".to_string(),
+        }
+    }
+
+    fn unexpected(&self) -> ParseError {
+        if self.peek().is_none() {
+            ParseError::Incomplete("syntax error: unexpected end of file".to_string())
+        } else {
+            ParseError::Syntax(format!("syntax error near unexpected token `{}'", self.describe_next()))
+        }
+    }
+
+    fn expect_keyword(&mut self, keyword: &str) -> Result<(), ParseError> {
+        if self.at_keyword(keyword) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(self.unexpected())
+        }
+    }
+
+    fn parse_program(&mut self) -> Result<List, ParseError> {
+        let list = self.parse_list(&[])?;
+        if self.peek().is_some() {
+            return Err(self.unexpected());
+        }
+        Ok(list)
+    }
+
+    /// Whether the next token ends the current list.
+    fn at_list_end(&self, stops: &[&str]) -> bool {
+        match self.peek_kind() {
+            None => true,
+            Some(TokenKind::Op(op)) => matches!(
+                op,
+                Operator::RParen | Operator::DSemi | Operator::SemiAnd | Operator::DSemiAnd
+            ),
+            Some(TokenKind::Word(w)) => stops.contains(&w.as_str()),
+            _ => false,
+        }
+    }
+
+    fn parse_list(&mut self, stops: &[&str]) -> Result<List, ParseError> {
+        let mut list = List::default();
+        loop {
+            self.skip_newlines();
+            if self.at_list_end(stops) {
+                break;
+            }
+            let and_or = self.parse_and_or()?;
+            let background = match self.peek_kind() {
+                Some(TokenKind::Op(Operator::Semi)) | Some(TokenKind::Newline) => {
+                    self.pos += 1;
+                    false
+                }
+                Some(TokenKind::Op(Operator::Amp)) => {
+                    self.pos += 1;
+                    true
+                }
+                _ => {
+                    list.items.push(ListItem { and_or, background: false });
+                    break;
+                }
+            };
+            list.items.push(ListItem { and_or, background });
+        }
+        Ok(list)
+    }
+
+    /// Parses a list that must contain at least one command.
+    fn parse_body(&mut self, stops: &[&str]) -> Result<List, ParseError> {
+        let list = self.parse_list(stops)?;
+        if list.items.is_empty() {
+            return Err(self.unexpected());
+        }
+        Ok(list)
+    }
+
+    fn parse_and_or(&mut self) -> Result<AndOr, ParseError> {
+        let start = self.peek().map_or(self.src.len(), |t| t.start);
+        let first = self.parse_pipeline()?;
+        let mut rest = Vec::new();
+        loop {
+            let connector = match self.peek_op() {
+                Some(Operator::AndIf) => Connector::And,
+                Some(Operator::OrIf) => Connector::Or,
+                _ => break,
+            };
+            self.pos += 1;
+            self.skip_newlines();
+            rest.push((connector, self.parse_pipeline()?));
+        }
+        let end = self.tokens[..self.pos].last().map_or(start, |t| t.end);
+        Ok(AndOr {
+            first,
+            rest,
+            text: self.src[start..end.max(start)].to_string(),
+        })
+    }
+
+    fn parse_pipeline(&mut self) -> Result<Pipeline, ParseError> {
+        let start = self.peek().map_or(self.src.len(), |t| t.start);
+        let mut negated = false;
+        while self.at_keyword("!") {
+            self.pos += 1;
+            negated = !negated;
+        }
+        let mut commands = vec![self.parse_command()?];
+        while let Some(op @ (Operator::Pipe | Operator::PipeAmp)) = self.peek_op() {
+            self.pos += 1;
+            if op == Operator::PipeAmp {
+                // `a |& b` is shorthand for `a 2>&1 | b`.
+                let redirect = Redirect {
+                    fd: Some(2),
+                    kind: RedirectKind::DupOutput,
+                    target: Word::new("1"),
+                    here_doc: None,
+                };
+                match commands.last_mut() {
+                    Some(Command::Simple(simple)) => simple.redirects.push(redirect),
+                    Some(Command::Compound(_, redirects)) => redirects.push(redirect),
+                    Some(Command::FunctionDef(_)) | None => {}
+                }
+            }
+            self.skip_newlines();
+            commands.push(self.parse_command()?);
+        }
+        let end = self.tokens[..self.pos].last().map_or(start, |t| t.end);
+        Ok(Pipeline {
+            negated,
+            commands,
+            text: self.src[start..end.max(start)].to_string(),
+        })
+    }
+
+    fn parse_command(&mut self) -> Result<Command, ParseError> {
+        self.expand_alias(self.pos, &mut Vec::new())?;
+        let compound = match self.peek_kind() {
+            None => return Err(self.unexpected()),
+            Some(TokenKind::Word(w)) => match w.as_str() {
+                "if" => self.parse_if()?,
+                "while" | "until" => self.parse_while()?,
+                "for" => self.parse_for()?,
+                "case" => self.parse_case()?,
+                "[[" => self.parse_conditional()?,
+                w if w.starts_with("((") => {
+                    let expression = Word::new(&w[2..w.len() - 2]);
+                    self.pos += 1;
+                    CompoundCommand::Arithmetic(expression)
+                }
+                "{" => self.parse_brace_group()?,
+                "function" => return self.parse_function(),
+                w if TERMINATING_KEYWORDS.contains(&w) => return Err(self.unexpected()),
+                _ if self.at_function_definition() => return self.parse_function(),
+                _ => return self.parse_simple_command().map(Command::Simple),
+            },
+            Some(TokenKind::Op(Operator::LParen)) => self.parse_subshell()?,
+            Some(_) => return self.parse_simple_command().map(Command::Simple),
+        };
+        let mut redirects = Vec::new();
+        while let Some(redirect) = self.parse_redirect()? {
+            redirects.push(redirect);
+        }
+        Ok(Command::Compound(compound, redirects))
+    }
+
+    /// Replaces the word at `at`, if it is an alias, with the tokens of its
+    /// value, which take the place of the word in the source.  The first word
+    /// of the value is looked up in turn, except for the aliases in
+    /// `expanding`, and a value ending in a blank makes the word after it
+    /// subject to alias expansion too.  Quoting any part of a word keeps it
+    /// from matching.
+    fn expand_alias(&mut self, at: usize, expanding: &mut Vec<String>) -> Result<(), ParseError> {
+        let Some(aliases) = self.aliases else {
+            return Ok(());
+        };
+        let Some(token) = self.tokens.get(at) else {
+            return Ok(());
+        };
+        let (name, value) = match &token.kind {
+            TokenKind::Word(word) if !expanding.contains(word) => match aliases.get(word) {
+                Some(value) => (word.clone(), value.clone()),
+                None => return Ok(()),
+            },
+            _ => return Ok(()),
+        };
+        let (start, end, line) = (token.start, token.end, token.line);
+        let replacement: Vec<Token> = tokenize(&value)?
+            .into_iter()
+            .map(|token| Token { start, end, line, ..token })
+            .collect();
+        let len = replacement.len();
+        self.tokens.splice(at..=at, replacement);
+
+        expanding.push(name);
+        // The word after the value first, while `at + len` still points at it
+        if value.ends_with([' ', '\t']) {
+            self.expand_alias(at + len, expanding)?;
+        }
+        if len > 0 {
+            self.expand_alias(at, expanding)?;
+        }
+        expanding.pop();
+        Ok(())
+    }
+
+    /// Whether the next tokens are `name (`, which starts a function definition.
+    fn at_function_definition(&self) -> bool {
+        let is_name = self.peek_word().is_some_and(is_function_name);
+        let paren = matches!(self.tokens.get(self.pos + 1).map(|t| &t.kind), Some(TokenKind::Op(Operator::LParen)));
+        is_name && paren
+    }
+
+    /// Parses `name () body` or `function name [()] body`.
+    fn parse_function(&mut self) -> Result<Command, ParseError> {
+        let keyword = self.at_keyword("function");
+        if keyword {
+            self.pos += 1;
+        }
+        let name = match self.peek_word() {
+            Some(name) if is_function_name(name) => name.to_string(),
+            _ => return Err(self.unexpected()),
+        };
+        self.pos += 1;
+        if self.peek_op() == Some(Operator::LParen) {
+            self.pos += 1;
+            if self.peek_op() != Some(Operator::RParen) {
+                return Err(self.unexpected());
+            }
+            self.pos += 1;
+        } else if !keyword {
+            return Err(self.unexpected());
+        }
+        self.skip_newlines();
+
+        let compound = self.peek_word().is_some_and(|w| COMPOUND_KEYWORDS.contains(&w) || w.starts_with("(("))
+            || self.peek_op() == Some(Operator::LParen);
+        if !compound {
+            return Err(self.unexpected());
+        }
+        let start = self.peek().map_or(self.src.len(), |t| t.start);
+        let body = self.parse_command()?;
+        let end = self.tokens[..self.pos].last().map_or(start, |t| t.end);
+        Ok(Command::FunctionDef(Arc::new(FunctionDef {
+            name,
+            body,
+            body_text: self.src[start..end.max(start)].to_string(),
+            line_offset: 0,
+        })))
+    }
+
+    fn parse_simple_command(&mut self) -> Result<SimpleCommand, ParseError> {
+        let mut command = SimpleCommand {
+            line: self.peek().map_or(0, |t| t.line),
+            ..SimpleCommand::default()
+        };
+        loop {
+            if let Some(redirect) = self.parse_redirect()? {
+                command.redirects.push(redirect);
+                continue;
+            }
+            let word = match self.peek_kind() {
+                Some(TokenKind::Word(w)) => w.clone(),
+                _ => break,
+            };
+            self.pos += 1;
+            if command.words.is_empty() {
+                if let Some(assignment) = split_assignment(&word) {
+                    command.assignments.push(assignment);
+                    continue;
+                }
+            }
+            command.words.push(Word::new(word));
+        }
+        if command.words.is_empty() && command.assignments.is_empty() && command.redirects.is_empty() {
+            return Err(self.unexpected());
+        }
+        Ok(command)
+    }
+
+    fn parse_redirect(&mut self) -> Result<Option<Redirect>, ParseError> {
+        let save = self.pos;
+        let fd = match self.peek_kind() {
+            Some(TokenKind::IoNumber(n)) => {
+                let n = *n;
+                self.pos += 1;
+                Some(n)
+            }
+            _ => None,
+        };
+        let op = match self.peek_op() {
+            Some(op) if op.is_redirection() => op,
+            _ => {
+                self.pos = save;
+                return Ok(None);
+            }
+        };
+        let kind = match op {
+            Operator::Less => RedirectKind::Input,
+            Operator::Great => RedirectKind::Output,
+            Operator::DGreat => RedirectKind::Append,
+            Operator::Clobber => RedirectKind::Clobber,
+            Operator::LessGreat => RedirectKind::ReadWrite,
+            Operator::LessAnd => RedirectKind::DupInput,
+            Operator::GreatAnd => RedirectKind::DupOutput,
+            Operator::AndGreat => RedirectKind::OutputAll,
+            Operator::AndDGreat => RedirectKind::AppendAll,
+            Operator::DLess | Operator::DLessDash => RedirectKind::HereDoc,
+            Operator::TLess => RedirectKind::HereString,
+            _ => unreachable!("{} is not a redirection operator", op.as_str()),
+        };
+        self.pos += 1;
+        let target = match self.peek_kind() {
+            Some(TokenKind::Word(w)) => Word::new(w.clone()),
+            _ => return Err(self.unexpected()),
+        };
+        let here_doc = match kind {
+            RedirectKind::HereDoc => {
+                // The lexer attaches the body once it has seen the following newline.
+                let Some(body) = self.tokens[self.pos].here_doc.clone() else {
+                    return Err(LexError::Incomplete(format!("here-document delimiter `{}'", target)).into());
+                };
+                let (_, quoted) = unquote_delimiter(&target.raw);
+                Some(HereDoc { body, expand: !quoted })
+            }
+            _ => None,
+        };
+        self.pos += 1;
+        Ok(Some(Redirect { fd, kind, target, here_doc }))
+    }
+
+    fn parse_brace_group(&mut self) -> Result<CompoundCommand, ParseError> {
+        self.expect_keyword("{")?;
+        let body = self.parse_body(&["}"])?;
+        self.expect_keyword("}")?;
+        Ok(CompoundCommand::BraceGroup(body))
+    }
+
+    fn parse_subshell(&mut self) -> Result<CompoundCommand, ParseError> {
+        let start = self.peek().map_or(self.src.len(), |t| t.start);
+        self.pos += 1;
+        let body = self.parse_body(&[])?;
+        if self.peek_op() != Some(Operator::RParen) {
+            return Err(self.unexpected());
+        }
+        self.pos += 1;
+        let end = self.tokens[self.pos - 1].end;
+        Ok(CompoundCommand::Subshell { body, text: self.src[start..end].to_string() })
+    }
+
+    fn parse_if(&mut self) -> Result<CompoundCommand, ParseError> {
+        self.expect_keyword("if")?;
+        let mut branches = Vec::new();
+        let mut else_branch = None;
+        loop {
+            let condition = self.parse_body(&["then"])?;
+            self.expect_keyword("then")?;
+            let body = self.parse_body(&["elif", "else", "fi"])?;
+            branches.push((condition, body));
+            if self.at_keyword("elif") {
+                self.pos += 1;
+                continue;
+            }
+            if self.at_keyword("else") {
+                self.pos += 1;
+                else_branch = Some(self.parse_body(&["fi"])?);
+            }
+            self.expect_keyword("fi")?;
+            break;
+        }
+        Ok(CompoundCommand::If { branches, else_branch })
+    }
+
+    fn parse_while(&mut self) -> Result<CompoundCommand, ParseError> {
+        let until = self.at_keyword("until");
+        self.pos += 1;
+        let condition = self.parse_body(&["do"])?;
+        self.expect_keyword("do")?;
+        let body = self.parse_body(&["done"])?;
+        self.expect_keyword("done")?;
+        Ok(if until {
+            CompoundCommand::Until { condition, body }
+        } else {
+            CompoundCommand::While { condition, body }
+        })
+    }
+
+    fn parse_for(&mut self) -> Result<CompoundCommand, ParseError> {
+        self.expect_keyword("for")?;
+        let var = match self.peek_word() {
+            Some(name) if is_valid_name(name) => name.to_string(),
+            _ => return Err(self.unexpected()),
+        };
+        self.pos += 1;
+        self.skip_newlines();
+        let mut words = None;
+        if self.at_keyword("in") {
+            self.pos += 1;
+            let mut list = Vec::new();
+            while let Some(TokenKind::Word(w)) = self.peek_kind() {
+                list.push(Word::new(w.clone()));
+                self.pos += 1;
+            }
+            words = Some(list);
+            match self.peek_kind() {
+                Some(TokenKind::Op(Operator::Semi)) | Some(TokenKind::Newline) => self.pos += 1,
+                _ => return Err(self.unexpected()),
+            }
+        } else if self.peek_op() == Some(Operator::Semi) {
+            self.pos += 1;
+        }
+        self.skip_newlines();
+        self.expect_keyword("do")?;
+        let body = self.parse_body(&["done"])?;
+        self.expect_keyword("done")?;
+        Ok(CompoundCommand::For { var, words, body })
+    }
+
+    fn parse_case(&mut self) -> Result<CompoundCommand, ParseError> {
+        self.expect_keyword("case")?;
+        let word = match self.peek_word() {
+            Some(word) => Word::new(word),
+            None => return Err(self.unexpected()),
+        };
+        self.pos += 1;
+        self.skip_newlines();
+        self.expect_keyword("in")?;
+
+        let mut items = Vec::new();
+        loop {
+            self.skip_newlines();
+            if self.at_keyword("esac") {
+                self.pos += 1;
+                break;
+            }
+            if self.peek_op() == Some(Operator::LParen) {
+                self.pos += 1;
+            }
+            let mut patterns = Vec::new();
+            loop {
+                match self.peek_word() {
+                    Some(pattern) => patterns.push(Word::new(pattern)),
+                    None => return Err(self.unexpected()),
+                }
+                self.pos += 1;
+                match self.peek_op() {
+                    Some(Operator::Pipe) => self.pos += 1,
+                    Some(Operator::RParen) => {
+                        self.pos += 1;
+                        break;
+                    }
+                    _ => return Err(self.unexpected()),
+                }
+            }
+
+            let body = self.parse_list(&["esac"])?;
+            let terminator = match self.peek_op() {
+                Some(Operator::DSemi) => CaseTerminator::Break,
+                Some(Operator::SemiAnd) => CaseTerminator::FallThrough,
+                Some(Operator::DSemiAnd) => CaseTerminator::Continue,
+                // The last arm may end at `esac` without a terminator
+                _ if self.at_keyword("esac") => {
+                    items.push(CaseItem { patterns, body, terminator: CaseTerminator::Break });
+                    continue;
+                }
+                _ => return Err(self.unexpected()),
+            };
+            self.pos += 1;
+            items.push(CaseItem { patterns, body, terminator });
+        }
+        Ok(CompoundCommand::Case { word, items })
+    }
+
+    /// Collects the operands of `[[ ... ]]`, where operators like `<` and `&&`
+    /// are conditional operators rather than redirections or list separators.
+    fn parse_conditional(&mut self) -> Result<CompoundCommand, ParseError> {
+        self.expect_keyword("[[")?;
+        let mut words = Vec::new();
+        loop {
+            match self.advance().map(|t| t.kind) {
+                None => return Err(ParseError::Incomplete("unexpected end of file while looking for `]]'".to_string())),
+                Some(TokenKind::Word(w)) if w == "]]" => break,
+                Some(TokenKind::Word(w)) => words.push(Word::new(w)),
+                Some(TokenKind::Op(op)) => words.push(Word::new(op.as_str())),
+                Some(TokenKind::IoNumber(n)) => words.push(Word::new(n.to_string())),
+                Some(TokenKind::Newline) => {}
+            }
+        }
+        Ok(CompoundCommand::Conditional(words))
+    }
+}
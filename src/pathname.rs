@@ -0,0 +1,129 @@
+// Pathname expansion.
+//
+// A glob is matched one `/`-separated component at a time against directory
+// listings, starting from the working directory for relative patterns.
+// Components without special characters are taken as they are.  As in
+// bash, names starting with `.` are only matched by a pattern that starts
+// with a literal `.` unless `dotglob` is set, and with `globstar` a `**`
+// component matches any depth of directories.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::options::ShoptOptions;
+use crate::pattern::Pattern;
+
+/// Whether `pattern` has unescaped special characters and so is a glob.
+pub fn is_glob(pattern: &str, options: &ShoptOptions) -> bool {
+    Pattern::parse(pattern, options.extglob).literal().is_none()
+}
+
+/// The paths matching `pattern`, sorted.  They are relative when the
+/// pattern is, resolved against `cwd`.
+pub fn expand(pattern: &str, cwd: &Path, options: &ShoptOptions) -> Vec<String> {
+    let absolute = pattern.starts_with('/');
+    // A trailing slash matches directories only
+    let dirs_only = pattern.ends_with('/');
+    let components: Vec<&str> = pattern.split('/').filter(|c| !c.is_empty()).collect();
+
+    // Paths matched so far, as they will be printed
+    let mut paths = vec![if absolute { "/".to_string() } else { String::new() }];
+    for (i, component) in components.iter().enumerate() {
+        let last = i == components.len() - 1;
+        let mut next = Vec::new();
+        if options.globstar && *component == "**" {
+            for path in &paths {
+                // Zero directories: the path itself
+                if !last {
+                    next.push(path.clone());
+                } else if !path.is_empty() {
+                    next.push(format!("{}/", path.trim_end_matches('/')));
+                }
+                descend(&resolve(cwd, path), path, !last, options.dotglob, &mut next);
+            }
+        } else {
+            let pattern = Pattern::parse(component, options.extglob).ignore_case(options.nocaseglob);
+            match pattern.literal() {
+                Some(name) => {
+                    for path in &paths {
+                        let joined = join(path, &name);
+                        if !last || fs::symlink_metadata(resolve(cwd, &joined)).is_ok() {
+                            next.push(joined);
+                        }
+                    }
+                }
+                None => {
+                    let dots = options.dotglob || component.starts_with('.') || component.starts_with("\\.");
+                    for path in &paths {
+                        for name in list(&resolve(cwd, path)) {
+                            if name.starts_with('.') && !dots || !pattern.matches(&name) {
+                                continue;
+                            }
+                            let joined = join(path, &name);
+                            if last || resolve(cwd, &joined).is_dir() {
+                                next.push(joined);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        paths = next;
+    }
+
+    if components.is_empty() {
+        return Vec::new();
+    }
+    if dirs_only {
+        paths.retain(|path| resolve(cwd, path).is_dir());
+        for path in paths.iter_mut().filter(|path| !path.ends_with('/')) {
+            path.push('/');
+        }
+    }
+    paths.sort();
+    paths.dedup();
+    paths
+}
+
+fn resolve(cwd: &Path, path: &str) -> PathBuf {
+    if path.is_empty() {
+        cwd.to_path_buf()
+    } else {
+        cwd.join(path)
+    }
+}
+
+fn join(path: &str, name: &str) -> String {
+    match path {
+        "" => name.to_string(),
+        "/" => format!("/{}", name),
+        _ => format!("{}/{}", path, name),
+    }
+}
+
+/// Names in a directory; an unreadable directory has none.
+fn list(dir: &Path) -> Vec<String> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    entries.flatten().map(|entry| entry.file_name().to_string_lossy().into_owned()).collect()
+}
+
+/// Adds everything below `dir`, or only the directories, for `**`.
+/// Symbolic links to directories are not followed.
+fn descend(dir: &Path, path: &str, dirs_only: bool, dotglob: bool, found: &mut Vec<String>) {
+    for name in list(dir) {
+        if name.starts_with('.') && !dotglob {
+            continue;
+        }
+        let joined = join(path, &name);
+        let child = dir.join(&name);
+        let is_dir = fs::symlink_metadata(&child).is_ok_and(|meta| meta.is_dir());
+        if is_dir || !dirs_only {
+            found.push(joined.clone());
+        }
+        if is_dir {
+            descend(&child, &joined, dirs_only, dotglob, found);
+        }
+    }
+}
@@ -0,0 +1,309 @@
+// Shell pattern matching.
+//
+// Patterns are written the way the expander hands them over: characters that
+// were quoted in the source arrive escaped with a backslash, so `"*"` only
+// matches a star while an unquoted `*` matches anything.
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Char(char),
+    /// `?`
+    Any,
+    /// `*`
+    Star,
+    /// `[...]`
+    Class { negated: bool, items: Vec<ClassItem> },
+    /// `?(...)`, `*(...)`, `+(...)`, `@(...)` or `!(...)` with its
+    /// `|`-separated alternatives
+    Ext(ExtKind, Vec<Vec<Token>>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ExtKind {
+    /// `?(...)`: zero or one of the alternatives
+    Optional,
+    /// `*(...)`: zero or more
+    Any,
+    /// `+(...)`: one or more
+    Some,
+    /// `@(...)`: exactly one
+    One,
+    /// `!(...)`: anything except one of them
+    Not,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum ClassItem {
+    Char(char),
+    Range(char, char),
+    /// `[:alpha:]` and friends
+    Named(String),
+}
+
+impl ClassItem {
+    fn matches(&self, c: char) -> bool {
+        match self {
+            ClassItem::Char(x) => *x == c,
+            ClassItem::Range(low, high) => (*low..=*high).contains(&c),
+            ClassItem::Named(name) => match name.as_str() {
+                "alnum" => c.is_alphanumeric(),
+                "alpha" => c.is_alphabetic(),
+                "blank" => c == ' ' || c == '\t',
+                "cntrl" => c.is_control(),
+                "digit" => c.is_ascii_digit(),
+                "graph" => c.is_ascii_graphic(),
+                "lower" => c.is_lowercase(),
+                "print" => c.is_ascii_graphic() || c == ' ',
+                "punct" => c.is_ascii_punctuation(),
+                "space" => c.is_whitespace(),
+                "upper" => c.is_uppercase(),
+                "xdigit" => c.is_ascii_hexdigit(),
+                _ => false,
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Pattern {
+    tokens: Vec<Token>,
+    nocase: bool,
+}
+
+impl Pattern {
+    /// Parses `pattern`; `extglob` enables the `?(...)` family of operators.
+    pub fn parse(pattern: &str, extglob: bool) -> Pattern {
+        let chars: Vec<char> = pattern.chars().collect();
+        let (tokens, _) = parse_tokens(&chars, 0, extglob, false);
+        Pattern { tokens, nocase: false }
+    }
+
+    /// Makes the pattern match letters regardless of case.
+    pub fn ignore_case(mut self, nocase: bool) -> Pattern {
+        self.nocase = nocase;
+        self
+    }
+
+    /// The text the pattern matches when it has no special characters.
+    pub fn literal(&self) -> Option<String> {
+        self.tokens
+            .iter()
+            .map(|token| match token {
+                Token::Char(c) => Some(*c),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Whether the pattern matches all of `text`.
+    pub fn matches(&self, text: &str) -> bool {
+        let text: Vec<char> = text.chars().collect();
+        match_tokens(&self.tokens, &text, self.nocase)
+    }
+}
+
+/// Parses tokens from `chars[start..]`.  Inside an extglob group (`nested`)
+/// parsing stops at an unescaped `|` or `)`, whose index is returned.
+fn parse_tokens(chars: &[char], start: usize, extglob: bool, nested: bool) -> (Vec<Token>, usize) {
+    let mut tokens = Vec::new();
+    let mut i = start;
+    while i < chars.len() {
+        if extglob && chars.get(i + 1) == Some(&'(') {
+            if let Some(kind) = ext_kind(chars[i]) {
+                if let Some((alternatives, end)) = parse_ext(chars, i + 2) {
+                    tokens.push(Token::Ext(kind, alternatives));
+                    i = end;
+                    continue;
+                }
+            }
+        }
+        match chars[i] {
+            '|' | ')' if nested => return (tokens, i),
+            '\\' if i + 1 < chars.len() => {
+                tokens.push(Token::Char(chars[i + 1]));
+                i += 2;
+            }
+            '?' => {
+                tokens.push(Token::Any);
+                i += 1;
+            }
+            '*' => {
+                // Consecutive stars match the same as one
+                if tokens.last() != Some(&Token::Star) {
+                    tokens.push(Token::Star);
+                }
+                i += 1;
+            }
+            '[' => match parse_class(chars, i) {
+                Some((token, end)) => {
+                    tokens.push(token);
+                    i = end;
+                }
+                None => {
+                    tokens.push(Token::Char('['));
+                    i += 1;
+                }
+            },
+            c => {
+                tokens.push(Token::Char(c));
+                i += 1;
+            }
+        }
+    }
+    (tokens, i)
+}
+
+fn ext_kind(c: char) -> Option<ExtKind> {
+    match c {
+        '?' => Some(ExtKind::Optional),
+        '*' => Some(ExtKind::Any),
+        '+' => Some(ExtKind::Some),
+        '@' => Some(ExtKind::One),
+        '!' => Some(ExtKind::Not),
+        _ => None,
+    }
+}
+
+/// Parses the alternatives of an extglob group whose first one starts at
+/// `start`.  Returns `None` when the group is not closed, in which case its
+/// characters are taken one by one.
+fn parse_ext(chars: &[char], start: usize) -> Option<(Vec<Vec<Token>>, usize)> {
+    let mut alternatives = Vec::new();
+    let mut i = start;
+    loop {
+        let (tokens, end) = parse_tokens(chars, i, true, true);
+        alternatives.push(tokens);
+        match chars.get(end) {
+            Some('|') => i = end + 1,
+            Some(')') => return Some((alternatives, end + 1)),
+            _ => return None,
+        }
+    }
+}
+
+/// Parses a bracket expression starting at `chars[start] == '['`.  Returns
+/// `None` when there is no closing bracket, in which case `[` is literal.
+fn parse_class(chars: &[char], start: usize) -> Option<(Token, usize)> {
+    let mut i = start + 1;
+    let negated = matches!(chars.get(i), Some('!') | Some('^'));
+    if negated {
+        i += 1;
+    }
+    let mut items = Vec::new();
+    let mut first = true;
+    loop {
+        let c = *chars.get(i)?;
+        if c == ']' && !first {
+            return Some((Token::Class { negated, items }, i + 1));
+        }
+        first = false;
+        if c == '[' && chars.get(i + 1) == Some(&':') {
+            let rest: String = chars[i + 2..].iter().collect();
+            if let Some(end) = rest.find(":]") {
+                items.push(ClassItem::Named(rest[..end].to_string()));
+                i += 2 + rest[..end].chars().count() + 2;
+                continue;
+            }
+        }
+        let (low, next) = match c {
+            '\\' => (*chars.get(i + 1)?, i + 2),
+            _ => (c, i + 1),
+        };
+        if chars.get(next) == Some(&'-') && chars.get(next + 1).is_some_and(|&c| c != ']') {
+            let (high, end) = match chars[next + 1] {
+                '\\' => (*chars.get(next + 2)?, next + 3),
+                high => (high, next + 2),
+            };
+            items.push(ClassItem::Range(low, high));
+            i = end;
+        } else {
+            items.push(ClassItem::Char(low));
+            i = next;
+        }
+    }
+}
+
+fn match_tokens(tokens: &[Token], text: &[char], nocase: bool) -> bool {
+    if tokens.iter().any(|token| matches!(token, Token::Ext(..))) {
+        return match_backtracking(tokens, text, nocase);
+    }
+    // Positions to resume from when the text after the last star fails to
+    // match: the classic single-backtrack algorithm, linear for one star.
+    let (mut t, mut s) = (0, 0);
+    let mut resume: Option<(usize, usize)> = None;
+    while s < text.len() {
+        match tokens.get(t) {
+            Some(Token::Star) => {
+                resume = Some((t, s));
+                t += 1;
+                continue;
+            }
+            Some(token) if token_matches(token, text[s], nocase) => {
+                t += 1;
+                s += 1;
+                continue;
+            }
+            _ => {}
+        }
+        match resume {
+            Some((star, from)) => {
+                t = star + 1;
+                s = from + 1;
+                resume = Some((star, from + 1));
+            }
+            None => return false,
+        }
+    }
+    tokens[t..].iter().all(|token| *token == Token::Star)
+}
+
+/// Matcher for patterns with extglob groups, which may match text of any
+/// length and so need general backtracking.
+fn match_backtracking(tokens: &[Token], text: &[char], nocase: bool) -> bool {
+    let Some((token, rest)) = tokens.split_first() else {
+        return text.is_empty();
+    };
+    match token {
+        Token::Star => (0..=text.len()).any(|i| match_backtracking(rest, &text[i..], nocase)),
+        Token::Ext(kind, alternatives) => (0..=text.len()).any(|i| {
+            match_ext(*kind, alternatives, &text[..i], nocase) && match_backtracking(rest, &text[i..], nocase)
+        }),
+        token => {
+            !text.is_empty() && token_matches(token, text[0], nocase) && match_backtracking(rest, &text[1..], nocase)
+        }
+    }
+}
+
+/// Whether an extglob group matches all of `text`.
+fn match_ext(kind: ExtKind, alternatives: &[Vec<Token>], text: &[char], nocase: bool) -> bool {
+    let one = |text: &[char]| alternatives.iter().any(|alt| match_tokens(alt, text, nocase));
+    match kind {
+        ExtKind::One => one(text),
+        ExtKind::Optional => text.is_empty() || one(text),
+        ExtKind::Not => !one(text),
+        ExtKind::Any => match_repeated(alternatives, text, nocase),
+        ExtKind::Some => (1..=text.len()).any(|i| one(&text[..i]) && match_repeated(alternatives, &text[i..], nocase)),
+    }
+}
+
+/// Whether `text` is a run of zero or more matches of the alternatives.
+fn match_repeated(alternatives: &[Vec<Token>], text: &[char], nocase: bool) -> bool {
+    text.is_empty()
+        || (1..=text.len()).any(|i| {
+            alternatives.iter().any(|alt| match_tokens(alt, &text[..i], nocase))
+                && match_repeated(alternatives, &text[i..], nocase)
+        })
+}
+
+fn token_matches(token: &Token, c: char, nocase: bool) -> bool {
+    // Whether `test` holds for `c`, or for its other case when ignoring case
+    let holds = |test: &dyn Fn(char) -> bool| {
+        test(c) || nocase && (c.to_lowercase().any(test) || c.to_uppercase().any(test))
+    };
+    match token {
+        Token::Char(x) => holds(&|c| c == *x),
+        Token::Any => true,
+        Token::Star | Token::Ext(..) => unreachable!("handled by the matchers"),
+        Token::Class { negated, items } => holds(&|c| items.iter().any(|item| item.matches(c))) != *negated,
+    }
+}
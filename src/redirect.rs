@@ -0,0 +1,249 @@
+// File descriptor redirections.
+//
+// Redirections are applied to the shell's own descriptors with dup2, so
+// builtins and spawned programs see the same thing.  Every descriptor that
+// gets replaced is first copied to a close-on-exec descriptor above 10 and
+// put back when the `SavedFds` guard is dropped.
+
+use std::io::{Seek, SeekFrom, Write};
+use std::os::unix::io::{IntoRawFd, RawFd};
+
+use nix::errno::Errno;
+use nix::fcntl::{fcntl, open, FcntlArg, FdFlag, OFlag};
+use nix::sys::stat::Mode;
+use nix::unistd::{close, dup2};
+
+use crate::ast::{Redirect, RedirectKind};
+use crate::{Shell, ShellError};
+
+/// Lowest descriptor used for the shell's private copies.
+const SAVED_FD_BASE: RawFd = 10;
+
+/// Original descriptors replaced by redirections, restored on drop.
+#[derive(Debug, Default)]
+pub struct SavedFds {
+    /// `(fd, copy)` in the order they were first touched; `None` when the
+    /// descriptor was closed beforehand.
+    saved: Vec<(RawFd, Option<RawFd>)>,
+}
+
+impl SavedFds {
+    fn save(&mut self, fd: RawFd) -> Result<(), ShellError> {
+        if self.saved.iter().any(|(saved, _)| *saved == fd) {
+            return Ok(());
+        }
+        let copy = match fcntl(fd, FcntlArg::F_DUPFD_CLOEXEC(SAVED_FD_BASE)) {
+            Ok(copy) => Some(copy),
+            Err(Errno::EBADF) => None,
+            Err(errno) => return Err(ShellError::IoError(errno.into())),
+        };
+        self.saved.push((fd, copy));
+        Ok(())
+    }
+
+    /// Makes `fd` refer to the same open file as `source`.
+    pub fn redirect(&mut self, source: RawFd, fd: RawFd) -> Result<(), ShellError> {
+        self.save(fd)?;
+        if source != fd {
+            flush_stdio();
+            dup2(source, fd).map_err(|errno| ShellError::IoError(errno.into()))?;
+        }
+        Ok(())
+    }
+
+    /// Closes `fd` until the guard is dropped.
+    pub fn close(&mut self, fd: RawFd) -> Result<(), ShellError> {
+        self.save(fd)?;
+        flush_stdio();
+        let _ = close(fd);
+        Ok(())
+    }
+
+    /// Opens `path` and installs it as `fd`.
+    fn open(&mut self, path: &str, flags: OFlag, fd: RawFd) -> Result<(), ShellError> {
+        // Save first so a closed `fd` handed out by open() is still closed again on restore.
+        self.save(fd)?;
+        let file = open(path, flags | OFlag::O_CLOEXEC, Mode::from_bits_truncate(0o666))
+            .map_err(|errno| open_error(path, errno))?;
+        self.install(file, fd)
+    }
+
+    /// Feeds `contents` to `fd` from an unlinked temporary file.
+    fn feed(&mut self, contents: &str, fd: RawFd) -> Result<(), ShellError> {
+        self.save(fd)?;
+        let mut file = tempfile::tempfile()?;
+        file.write_all(contents.as_bytes())?;
+        file.seek(SeekFrom::Start(0))?;
+        self.install(file.into_raw_fd(), fd)
+    }
+
+    /// Moves the freshly opened `file` onto `fd`, which has already been saved.
+    fn install(&mut self, file: RawFd, fd: RawFd) -> Result<(), ShellError> {
+        if file == fd {
+            fcntl(fd, FcntlArg::F_SETFD(FdFlag::empty())).map_err(|errno| ShellError::IoError(errno.into()))?;
+            return Ok(());
+        }
+        let result = self.redirect(file, fd);
+        let _ = close(file);
+        result
+    }
+}
+
+impl SavedFds {
+    /// Leaves the redirections in place for good, as `exec` without a
+    /// command does, by dropping the saved originals instead of restoring them.
+    pub fn keep(mut self) {
+        for (_, copy) in self.saved.drain(..) {
+            if let Some(copy) = copy {
+                let _ = close(copy);
+            }
+        }
+    }
+}
+
+impl Drop for SavedFds {
+    fn drop(&mut self) {
+        flush_stdio();
+        for (fd, copy) in self.saved.drain(..).rev() {
+            match copy {
+                Some(copy) => {
+                    let _ = dup2(copy, fd);
+                    let _ = close(copy);
+                }
+                None => {
+                    let _ = close(fd);
+                }
+            }
+        }
+    }
+}
+
+/// Placeholders on the free descriptors below `SAVED_FD_BASE`.  Those are
+/// left to the user (`exec 3>log`), so the runtime's own descriptors are
+/// opened while they are held and land above them.
+pub(crate) struct ReservedFds(Vec<RawFd>);
+
+impl ReservedFds {
+    pub fn take() -> Self {
+        let mut reserved = Vec::new();
+        while let Ok(fd) = open("/dev/null", OFlag::O_RDONLY | OFlag::O_CLOEXEC, Mode::empty()) {
+            if fd >= SAVED_FD_BASE {
+                let _ = close(fd);
+                break;
+            }
+            reserved.push(fd);
+        }
+        ReservedFds(reserved)
+    }
+}
+
+impl Drop for ReservedFds {
+    fn drop(&mut self) {
+        for &fd in &self.0 {
+            let _ = close(fd);
+        }
+    }
+}
+
+/// Pushes buffered output through before the descriptors underneath change.
+pub(crate) fn flush_stdio() {
+    let _ = std::io::stdout().flush();
+    let _ = std::io::stderr().flush();
+}
+
+fn open_error(path: &str, errno: Errno) -> ShellError {
+    match errno {
+        Errno::ENOENT => ShellError::FileNotFound(path.to_string()),
+        Errno::EACCES => ShellError::PermissionDenied(path.to_string()),
+        errno => ShellError::Redirection(format!("{}: {}", path, errno.desc())),
+    }
+}
+
+fn is_open(fd: RawFd) -> bool {
+    fcntl(fd, FcntlArg::F_GETFD).is_ok()
+}
+
+impl Shell {
+    /// Applies `redirects` left to right to the shell's descriptors.
+    pub(crate) async fn apply_redirections(&mut self, redirects: &[Redirect]) -> Result<SavedFds, ShellError> {
+        let mut saved = SavedFds::default();
+        self.apply_redirections_to(&mut saved, redirects).await?;
+        Ok(saved)
+    }
+
+    pub(crate) async fn apply_redirections_to(&mut self, saved: &mut SavedFds, redirects: &[Redirect]) -> Result<(), ShellError> {
+        let truncate = OFlag::O_WRONLY | OFlag::O_CREAT | OFlag::O_TRUNC;
+        let append = OFlag::O_WRONLY | OFlag::O_CREAT | OFlag::O_APPEND;
+        for redirect in redirects {
+            let fd = redirect.target_fd();
+            if let Some(here_doc) = &redirect.here_doc {
+                let body = if here_doc.expand {
+                    self.expand_here_document(&here_doc.body).await?
+                } else {
+                    here_doc.body.clone()
+                };
+                saved.feed(&body, fd)?;
+                continue;
+            }
+            let target = self.expand_word(&redirect.target).await?;
+            match redirect.kind {
+                RedirectKind::HereDoc => unreachable!("here-document without a body"),
+                RedirectKind::HereString => saved.feed(&format!("{}\n", target), fd)?,
+                RedirectKind::Input => saved.open(&target, OFlag::O_RDONLY, fd)?,
+                RedirectKind::Output => {
+                    self.check_noclobber(&target).await?;
+                    saved.open(&target, truncate, fd)?;
+                }
+                RedirectKind::Clobber => saved.open(&target, truncate, fd)?,
+                RedirectKind::Append => saved.open(&target, append, fd)?,
+                RedirectKind::ReadWrite => saved.open(&target, OFlag::O_RDWR | OFlag::O_CREAT, fd)?,
+                RedirectKind::DupInput | RedirectKind::DupOutput => {
+                    if target == "-" {
+                        saved.close(fd)?;
+                    } else if let Ok(source) = target.trim_end_matches('-').parse::<RawFd>() {
+                        if !is_open(source) {
+                            return Err(ShellError::Redirection(format!("{}: Bad file descriptor", source)));
+                        }
+                        saved.redirect(source, fd)?;
+                        // `N>&M-` moves the descriptor instead of copying it
+                        if target.ends_with('-') && source != fd {
+                            saved.close(source)?;
+                        }
+                    } else if redirect.kind == RedirectKind::DupOutput && redirect.fd.is_none() {
+                        // `>&file` is the older spelling of `&>file`
+                        self.check_noclobber(&target).await?;
+                        saved.open(&target, truncate, 1)?;
+                        saved.redirect(1, 2)?;
+                    } else {
+                        return Err(ShellError::Redirection(format!("{}: ambiguous redirect", target)));
+                    }
+                }
+                RedirectKind::OutputAll | RedirectKind::AppendAll => {
+                    let flags = if redirect.kind == RedirectKind::OutputAll {
+                        self.check_noclobber(&target).await?;
+                        truncate
+                    } else {
+                        append
+                    };
+                    saved.open(&target, flags, 1)?;
+                    saved.redirect(1, 2)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// With `set -C`, `>` refuses to truncate an existing regular file.
+    async fn check_noclobber(&self, target: &str) -> Result<(), ShellError> {
+        if !self.options.read().await.noclobber {
+            return Ok(());
+        }
+        let path = self.current_dir.read().await.join(target);
+        match std::fs::metadata(path) {
+            Ok(metadata) if metadata.is_file() => {
+                Err(ShellError::Redirection(format!("{}: cannot overwrite existing file", target)))
+            }
+            _ => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,99 @@
+// Running commands that are not typed at the prompt.
+//
+// `-c` strings, script files and scripts piped to the shell are read one
+// complete command at a time, as bash does: each command runs before the
+// next one is parsed, a syntax error stops the script only where it occurs,
+// and commands that read stdin get the rest of a script fed through it.
+
+use crate::parser::{self, ParseError};
+use crate::streams::Input;
+use crate::{Shell, ShellError};
+
+/// Where the lines of a script come from.
+pub enum ScriptSource {
+    Text(String),
+    /// Read a line at a time, leaving the rest for the commands themselves.
+    Stdin(Input),
+}
+
+impl ScriptSource {
+    /// The next line including its newline, `None` at end of input.
+    fn next_line(&mut self) -> Result<Option<String>, ShellError> {
+        match self {
+            ScriptSource::Text(text) => {
+                if text.is_empty() {
+                    return Ok(None);
+                }
+                let end = text.find('\n').map_or(text.len(), |i| i + 1);
+                Ok(Some(text.drain(..end).collect()))
+            }
+            ScriptSource::Stdin(input) => Ok(input.read_line()?.map(|line| line + "\n")),
+        }
+    }
+}
+
+impl Shell {
+    /// Runs every command of `source` and returns the status of the last.
+    /// Errors are reported under `name`; a syntax error ends the script with
+    /// status 2.  `exit`, `return` and Ctrl-C unwind out of it to the caller.
+    pub(crate) async fn run_script(&mut self, mut source: ScriptSource, name: &str) -> Result<i32, ShellError> {
+        let mut status = 0;
+        let mut buffer = String::new();
+        let mut line_number = 0;
+        // Line on which the command in `buffer` starts
+        let mut start_line = 1;
+        loop {
+            let line = source.next_line()?;
+            let at_end = line.is_none();
+            if let Some(line) = line {
+                if self.options.read().await.verbose {
+                    eprint!("{}", line);
+                }
+                if buffer.is_empty() {
+                    start_line = line_number + 1;
+                }
+                line_number += 1;
+                buffer.push_str(&line);
+            }
+
+            let list = match parser::parse(&buffer) {
+                Ok(list) => list,
+                Err(ParseError::Incomplete(_)) if !at_end => continue,
+                Err(e) => {
+                    eprintln!("{}: line {}: {}", name, start_line, e);
+                    return Ok(2);
+                }
+            };
+            buffer.clear();
+            // Blank lines and comments leave `$?` alone
+            if list.items.is_empty() {
+                if at_end {
+                    return Ok(status);
+                }
+                continue;
+            }
+            self.line.write().await.0 = start_line - 1;
+            status = match self.execute_list(&list).await {
+                Ok(status) => status,
+                Err(e @ (ShellError::Exit(_)
+                | ShellError::Return(_)
+                | ShellError::Break(_)
+                | ShellError::Continue(_)
+                | ShellError::Interrupted)) => return Err(e),
+                // A failed expansion ends a non-interactive shell
+                Err(e @ ShellError::Expansion(_)) => {
+                    eprintln!("{}: line {}: {}", name, start_line, e);
+                    return Err(ShellError::Exit(1));
+                }
+                Err(e) => {
+                    eprintln!("{}: line {}: {}", name, start_line, e);
+                    1
+                }
+            };
+            *self.exit_code.write().await = status;
+            if at_end {
+                return Ok(status);
+            }
+        }
+    }
+}
@@ -0,0 +1,116 @@
+// Signals the shell catches for itself.
+//
+// An interactive shell must survive Ctrl-C.  SIGINT only reaches the shell
+// when it owns the terminal in cooked mode, i.e. while a builtin or a loop of
+// builtins is running, or when job control is off and children share its
+// process group.  The handler just records the signal; the executor checks
+// the flag between commands and unwinds with `ShellError::Interrupted`.  The
+// handler is installed without SA_RESTART so a blocking read in `read` is
+// interrupted too.
+//
+// Signals with a trap are caught the same way, each recorded in a bit of
+// `PENDING` for the executor to run its trap.  SIGCHLD is the exception:
+// its handler belongs to the job reaper, so the trap is hooked in next to
+// it through `signal_hook` rather than replacing it.
+
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Once;
+
+use nix::sys::signal::{sigaction, SaFlags, SigAction, SigHandler, SigSet, Signal};
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+/// Trapped signals that have arrived, one bit per signal number.
+static PENDING: AtomicU64 = AtomicU64::new(0);
+/// Signals ignored with `trap ''`, which started commands keep ignoring.
+static IGNORED: AtomicU64 = AtomicU64::new(0);
+static CHILD_TRAPPED: AtomicBool = AtomicBool::new(false);
+static CHILD_HOOK: Once = Once::new();
+
+extern "C" fn on_interrupt(_: libc::c_int) {
+    INTERRUPTED.store(true, Ordering::SeqCst);
+}
+
+/// Catches SIGINT instead of letting it terminate the shell.
+pub fn catch_interrupt() {
+    let action = SigAction::new(SigHandler::Handler(on_interrupt), SaFlags::empty(), SigSet::empty());
+    unsafe {
+        let _ = sigaction(Signal::SIGINT, &action);
+    }
+}
+
+/// Whether SIGINT has arrived since the flag was last cleared.
+pub fn interrupt_pending() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
+
+/// Clears the flag, returning whether SIGINT had arrived.
+pub fn take_interrupt() -> bool {
+    INTERRUPTED.swap(false, Ordering::SeqCst)
+}
+
+fn bit(signal: Signal) -> u64 {
+    1 << signal as i32
+}
+
+extern "C" fn on_trapped(signal: libc::c_int) {
+    PENDING.fetch_or(1 << signal, Ordering::SeqCst);
+}
+
+fn set_handler(signal: Signal, handler: SigHandler) {
+    let action = SigAction::new(handler, SaFlags::empty(), SigSet::empty());
+    unsafe {
+        let _ = sigaction(signal, &action);
+    }
+}
+
+/// Catches `signal` so that its trap can run.
+pub fn catch(signal: Signal) {
+    IGNORED.fetch_and(!bit(signal), Ordering::SeqCst);
+    if signal == Signal::SIGCHLD {
+        CHILD_HOOK.call_once(|| unsafe {
+            let _ = signal_hook::low_level::register(libc::SIGCHLD, || {
+                if CHILD_TRAPPED.load(Ordering::SeqCst) {
+                    PENDING.fetch_or(bit(Signal::SIGCHLD), Ordering::SeqCst);
+                }
+            });
+        });
+        CHILD_TRAPPED.store(true, Ordering::SeqCst);
+        return;
+    }
+    set_handler(signal, SigHandler::Handler(on_trapped));
+}
+
+/// Ignores `signal` in the shell and in the commands it starts.
+pub fn ignore(signal: Signal) {
+    if signal == Signal::SIGCHLD {
+        // Ignoring SIGCHLD would have the kernel reap children behind the
+        // reaper's back
+        CHILD_TRAPPED.store(false, Ordering::SeqCst);
+        return;
+    }
+    IGNORED.fetch_or(bit(signal), Ordering::SeqCst);
+    set_handler(signal, SigHandler::SigIgn);
+}
+
+/// Drops the trap on `signal`, leaving it to `handler`.
+pub fn untrap(signal: Signal, handler: SigHandler) {
+    IGNORED.fetch_and(!bit(signal), Ordering::SeqCst);
+    PENDING.fetch_and(!bit(signal), Ordering::SeqCst);
+    if signal == Signal::SIGCHLD {
+        CHILD_TRAPPED.store(false, Ordering::SeqCst);
+        return;
+    }
+    set_handler(signal, handler);
+}
+
+/// Whether `trap ''` ignores `signal`.
+pub fn is_ignored(signal: Signal) -> bool {
+    IGNORED.load(Ordering::SeqCst) & bit(signal) != 0
+}
+
+/// Clears the trapped signals that have arrived and returns them, lowest
+/// number first.
+pub fn take_pending() -> Vec<Signal> {
+    let pending = PENDING.swap(0, Ordering::SeqCst);
+    Signal::iterator().filter(|&signal| pending & bit(signal) != 0).collect()
+}
@@ -0,0 +1,82 @@
+// Standard streams as seen by builtins.
+//
+// Builtins read and write through these handles rather than the process-wide
+// `std::io` objects.  Every call goes straight to the descriptor, so whatever
+// redirections or pipes are in place at the time are honoured, nothing stays
+// behind in a buffer across a fork, and a failed write is returned as an
+// error instead of panicking the shell.
+
+use std::io;
+use std::os::unix::io::RawFd;
+
+use nix::errno::Errno;
+use nix::unistd;
+
+use crate::signals;
+
+/// A writable descriptor.
+#[derive(Debug)]
+pub struct Output {
+    fd: RawFd,
+}
+
+impl io::Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        loop {
+            match unistd::write(self.fd, buf) {
+                Err(Errno::EINTR) => continue,
+                result => return result.map_err(io::Error::from),
+            }
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A readable descriptor, read without buffering so that nothing past the
+/// requested data is taken away from other readers of the same file.
+#[derive(Debug)]
+pub struct Input {
+    fd: RawFd,
+}
+
+impl Input {
+    /// Reads one line without its newline; `None` at end of input.
+    pub fn read_line(&mut self) -> io::Result<Option<String>> {
+        let mut line = Vec::new();
+        let mut byte = [0u8; 1];
+        loop {
+            match unistd::read(self.fd, &mut byte) {
+                Ok(0) if line.is_empty() => return Ok(None),
+                Ok(0) => break,
+                Ok(_) if byte[0] == b'\n' => break,
+                Ok(_) => line.push(byte[0]),
+                // Ctrl-C abandons the read; other signals just restart it
+                Err(Errno::EINTR) if !signals::interrupt_pending() => continue,
+                Err(errno) => return Err(errno.into()),
+            }
+        }
+        Ok(Some(String::from_utf8_lossy(&line).into_owned()))
+    }
+}
+
+/// The stdin, stdout and stderr a builtin runs with.
+#[derive(Debug)]
+pub struct Streams {
+    pub stdin: Input,
+    pub stdout: Output,
+    pub stderr: Output,
+}
+
+impl Streams {
+    /// Descriptors 0, 1 and 2 as currently set up by redirections and pipes.
+    pub fn standard() -> Self {
+        Streams {
+            stdin: Input { fd: 0 },
+            stdout: Output { fd: 1 },
+            stderr: Output { fd: 2 },
+        }
+    }
+}
@@ -0,0 +1,147 @@
+// Command and process substitution.
+//
+// `$(...)` and backquotes run their commands in a forked copy of the shell,
+// so functions, builtins and pipelines all work and nothing they change
+// leaks back.  The output is read from a pipe until the child closes it.
+//
+// `<(...)` and `>(...)` also run in a forked shell, connected to a pipe
+// whose other end the shell keeps open as `/dev/fd/N` for the command that
+// takes the path.  Once that command finishes the shell closes its end and
+// waits for the helper.
+
+use std::fs::File;
+use std::io::Read;
+use std::os::fd::{FromRawFd, RawFd};
+
+use nix::errno::Errno;
+use nix::fcntl::{fcntl, FcntlArg, OFlag};
+use nix::sys::wait::waitpid;
+use nix::unistd::{close, dup2, fork, pipe2, ForkResult, Pid};
+
+use crate::jobs::{wait_until_done, Job};
+use crate::redirect::flush_stdio;
+use crate::script::ScriptSource;
+use crate::{child_status, exit_child, Shell, ShellError};
+
+/// Lowest descriptor used for the shell's end of a process substitution,
+/// out of the way of descriptors scripts use themselves.
+const PROCESS_SUBSTITUTION_FD_BASE: RawFd = 63;
+
+/// A running `<(...)` or `>(...)`.
+#[derive(Debug)]
+pub struct ProcessSubstitution {
+    /// The shell's end of the pipe, named by the `/dev/fd` path.
+    fd: RawFd,
+    pid: Pid,
+}
+
+impl Shell {
+    /// Runs `command` in a subshell and returns its output less trailing
+    /// newlines.  Its status becomes `$?`.
+    pub(crate) async fn command_substitution(&mut self, command: &str) -> Result<String, ShellError> {
+        let (read, write) = pipe2(OFlag::O_CLOEXEC).map_err(|errno| ShellError::IoError(errno.into()))?;
+        flush_stdio();
+        let child = match unsafe { fork() } {
+            Ok(ForkResult::Parent { child }) => child,
+            Ok(ForkResult::Child) => {
+                let _ = close(read);
+                let _ = dup2(write, 1);
+                let _ = close(write);
+                self.enter_subshell().await;
+                // As in bash, `set -e` does not carry into the substitution
+                self.options.write().await.errexit = false;
+                self.job_setup(false).await.enter_child();
+                let name = self.arg0.clone();
+                let status = child_status(Box::pin(self.run_script(ScriptSource::Text(command.to_string()), &name)).await);
+                exit_child(self.run_exit_trap(status).await)
+            }
+            Err(errno) => {
+                let _ = close(read);
+                let _ = close(write);
+                return Err(ShellError::IoError(errno.into()));
+            }
+        };
+        let _ = close(write);
+
+        let mut output = Vec::new();
+        let read_result = unsafe { File::from_raw_fd(read) }.read_to_end(&mut output);
+        let mut job = Job::new(command);
+        job.push(child);
+        wait_until_done(&mut job);
+        let status = job.status();
+        *self.exit_code.write().await = status;
+        *self.substitution_status.write().await = Some(status);
+        read_result?;
+
+        let mut output = String::from_utf8_lossy(&output).into_owned();
+        while output.ends_with('\n') {
+            output.pop();
+        }
+        Ok(output)
+    }
+
+    /// Starts `command` with its output (or, for `>(...)`, its input)
+    /// connected to a pipe and returns the path of the shell's end.
+    pub(crate) async fn process_substitution(&mut self, command: &str, output: bool) -> Result<String, ShellError> {
+        let (read, write) = pipe2(OFlag::O_CLOEXEC).map_err(|errno| ShellError::IoError(errno.into()))?;
+        let (ours, theirs, target) = if output { (write, read, 0) } else { (read, write, 1) };
+        flush_stdio();
+        let pid = match unsafe { fork() } {
+            Ok(ForkResult::Parent { child }) => child,
+            Ok(ForkResult::Child) => {
+                let _ = close(ours);
+                let _ = dup2(theirs, target);
+                let _ = close(theirs);
+                self.enter_subshell().await;
+                self.job_setup(false).await.enter_child();
+                let name = self.arg0.clone();
+                let status = child_status(Box::pin(self.run_script(ScriptSource::Text(command.to_string()), &name)).await);
+                exit_child(self.run_exit_trap(status).await)
+            }
+            Err(errno) => {
+                let _ = close(read);
+                let _ = close(write);
+                return Err(ShellError::IoError(errno.into()));
+            }
+        };
+        let _ = close(theirs);
+        // A copy without close-on-exec, so that commands inherit it
+        let fd = fcntl(ours, FcntlArg::F_DUPFD(PROCESS_SUBSTITUTION_FD_BASE));
+        let _ = close(ours);
+        let fd = fd.map_err(|errno| ShellError::IoError(errno.into()))?;
+        self.process_substitutions.write().await.push(ProcessSubstitution { fd, pid });
+        Ok(format!("/dev/fd/{}", fd))
+    }
+
+    /// How many process substitutions are open; a command passes this to
+    /// `finish_process_substitutions` to clean up the ones it started.
+    pub(crate) async fn process_substitution_mark(&self) -> usize {
+        self.process_substitutions.read().await.len()
+    }
+
+    /// Closes the process substitutions started since `mark` and waits for
+    /// their helpers.  Those of a background command are reaped by a thread
+    /// instead, so that the shell does not wait for the command.
+    pub(crate) async fn finish_process_substitutions(&self, mark: usize, background: bool) {
+        let finished: Vec<ProcessSubstitution> = {
+            let mut substitutions = self.process_substitutions.write().await;
+            if substitutions.len() <= mark {
+                return;
+            }
+            substitutions.drain(mark..).collect()
+        };
+        for substitution in &finished {
+            let _ = close(substitution.fd);
+        }
+        let reap = move || {
+            for substitution in finished {
+                while waitpid(substitution.pid, None) == Err(Errno::EINTR) {}
+            }
+        };
+        if background {
+            std::thread::spawn(reap);
+        } else {
+            reap();
+        }
+    }
+}
@@ -0,0 +1,331 @@
+// Traps.
+//
+// `trap` attaches commands to signals and to four conditions of the shell's
+// own: EXIT as the shell ends, ERR when a command fails where `set -e` would
+// end the shell, DEBUG before each simple command and RETURN when a function
+// or sourced script returns.  A trapped signal is only recorded when it
+// arrives (see `signals`); the executor runs the trap between commands.  An
+// empty action ignores the signal, and so do the commands the shell starts.
+//
+// A subshell keeps the signals its parent ignores but none of the other
+// traps, although `trap` with no operands lists the parent's until the
+// subshell sets one of its own, so that `saved=$(trap -p)` works.  As in
+// bash without `set -T`, functions do not inherit DEBUG, ERR and RETURN:
+// the traps on them are put aside while a function runs and come back when
+// it returns, unless it set new ones, which stay.
+
+use std::collections::BTreeMap;
+use std::io::Write;
+
+use nix::sys::signal::{SigHandler, Signal};
+
+use crate::ast::SimpleCommand;
+use crate::jobs::parse_signal;
+use crate::redirect::flush_stdio;
+use crate::script::ScriptSource;
+use crate::streams::Streams;
+use crate::{exit_child, expand, signals, Shell, ShellError};
+
+/// While an EXIT trap is set these are caught, unless trapped themselves,
+/// so that the trap runs before the shell dies of them.
+const FATAL_SIGNALS: [Signal; 2] = [Signal::SIGHUP, Signal::SIGTERM];
+
+/// The conditions whose traps functions do not inherit.
+const FUNCTION_CONDITIONS: [Condition; 3] = [Condition::Debug, Condition::Err, Condition::Return];
+
+/// What a trap is set on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Condition {
+    Exit,
+    Signal(Signal),
+    Debug,
+    Err,
+    Return,
+}
+
+impl Condition {
+    /// Parses `EXIT`, `DEBUG`, `ERR`, `RETURN`, or a signal given as a
+    /// number, `INT` or `SIGINT`; 0 is EXIT.
+    fn parse(spec: &str) -> Option<Condition> {
+        match spec.to_ascii_uppercase().as_str() {
+            "0" | "EXIT" | "SIGEXIT" => Some(Condition::Exit),
+            "DEBUG" => Some(Condition::Debug),
+            "ERR" => Some(Condition::Err),
+            "RETURN" => Some(Condition::Return),
+            _ => parse_signal(spec).map(Condition::Signal),
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Condition::Exit => "EXIT",
+            Condition::Signal(signal) => signal.as_str(),
+            Condition::Debug => "DEBUG",
+            Condition::Err => "ERR",
+            Condition::Return => "RETURN",
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Traps {
+    /// Commands to run for each condition; empty to ignore a signal.
+    traps: BTreeMap<Condition, String>,
+    /// The parent's traps, which a subshell lists until it sets its own.
+    inherited: Option<BTreeMap<Condition, String>>,
+    /// Conditions whose trap is running; they do not fire again meanwhile.
+    running: Vec<Condition>,
+}
+
+/// The text of a simple command for `BASH_COMMAND`.
+pub fn command_text(command: &SimpleCommand) -> String {
+    let assignments = command.assignments.iter().map(|assignment| {
+        let subscript = assignment.subscript.as_ref().map(|s| format!("[{}]", s)).unwrap_or_default();
+        let operator = if assignment.append { "+=" } else { "=" };
+        let value = match &assignment.array {
+            Some(elements) => format!("({})", elements.iter().map(|e| e.raw.as_str()).collect::<Vec<_>>().join(" ")),
+            None => assignment.value.raw.clone(),
+        };
+        format!("{}{}{}{}", assignment.name, subscript, operator, value)
+    });
+    assignments.chain(command.words.iter().map(|word| word.raw.clone())).collect::<Vec<_>>().join(" ")
+}
+
+impl Shell {
+    pub(crate) async fn builtin_trap(&mut self, args: &[String], io: &mut Streams) -> Result<i32, ShellError> {
+        let mut args = args;
+        let mut print = false;
+        loop {
+            match args.first().map(String::as_str) {
+                Some("-l") => {
+                    list_signals(io)?;
+                    return Ok(0);
+                }
+                Some("-p") => {
+                    print = true;
+                    args = &args[1..];
+                }
+                Some("--") => {
+                    args = &args[1..];
+                    break;
+                }
+                Some(option) if option.len() > 1 && option.starts_with('-') => {
+                    writeln!(io.stderr, "trap: {}: invalid option", option)?;
+                    writeln!(io.stderr, "trap: usage: trap [-lp] [[arg] signal_spec ...]")?;
+                    return Ok(2);
+                }
+                _ => break,
+            }
+        }
+        if print || args.is_empty() {
+            return self.print_traps(args, io).await;
+        }
+
+        // A lone condition, or a list of them starting with a number, is
+        // reset to its default like `trap - ...`
+        let reset = args[0] == "-" || args.len() == 1 || args[0].parse::<u32>().is_ok();
+        let (action, specs) = match (reset, args[0] == "-") {
+            (true, true) => (None, &args[1..]),
+            (true, false) => (None, args),
+            (false, _) => (Some(&args[0]), &args[1..]),
+        };
+        let mut status = 0;
+        for spec in specs {
+            match Condition::parse(spec) {
+                Some(condition) => self.set_trap(condition, action.cloned()).await,
+                None => {
+                    writeln!(io.stderr, "trap: {}: invalid signal specification", spec)?;
+                    status = 1;
+                }
+            }
+        }
+        Ok(status)
+    }
+
+    /// `trap -p`: the traps on `specs`, or all of them, as commands that
+    /// set them again.
+    async fn print_traps(&self, specs: &[String], io: &mut Streams) -> Result<i32, ShellError> {
+        let traps = self.traps.read().await;
+        let listed = traps.inherited.as_ref().unwrap_or(&traps.traps);
+        let mut status = 0;
+        let mut conditions = Vec::new();
+        for spec in specs {
+            match Condition::parse(spec) {
+                Some(condition) => conditions.push(condition),
+                None => {
+                    writeln!(io.stderr, "trap: {}: invalid signal specification", spec)?;
+                    status = 1;
+                }
+            }
+        }
+        for (condition, action) in listed {
+            if specs.is_empty() || conditions.contains(condition) {
+                writeln!(io.stdout, "trap -- {} {}", expand::quote(action), condition.name())?;
+            }
+        }
+        Ok(status)
+    }
+
+    /// Sets the trap on `condition` to `action`, or resets it with `None`.
+    async fn set_trap(&mut self, condition: Condition, action: Option<String>) {
+        {
+            let mut traps = self.traps.write().await;
+            traps.inherited = None;
+            match &action {
+                Some(action) => traps.traps.insert(condition, action.clone()),
+                None => traps.traps.remove(&condition),
+            };
+        }
+        if let Condition::Signal(signal) = condition {
+            match action.as_deref() {
+                Some("") => signals::ignore(signal),
+                Some(_) => signals::catch(signal),
+                None => self.untrap_signal(signal),
+            }
+        }
+        if condition == Condition::Exit || matches!(condition, Condition::Signal(signal) if FATAL_SIGNALS.contains(&signal)) {
+            self.update_fatal_signals().await;
+        }
+    }
+
+    /// Gives `signal` back the handling the shell has for it untrapped.
+    fn untrap_signal(&self, signal: Signal) {
+        let job_control = self.terminal.is_some() && matches!(signal, Signal::SIGTSTP | Signal::SIGTTIN | Signal::SIGTTOU);
+        signals::untrap(signal, if job_control { SigHandler::SigIgn } else { SigHandler::SigDfl });
+        if signal == Signal::SIGINT && self.interactive {
+            signals::catch_interrupt();
+        }
+    }
+
+    async fn update_fatal_signals(&self) {
+        let traps = self.traps.read().await;
+        let exit = traps.traps.contains_key(&Condition::Exit);
+        for signal in FATAL_SIGNALS {
+            if traps.traps.contains_key(&Condition::Signal(signal)) {
+                continue;
+            }
+            if exit {
+                signals::catch(signal);
+            } else {
+                self.untrap_signal(signal);
+            }
+        }
+    }
+
+    /// Whether `signal` has a trap, ignoring it or not.
+    pub(crate) async fn is_trapped(&self, signal: Signal) -> bool {
+        self.traps.read().await.traps.contains_key(&Condition::Signal(signal))
+    }
+
+    /// Runs the trap on `condition`, if it has one in effect, leaving `$?`
+    /// as it was.  `exit` and `return` in the trap unwind as usual.
+    pub(crate) async fn run_trap(&mut self, condition: Condition) -> Result<(), ShellError> {
+        let action = {
+            let traps = self.traps.read().await;
+            if traps.running.contains(&condition) {
+                return Ok(());
+            }
+            match traps.traps.get(&condition) {
+                Some(action) if !action.is_empty() => action.clone(),
+                _ => return Ok(()),
+            }
+        };
+        self.traps.write().await.running.push(condition);
+        let status = *self.exit_code.read().await;
+        let line = *self.line.read().await;
+        let name = self.arg0.clone();
+        let result = Box::pin(self.run_script(ScriptSource::Text(action), &name)).await;
+        *self.line.write().await = line;
+        *self.exit_code.write().await = status;
+        self.traps.write().await.running.retain(|&running| running != condition);
+        result.map(|_| ())
+    }
+
+    /// Whether the shell is running a trap.
+    pub(crate) async fn in_trap(&self) -> bool {
+        !self.traps.read().await.running.is_empty()
+    }
+
+    /// Puts aside the traps a function does not inherit as it is called.
+    pub(crate) async fn hide_function_traps(&self) -> Vec<(Condition, String)> {
+        let mut traps = self.traps.write().await;
+        FUNCTION_CONDITIONS.iter().filter_map(|condition| traps.traps.remove(condition).map(|action| (*condition, action))).collect()
+    }
+
+    /// Brings back the traps `hide_function_traps` put aside, except where
+    /// the function set its own.
+    pub(crate) async fn restore_function_traps(&self, hidden: Vec<(Condition, String)>) {
+        let mut traps = self.traps.write().await;
+        for (condition, action) in hidden {
+            traps.traps.entry(condition).or_insert(action);
+        }
+    }
+
+    /// Runs the traps of the signals that have arrived since the last call.
+    pub(crate) async fn run_pending_traps(&mut self) -> Result<(), ShellError> {
+        for signal in signals::take_pending() {
+            if self.is_trapped(signal).await {
+                self.run_trap(Condition::Signal(signal)).await?;
+            } else if FATAL_SIGNALS.contains(&signal) {
+                // Caught only for the EXIT trap: run it, then die of the signal
+                let status = self.run_exit_trap(128 + signal as i32).await;
+                flush_stdio();
+                signals::untrap(signal, SigHandler::SigDfl);
+                let _ = nix::sys::signal::raise(signal);
+                exit_child(status);
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs the EXIT trap as the shell ends with `status`, and returns the
+    /// status to end with: that of an `exit` in the trap, or `status`.
+    pub(crate) async fn run_exit_trap(&mut self, status: i32) -> i32 {
+        // Removed first, so that `exit` in the trap does not run it again
+        let Some(action) = self.traps.write().await.traps.remove(&Condition::Exit) else {
+            return status;
+        };
+        if action.is_empty() {
+            return status;
+        }
+        *self.exit_code.write().await = status;
+        let name = self.arg0.clone();
+        match Box::pin(self.run_script(ScriptSource::Text(action), &name)).await {
+            Err(ShellError::Exit(status)) => status,
+            _ => status,
+        }
+    }
+
+    /// Leaves a freshly forked subshell only the ignored signals of its
+    /// parent's traps.
+    pub(crate) async fn reset_traps(&mut self) {
+        let mut traps = self.traps.write().await;
+        let parent = std::mem::take(&mut traps.traps);
+        for (condition, action) in &parent {
+            match condition {
+                Condition::Signal(_) if action.is_empty() => {
+                    traps.traps.insert(*condition, action.clone());
+                }
+                Condition::Signal(signal) => signals::untrap(*signal, SigHandler::SigDfl),
+                _ => {}
+            }
+        }
+        if parent.contains_key(&Condition::Exit) {
+            for signal in FATAL_SIGNALS.iter().filter(|signal| !parent.contains_key(&Condition::Signal(**signal))) {
+                signals::untrap(*signal, SigHandler::SigDfl);
+            }
+        }
+        traps.inherited = Some(parent);
+        traps.running.clear();
+    }
+}
+
+/// `trap -l`: signal numbers and names, five to a line as bash shows them.
+fn list_signals(io: &mut Streams) -> Result<(), ShellError> {
+    let signals: Vec<Signal> = Signal::iterator().collect();
+    for (i, signal) in signals.iter().enumerate() {
+        let end = if (i + 1) % 5 == 0 || i + 1 == signals.len() { "\n" } else { "\t" };
+        write!(io.stdout, "{:2}) {}{}", *signal as i32, signal.as_str(), end)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,424 @@
+// Variable attributes.
+//
+// Besides its value a variable carries the attributes given to it with
+// `declare`, `local`, `export` and `readonly`.  They are kept in
+// `Shell::attributes` apart from the value, so a name can be declared with
+// attributes before it is set.  Only exported variables make up the
+// environment of the commands the shell runs, and `FOO=1 cmd` exports `FOO`
+// to that one command.  An integer variable evaluates what is assigned to
+// it; a lowercase or uppercase one folds it.  A nameref holds the name of
+// another variable, and reading or assigning it reads or assigns that one.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::Write;
+
+use crate::arrays::Array;
+use crate::ast::Assignment;
+use crate::parser;
+use crate::streams::Streams;
+use crate::{Shell, ShellError};
+
+/// Namerefs followed before giving up on a chain of them.
+const NAMEREF_LIMIT: usize = 8;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Attributes {
+    pub exported: bool,
+    pub readonly: bool,
+    pub integer: bool,
+    pub lowercase: bool,
+    pub uppercase: bool,
+    pub nameref: bool,
+}
+
+impl Attributes {
+    /// Option letters of `declare` that stand for attributes.
+    pub const LETTERS: &'static str = "inrxlu";
+
+    /// Turns the attribute of `declare -<letter>` on or off.  Lowercase and
+    /// uppercase exclude each other.
+    pub fn set(&mut self, letter: char, on: bool) {
+        match letter {
+            'i' => self.integer = on,
+            'n' => self.nameref = on,
+            'r' => self.readonly = on,
+            'x' => self.exported = on,
+            'l' => {
+                self.lowercase = on;
+                self.uppercase &= !on;
+            }
+            'u' => {
+                self.uppercase = on;
+                self.lowercase &= !on;
+            }
+            _ => {}
+        }
+    }
+
+    /// The attribute letters, in the order `declare -p` prints them.
+    fn letters(&self) -> String {
+        [
+            (self.integer, 'i'),
+            (self.nameref, 'n'),
+            (self.readonly, 'r'),
+            (self.exported, 'x'),
+            (self.lowercase, 'l'),
+            (self.uppercase, 'u'),
+        ]
+        .iter()
+        .filter(|(on, _)| *on)
+        .map(|(_, letter)| *letter)
+        .collect()
+    }
+}
+
+/// The value of a variable.
+#[derive(Debug)]
+enum Value {
+    Scalar(String),
+    Array(Array),
+}
+
+/// A variable as it was before a local or a prefix assignment hid it.
+#[derive(Debug)]
+pub struct SavedVariable {
+    name: String,
+    value: Option<Value>,
+    attributes: Option<Attributes>,
+}
+
+impl SavedVariable {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+/// Quotes a value the way `declare -p` prints it, so it can be read back.
+fn double_quote(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+    for c in value.chars() {
+        if matches!(c, '"' | '\\' | '$' | '`') {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Key of an associative array as `declare -p` prints it.
+fn quote_key(key: &str) -> String {
+    if !key.is_empty() && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        key.to_string()
+    } else {
+        double_quote(key)
+    }
+}
+
+impl Shell {
+    /// The attributes of `name` itself, without following namerefs.
+    pub(crate) async fn attributes(&self, name: &str) -> Attributes {
+        self.attributes.read().await.get(name).copied().unwrap_or_default()
+    }
+
+    pub(crate) async fn set_attribute(&self, name: &str, letter: char, on: bool) {
+        self.attributes.write().await.entry(name.to_string()).or_default().set(letter, on);
+    }
+
+    /// Records `name` as declared, so that it has attributes (perhaps none)
+    /// even while it has no value.
+    pub(crate) async fn declare_name(&self, name: &str) {
+        self.attributes.write().await.entry(name.to_string()).or_default();
+    }
+
+    /// The variable a nameref refers to; `None` when `name` is not a
+    /// nameref or refers to nothing yet.
+    pub(crate) async fn nameref_target(&self, name: &str) -> Option<String> {
+        if !self.attributes(name).await.nameref {
+            return None;
+        }
+        self.variables.read().await.get(name).filter(|target| !target.is_empty()).cloned()
+    }
+
+    /// Follows namerefs from `name` to the variable it stands for.
+    pub(crate) async fn resolve_nameref(&self, name: &str) -> String {
+        let mut name = name.to_string();
+        for _ in 0..NAMEREF_LIMIT {
+            match self.nameref_target(&name).await {
+                Some(target) if target != name => name = target,
+                _ => break,
+            }
+        }
+        name
+    }
+
+    /// Fails for a readonly variable.
+    pub(crate) async fn check_writable(&self, name: &str) -> Result<(), ShellError> {
+        if self.attributes(name).await.readonly {
+            return Err(ShellError::ReadOnly(name.to_string()));
+        }
+        Ok(())
+    }
+
+    /// What an assignment of `value` stores in `name`, given its attributes.
+    /// With `append`, `old` is the value `+=` adds to: an integer variable
+    /// adds arithmetically, others append the text.
+    pub(crate) async fn converted_value(&self, name: &str, old: Option<String>, value: String, append: bool) -> Result<String, ShellError> {
+        let attributes = self.attributes(name).await;
+        let old = old.filter(|_| append).unwrap_or_default();
+        if attributes.integer {
+            let expression = if old.is_empty() { value } else { format!("{}+({})", old, value) };
+            return Ok(Box::pin(self.evaluate_arithmetic(&expression)).await?.to_string());
+        }
+        let value = old + &value;
+        Ok(if attributes.lowercase {
+            value.to_lowercase()
+        } else if attributes.uppercase {
+            value.to_uppercase()
+        } else {
+            value
+        })
+    }
+
+    /// Assigns a scalar value to `name`, or to the variable it refers to,
+    /// honouring its attributes.
+    pub(crate) async fn assign_scalar(&self, name: &str, value: String, append: bool) -> Result<(), ShellError> {
+        let name = self.resolve_nameref(name).await;
+        self.check_writable(&name).await?;
+        self.assign_dynamic(&name, &value).await;
+        let old = if append { self.parameter_value(&name).await } else { None };
+        let value = self.converted_value(&name, old, value, append).await?;
+        self.set_scalar(&name, value).await;
+        self.export_if_allexport(&name).await;
+        Ok(())
+    }
+
+    /// Under `set -a`, exports a variable that was just assigned.
+    pub(crate) async fn export_if_allexport(&self, name: &str) {
+        if self.options.read().await.allexport {
+            self.set_attribute(name, 'x', true).await;
+        }
+    }
+
+    /// Exported variables that are set, as the environment of a command.
+    pub(crate) async fn environment(&self) -> BTreeMap<String, String> {
+        let attributes = self.attributes.read().await;
+        let variables = self.variables.read().await;
+        attributes
+            .iter()
+            .filter(|(_, attributes)| attributes.exported)
+            .filter_map(|(name, _)| Some((name.clone(), variables.get(name)?.clone())))
+            .collect()
+    }
+
+    /// A copy of `name`'s value and attributes to put back later.
+    pub(crate) async fn save_variable(&self, name: &str) -> SavedVariable {
+        let value = match self.variables.read().await.get(name) {
+            Some(value) => Some(Value::Scalar(value.clone())),
+            None => self.arrays.read().await.get(name).cloned().map(Value::Array),
+        };
+        SavedVariable {
+            name: name.to_string(),
+            value,
+            attributes: self.attributes.read().await.get(name).copied(),
+        }
+    }
+
+    /// Unsets `name` along with its attributes.
+    pub(crate) async fn remove_variable(&self, name: &str) -> bool {
+        let variable = self.variables.write().await.remove(name).is_some();
+        let array = self.arrays.write().await.remove(name).is_some();
+        let declared = self.attributes.write().await.remove(name).is_some();
+        variable || array || declared
+    }
+
+    /// Puts a saved variable back the way it was.
+    pub(crate) async fn restore_variable(&self, saved: SavedVariable) {
+        self.remove_variable(&saved.name).await;
+        match saved.value {
+            Some(Value::Scalar(value)) => {
+                self.variables.write().await.insert(saved.name.clone(), value);
+            }
+            Some(Value::Array(array)) => {
+                self.arrays.write().await.insert(saved.name.clone(), array);
+            }
+            None => {}
+        }
+        if let Some(attributes) = saved.attributes {
+            self.attributes.write().await.insert(saved.name, attributes);
+        }
+    }
+
+    /// Makes the prefix assignments of a command, exporting them.  The
+    /// caller restores the returned variables once the command is done.
+    pub(crate) async fn apply_prefix_assignments(&mut self, assignments: &[Assignment]) -> Result<Vec<SavedVariable>, ShellError> {
+        let mut saved = Vec::with_capacity(assignments.len());
+        for assignment in assignments {
+            let name = self.resolve_nameref(&assignment.name).await;
+            saved.push(self.save_variable(&name).await);
+            if let Err(e) = self.handle_variable_assignment(assignment).await {
+                self.restore_variables(saved).await;
+                return Err(e);
+            }
+            self.set_attribute(&name, 'x', true).await;
+        }
+        Ok(saved)
+    }
+
+    pub(crate) async fn restore_variables(&self, saved: Vec<SavedVariable>) {
+        for variable in saved.into_iter().rev() {
+            self.restore_variable(variable).await;
+        }
+    }
+
+    /// `declare -p name` output for `name`; `None` when it is neither set
+    /// nor declared.
+    async fn declaration(&self, name: &str) -> Option<String> {
+        let attributes = self.attributes.read().await.get(name).copied();
+        let array = self.arrays.read().await.get(name).cloned();
+        let value = self.variables.read().await.get(name).cloned();
+        if attributes.is_none() && array.is_none() && value.is_none() {
+            return None;
+        }
+        let mut letters = match array {
+            Some(Array::Indexed(_)) => "a".to_string(),
+            Some(Array::Associative(_)) => "A".to_string(),
+            None => String::new(),
+        };
+        letters.push_str(&attributes.unwrap_or_default().letters());
+        if letters.is_empty() {
+            letters.push('-');
+        }
+        let assigned = match (array, value) {
+            (Some(Array::Indexed(elements)), _) => {
+                let elements: Vec<String> = elements.iter().map(|(index, value)| format!("[{}]={}", index, double_quote(value))).collect();
+                format!("=({})", elements.join(" "))
+            }
+            (Some(Array::Associative(elements)), _) => {
+                let elements: String = elements.iter().map(|(key, value)| format!("[{}]={} ", quote_key(key), double_quote(value))).collect();
+                format!("=({})", elements)
+            }
+            (None, Some(value)) => format!("={}", double_quote(&value)),
+            (None, None) => String::new(),
+        };
+        Some(format!("declare -{} {}{}", letters, name, assigned))
+    }
+
+    /// Prints variables as `declare -p` does.  Without `names` every
+    /// variable having all the attribute letters in `filter` is printed,
+    /// sorted by name.  Returns false if one of `names` is not a variable.
+    pub(crate) async fn print_declarations(&self, command: &str, names: &[String], filter: &str, io: &mut Streams) -> Result<bool, ShellError> {
+        if !names.is_empty() {
+            let mut found = true;
+            for name in names {
+                match self.declaration(name).await {
+                    Some(line) => writeln!(io.stdout, "{}", line)?,
+                    None => {
+                        writeln!(io.stderr, "{}: {}: not found", command, name)?;
+                        found = false;
+                    }
+                }
+            }
+            return Ok(found);
+        }
+
+        let mut all = BTreeSet::new();
+        all.extend(self.variables.read().await.keys().cloned());
+        all.extend(self.arrays.read().await.keys().cloned());
+        all.extend(self.attributes.read().await.keys().cloned());
+        for name in all {
+            let Some(line) = self.declaration(&name).await else {
+                continue;
+            };
+            let letters = line["declare -".len()..].split(' ').next().unwrap_or_default();
+            if filter.chars().all(|letter| letters.contains(letter)) {
+                writeln!(io.stdout, "{}", line)?;
+            }
+        }
+        Ok(true)
+    }
+
+    /// Sets or declares the `name[=value]` operands of `declare`, `local`,
+    /// `export` and `readonly`, turning on the attributes and array kinds
+    /// in `on` and turning off those in `off`.  Errors are reported under
+    /// `command`.
+    pub(crate) async fn declare_variables(&mut self, command: &str, (on, off): (&str, &str), operands: &[String], local: bool, io: &mut Streams) -> Result<i32, ShellError> {
+        let array = if on.contains('A') {
+            Some(true)
+        } else if on.contains('a') {
+            Some(false)
+        } else {
+            None
+        };
+        let mut exit_code = 0;
+        for operand in operands {
+            let (name, value) = match operand.split_once('=') {
+                Some((name, value)) => (name, Some(value.to_string())),
+                None => (operand.as_str(), None),
+            };
+            let (name, append) = match name.strip_suffix('+') {
+                Some(name) if value.is_some() => (name, true),
+                _ => (name, false),
+            };
+            if !parser::is_valid_name(name) {
+                writeln!(io.stderr, "{}: `{}': not a valid identifier", command, operand)?;
+                exit_code = 1;
+                continue;
+            }
+            let readonly = self.attributes(name).await.readonly;
+            if readonly && (local || off.contains('r')) {
+                writeln!(io.stderr, "{}: {}: readonly variable", command, name)?;
+                exit_code = 1;
+                continue;
+            }
+            if local {
+                self.make_local(name).await;
+            }
+            if let Some(associative) = array {
+                if let Err(msg) = self.declare_array(name, associative).await {
+                    writeln!(io.stderr, "{}: {}", command, msg)?;
+                    exit_code = 1;
+                    continue;
+                }
+            }
+            for letter in Attributes::LETTERS.chars() {
+                // Readonly comes on once the value is in place
+                if on.contains(letter) && letter != 'r' {
+                    self.set_attribute(name, letter, true).await;
+                } else if off.contains(letter) {
+                    self.set_attribute(name, letter, false).await;
+                }
+            }
+
+            match value {
+                // The value of a nameref is the name it refers to
+                Some(target) if self.attributes(name).await.nameref => {
+                    if readonly {
+                        writeln!(io.stderr, "{}: {}: readonly variable", command, name)?;
+                        exit_code = 1;
+                        continue;
+                    }
+                    if !target.is_empty() && !parser::is_valid_name(&target) {
+                        writeln!(io.stderr, "{}: `{}': invalid variable name for name reference", command, target)?;
+                        exit_code = 1;
+                        continue;
+                    }
+                    self.variables.write().await.insert(name.to_string(), target);
+                }
+                Some(value) => {
+                    if let Err(e) = self.assign_scalar(name, value, append).await {
+                        writeln!(io.stderr, "{}: {}", command, e)?;
+                        exit_code = 1;
+                        continue;
+                    }
+                }
+                None => self.declare_name(name).await,
+            }
+            if on.contains('r') {
+                self.set_attribute(name, 'r', true).await;
+            }
+        }
+        Ok(exit_code)
+    }
+}
@@ -0,0 +1,14 @@
+use std::process::Command;
+
+fn run(script: &str) -> (String, Option<i32>) {
+    let output = Command::new(env!("CARGO_BIN_EXE_nexusshell")).args(["-c", script]).output().expect("run nexusshell");
+    (String::from_utf8_lossy(&output.stdout).into_owned(), output.status.code())
+}
+
+#[test]
+fn o_inside_a_flag_cluster_takes_the_next_argument() {
+    // pipefail makes the pipeline fail, and -e then ends the shell with its status
+    assert_eq!(run("set -euo pipefail; false | true; echo $?"), (String::new(), Some(1)));
+    assert_eq!(run("set -uo pipefail; false | true; echo $?"), ("1\n".to_string(), Some(0)));
+    assert_eq!(run("set -uo pipefail; set +uo pipefail; false | true; echo $?"), ("0\n".to_string(), Some(0)));
+}